@@ -0,0 +1,28 @@
+use cranelift_equation_parser::{EntityArena, Function, Visitor, walk};
+
+/// Counts the function calls in an equation, like the 2 in `sin(x)+cos(x)`.
+struct FunctionCounter {
+    calls: usize,
+}
+
+impl<'a, T> Visitor<'a, T> for FunctionCounter {
+    fn visit_function(&mut self, _function: &Function<'a, T>) {
+        self.calls += 1;
+    }
+}
+
+pub fn main() {
+    let equation = std::env::args()
+        .nth(1)
+        .unwrap_or_else(|| "sin(x)+cos(x)".to_string());
+
+    let arena = EntityArena::new();
+    match cranelift_equation_parser::parse::<f64>(&equation, &arena) {
+        Ok(entity) => {
+            let mut counter = FunctionCounter { calls: 0 };
+            walk(&entity, &mut counter);
+            println!("{} => {} function calls", equation, counter.calls);
+        }
+        Err(error) => println!("{} => {}", equation, error),
+    }
+}
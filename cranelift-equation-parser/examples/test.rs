@@ -1,12 +1,36 @@
 use std::io::BufRead;
 
+use cranelift_equation_parser::EntityArena;
+
+/// Parses one equation per line of stdin, and exits on EOF (Ctrl+D).
 pub fn main() {
     let mut buffer = String::with_capacity(2048);
     // Lock our standard input to eliminate synchronization overhead (unlocks when dropped)
     let mut stdin = std::io::stdin().lock();
 
-    // Read our first line.
-    stdin.read_line(&mut buffer).unwrap();
+    loop {
+        buffer.clear();
+        // Read the next line, stopping on EOF, where nothing is read.
+        if stdin.read_line(&mut buffer).unwrap() == 0 {
+            break;
+        }
+        // the last line might not end in a newline
+        let equation = buffer.trim_end();
+
+        let tokens = match cranelift_equation_parser::parse_tokens::<f64>(equation) {
+            Ok(tokens) => tokens,
+            Err(error) => {
+                println!("{} => {}", equation, error);
+                continue;
+            }
+        };
+        print!("{} => ", equation);
+        cranelift_equation_parser::print(&tokens[..]);
 
-    cranelift_equation_parser::parse::<f64>(&buffer[..buffer.len() - 1]);
+        let arena = EntityArena::new();
+        match cranelift_equation_parser::parse::<f64>(equation, &arena) {
+            Ok(entity) => println!("{:#?}", entity),
+            Err(error) => println!("{}", error),
+        }
+    }
 }
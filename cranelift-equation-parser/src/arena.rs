@@ -0,0 +1,65 @@
+use crate::ast::Entity;
+
+/// Owns every node of an [`Entity`] tree.
+/// The parser allocates nodes here and hands out references that live as long as the arena does,
+/// so a parsed tree can outlive the parser call that built it.
+pub struct EntityArena<'a, T> {
+    arena: typed_arena::Arena<Entity<'a, T>>,
+}
+
+impl<'a, T> EntityArena<'a, T> {
+    pub fn new() -> Self {
+        Self {
+            arena: typed_arena::Arena::new(),
+        }
+    }
+
+    /// Moves `entity` into the arena, returning a reference to it.
+    pub fn alloc(&self, entity: Entity<'a, T>) -> &Entity<'a, T> {
+        self.arena.alloc(entity)
+    }
+
+    /// The number of nodes allocated so far.
+    pub fn len(&self) -> usize {
+        self.arena.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
+
+impl<T> Default for EntityArena<'_, T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ast::{Function, Operation};
+    use crate::parse_into;
+
+    #[test]
+    fn owns_parsed_tree() {
+        let arena = EntityArena::new();
+        let root = parse_into::<f64>("sin(x)+1", &arena).unwrap();
+        assert!(!arena.is_empty());
+
+        let Entity::Operation(Operation::Add(lhs, rhs)) = root else {
+            panic!("expected an addition, got {root:?}");
+        };
+        assert_eq!(**lhs, Entity::Function(Function::Sin(&Entity::Value("x"))));
+        assert_eq!(**rhs, Entity::ValueLit(1.0));
+    }
+
+    #[test]
+    fn hands_out_references_to_allocated_nodes() {
+        let arena = EntityArena::new();
+        let x = arena.alloc(Entity::<f64>::Value("x"));
+        let neg = arena.alloc(Entity::Operation(Operation::Neg(x)));
+        assert_eq!(arena.len(), 2);
+        assert_eq!(neg.children(), [x]);
+    }
+}
@@ -1,30 +1,70 @@
 use crate::EquationParseError;
 
-use std::str::FromStr;
+use alloc::vec;
+use alloc::vec::Vec;
+use core::fmt::Display;
+use core::str::FromStr;
 
-#[derive(Debug, Clone, Copy)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 /// An easily parsable and cheaply clonable 1st stage AST.
 /// This is a direct mapping of the equation text to something we can work with.
+/// Every token carries the byte range `start..end` it was read from.
 pub enum RawSyntax {
-    /// Indices to a literal value, like `5`
+    /// A literal value, like `5`, `1_000`, `2.5e-3`, `0xFF` or `0b1010`
     ValueLit { start: usize, end: usize },
-    /// Indices to a value identifier, like `x`
+    /// A value identifier, like `x`
     ValueIdent { start: usize, end: usize },
     /// An operator, like `+`, `-`, `*`, `/`
-    Operator(Operator),
+    Operator {
+        kind: Operator,
+        start: usize,
+        end: usize,
+    },
     /// A parenthesis, like `(` or `)`
-    Parenthesis(ParenthesisType),
+    Parenthesis {
+        kind: ParenthesisType,
+        start: usize,
+        end: usize,
+    },
     /// A known function name, like `sin` or `cos`
     Function { start: usize, end: usize },
     /// A comma. Only used for functions (like log) that can have more than one argument
-    Comma,
+    Comma { start: usize, end: usize },
     /// Absolute value symbol, |x|
-    Abs,
+    Abs { start: usize, end: usize },
+    /// A superscript exponent, like the `²` in `x²` or the `¹²` in `x¹²`
+    Superscript { start: usize, end: usize },
+    /// A relation, like `=` or `<=`. Only used by [`crate::parse_equation`]
+    Relation {
+        kind: RelationOp,
+        start: usize,
+        end: usize,
+    },
 }
 
-#[derive(Debug, Clone, Copy)]
+impl RawSyntax {
+    /// The byte range of the equation this token was read from.
+    pub fn span(&self) -> core::ops::Range<usize> {
+        match *self {
+            Self::ValueLit { start, end }
+            | Self::ValueIdent { start, end }
+            | Self::Operator { start, end, .. }
+            | Self::Parenthesis { start, end, .. }
+            | Self::Function { start, end }
+            | Self::Comma { start, end }
+            | Self::Abs { start, end }
+            | Self::Superscript { start, end }
+            | Self::Relation { start, end, .. } => start..end,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 /// Easily parsable 2nd stage AST.
 /// At this stage, the parser has expanded out implicit multiplication, parsed literals and functions,
+/// and rewritten absolute value bars like `|x|` into `abs(x)`,
 /// but has no idea about the relationship between symbols.
 pub enum Syntax<'a, T> {
     /// A literal value, like `5`
@@ -40,11 +80,10 @@ pub enum Syntax<'a, T> {
     Function(FunctionType),
     /// A comma. Only used for functions (like log) that can have more than one argument
     Comma,
-    /// Absolute value symbol, |x|
-    Abs,
 }
 
-#[derive(Debug, Clone, Copy)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum Operator {
     /// `+`
     Add,
@@ -54,8 +93,51 @@ pub enum Operator {
     Mul,
     /// `/`
     Div,
-    /// `^`
+    /// `//`, division rounded down
+    FloorDiv,
+    /// `^`, or `**`
     Pow,
+    /// `%`, the floating point remainder
+    Rem,
+    /// Unary `-`, like in `-x` or `2*-3`
+    Neg,
+    /// Postfix `!`, like in `5!`
+    Factorial,
+    /// Postfix `%` in [`Parser::percent_mode`](crate::Parser::percent_mode), like in `50%`.
+    /// It divides by 100, and becomes a division in the tree.
+    Percent,
+    /// Prefix `√`, like in `√2`. It binds like unary `-`, so `√x^2` is `√(x^2)`,
+    /// and becomes a [`Function::Sqrt`] in the tree.
+    Sqrt,
+}
+
+impl Operator {
+    /// How tightly this operator binds. Higher binds tighter, so compare these to order
+    /// operators, like `Operator::Pow.precedence() > Operator::Mul.precedence()`.
+    /// Different operators can bind just as tightly, like `Add` and `Sub`.
+    pub fn precedence(&self) -> u8 {
+        match self {
+            Self::Add | Self::Sub => 1,
+            Self::Mul | Self::Div | Self::FloorDiv | Self::Rem | Self::Neg | Self::Sqrt => 2,
+            Self::Pow => 3,
+            Self::Factorial | Self::Percent => 4,
+        }
+    }
+
+    /// Whether this operator goes before its only operand, like `-x`
+    pub fn is_prefix(&self) -> bool {
+        matches!(self, Self::Neg | Self::Sqrt)
+    }
+
+    /// Whether this operator goes after its only operand, like `5!`
+    pub fn is_postfix(&self) -> bool {
+        matches!(self, Self::Factorial | Self::Percent)
+    }
+
+    /// Whether chains of this operator group from the right, like `2^3^2 = 2^(3^2)`
+    pub fn is_right_associative(&self) -> bool {
+        matches!(self, Self::Pow)
+    }
 }
 
 impl TryFrom<char> for Operator {
@@ -64,16 +146,75 @@ impl TryFrom<char> for Operator {
     fn try_from(value: char) -> Result<Self, Self::Error> {
         match value {
             '+' => Ok(Self::Add),
-            '-' => Ok(Self::Sub),
-            '*' => Ok(Self::Mul),
-            '/' => Ok(Self::Div),
+            // the Unicode minus sign, `−`, is what copied equations often use
+            '-' | '−' => Ok(Self::Sub),
+            '*' | '×' => Ok(Self::Mul),
+            '/' | '÷' => Ok(Self::Div),
+            '√' => Ok(Self::Sqrt),
             '^' => Ok(Self::Pow),
+            '%' => Ok(Self::Rem),
+            '!' => Ok(Self::Factorial),
             _ => Err(EquationParseError::NoMatch),
         }
     }
 }
 
-#[derive(Debug, Clone, Copy)]
+impl Display for Operator {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        f.write_str(match self {
+            Self::Add => "+",
+            Self::Sub => "-",
+            Self::Mul => "*",
+            Self::Div => "/",
+            Self::FloorDiv => "//",
+            Self::Pow => "^",
+            Self::Rem => "%",
+            Self::Neg => "-",
+            Self::Factorial => "!",
+            Self::Percent => "%",
+            Self::Sqrt => "√",
+        })
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+/// How the two sides of an equation relate, like the `=` in `x^2 = 4`.
+pub enum RelationOp {
+    /// `=`
+    Eq,
+    /// `<`
+    Lt,
+    /// `>`
+    Gt,
+    /// `<=`
+    Le,
+    /// `>=`
+    Ge,
+}
+
+impl Display for RelationOp {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        f.write_str(match self {
+            Self::Eq => "=",
+            Self::Lt => "<",
+            Self::Gt => ">",
+            Self::Le => "<=",
+            Self::Ge => ">=",
+        })
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+/// An equation or inequality, like `x^2 = 4`: two [`Entity`] trees and how they relate.
+pub struct Relation<'a, T> {
+    pub lhs: Entity<'a, T>,
+    pub op: RelationOp,
+    pub rhs: Entity<'a, T>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum ParenthesisType {
     /// `(`
     Open,
@@ -89,6 +230,28 @@ pub enum ParenthesisType {
     CloseCurly,
 }
 
+impl ParenthesisType {
+    /// Whether this is an opening parenthesis, like `(`
+    pub fn is_open(&self) -> bool {
+        matches!(self, Self::Open | Self::OpenSquare | Self::OpenCurly)
+    }
+
+    /// Whether this is `(` or `)`, rather than a square or curly bracket
+    pub fn is_round(&self) -> bool {
+        matches!(self, Self::Open | Self::Close)
+    }
+
+    /// Whether `close` is the closing counterpart of this parenthesis, like `(` and `)`
+    pub fn is_closed_by(&self, close: ParenthesisType) -> bool {
+        matches!(
+            (self, close),
+            (Self::Open, Self::Close)
+                | (Self::OpenSquare, Self::CloseSquare)
+                | (Self::OpenCurly, Self::CloseCurly)
+        )
+    }
+}
+
 impl TryFrom<char> for ParenthesisType {
     type Error = crate::EquationParseError;
 
@@ -105,7 +268,21 @@ impl TryFrom<char> for ParenthesisType {
     }
 }
 
-#[derive(Debug, Clone, Copy)]
+impl Display for ParenthesisType {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        f.write_str(match self {
+            Self::Open => "(",
+            Self::Close => ")",
+            Self::OpenSquare => "[",
+            Self::CloseSquare => "]",
+            Self::OpenCurly => "{",
+            Self::CloseCurly => "}",
+        })
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum FunctionType {
     Sin,
     Cos,
@@ -113,28 +290,158 @@ pub enum FunctionType {
     Cot,
     Sec,
     Csc,
+    Asin,
+    Acos,
+    Atan,
+    Atan2,
     Sinh,
     Cosh,
     Tanh,
     Coth,
     Sech,
     Csch,
+    Asinh,
+    Acosh,
+    Atanh,
 
     Log,
     Ln,
+    Log2,
 
     Sqrt,
     Root,
+    Hypot,
 
     Exp,
+    Exp2,
+    Exp10,
     Mod,
+    Gcd,
+    Lcm,
+    Gamma,
+    Lgamma,
 
     Ceil,
     Floor,
     Round,
+    Trunc,
+    Fract,
     Abs,
+    Signum,
+    Min,
+    Max,
+    Clamp,
+
+    /// The operators written as functions, like `pow(x, 2)` for `x^2`. They're turned into the
+    /// [`Operation`] while parsing, so no [`Function`] is ever one of these.
+    Pow,
+    Add,
+    Sub,
+    Mul,
+    Div,
+}
+
+impl FunctionType {
+    /// The number of comma-separated arguments this function takes.
+    /// `log` is the only function with an optional argument: `log(x)` is the base-10 logarithm,
+    /// while `log(base, x)` takes the full 2.
+    pub fn arity(&self) -> usize {
+        match self {
+            Self::Log
+            | Self::Root
+            | Self::Mod
+            | Self::Atan2
+            | Self::Min
+            | Self::Max
+            | Self::Gcd
+            | Self::Lcm
+            | Self::Hypot
+            | Self::Pow
+            | Self::Add
+            | Self::Sub
+            | Self::Mul
+            | Self::Div => 2,
+            Self::Clamp => 3,
+            _ => 1,
+        }
+    }
+
+    /// The binary operator this function is another way to write, like [`Operator::Pow`]
+    /// for `pow`.
+    pub fn operator(&self) -> Option<Operator> {
+        match self {
+            Self::Pow => Some(Operator::Pow),
+            Self::Add => Some(Operator::Add),
+            Self::Sub => Some(Operator::Sub),
+            Self::Mul => Some(Operator::Mul),
+            Self::Div => Some(Operator::Div),
+            _ => None,
+        }
+    }
+
+    /// Whether this is a transcendental function: a trigonometric or hyperbolic one, their
+    /// inverses, an exponential, a logarithm, or `gamma` and `lgamma`. These need a call into a
+    /// math library, while the others are arithmetic, rounding, comparisons or `sqrt`, which
+    /// CPUs have instructions for, or are built out of those.
+    ///
+    /// `pow` and `root` are transcendental too, as a general `x^y` is `exp(y*ln(x))`.
+    /// `hypot` is only a `sqrt`, so it isn't.
+    pub fn is_transcendental(&self) -> bool {
+        match self {
+            Self::Sin
+            | Self::Cos
+            | Self::Tan
+            | Self::Cot
+            | Self::Sec
+            | Self::Csc
+            | Self::Asin
+            | Self::Acos
+            | Self::Atan
+            | Self::Atan2
+            | Self::Sinh
+            | Self::Cosh
+            | Self::Tanh
+            | Self::Coth
+            | Self::Sech
+            | Self::Csch
+            | Self::Asinh
+            | Self::Acosh
+            | Self::Atanh
+            | Self::Log
+            | Self::Ln
+            | Self::Log2
+            | Self::Exp
+            | Self::Exp2
+            | Self::Exp10
+            | Self::Gamma
+            | Self::Lgamma
+            | Self::Root
+            | Self::Pow => true,
+            Self::Sqrt
+            | Self::Hypot
+            | Self::Mod
+            | Self::Gcd
+            | Self::Lcm
+            | Self::Ceil
+            | Self::Floor
+            | Self::Round
+            | Self::Trunc
+            | Self::Fract
+            | Self::Abs
+            | Self::Signum
+            | Self::Min
+            | Self::Max
+            | Self::Clamp
+            | Self::Add
+            | Self::Sub
+            | Self::Mul
+            | Self::Div => false,
+        }
+    }
 }
 
+/// Parses the name of a function, or one of its common aliases,
+/// like `arcsin` for `asin`, `tg` for `tan` or `lg` for `log2`.
 impl FromStr for FunctionType {
     type Err = EquationParseError;
 
@@ -142,56 +449,151 @@ impl FromStr for FunctionType {
         match s {
             "sin" => Ok(Self::Sin),
             "cos" => Ok(Self::Cos),
-            "tan" => Ok(Self::Tan),
-            "cot" => Ok(Self::Cot),
+            "tan" | "tg" => Ok(Self::Tan),
+            "cot" | "ctg" => Ok(Self::Cot),
             "sec" => Ok(Self::Sec),
             "csc" => Ok(Self::Csc),
+            "asin" | "arcsin" => Ok(Self::Asin),
+            "acos" | "arccos" => Ok(Self::Acos),
+            "atan" | "arctan" => Ok(Self::Atan),
+            "atan2" => Ok(Self::Atan2),
             "sinh" => Ok(Self::Sinh),
             "cosh" => Ok(Self::Cosh),
             "tanh" => Ok(Self::Tanh),
             "coth" => Ok(Self::Coth),
             "sech" => Ok(Self::Sech),
             "csch" => Ok(Self::Csch),
+            "asinh" | "arsinh" => Ok(Self::Asinh),
+            "acosh" | "arcosh" => Ok(Self::Acosh),
+            "atanh" | "artanh" => Ok(Self::Atanh),
 
             "log" => Ok(Self::Log),
             "ln" => Ok(Self::Ln),
+            "log2" | "lg" => Ok(Self::Log2),
 
             "sqrt" => Ok(Self::Sqrt),
             "root" => Ok(Self::Root),
+            "hypot" => Ok(Self::Hypot),
 
             "exp" => Ok(Self::Exp),
+            "exp2" => Ok(Self::Exp2),
+            "exp10" => Ok(Self::Exp10),
             "mod" => Ok(Self::Mod),
+            "gcd" => Ok(Self::Gcd),
+            "lcm" => Ok(Self::Lcm),
+            "gamma" => Ok(Self::Gamma),
+            "lgamma" => Ok(Self::Lgamma),
 
             "ceil" => Ok(Self::Ceil),
             "floor" => Ok(Self::Floor),
             "round" => Ok(Self::Round),
+            "trunc" => Ok(Self::Trunc),
+            "fract" => Ok(Self::Fract),
             "abs" => Ok(Self::Abs),
+            "sign" | "signum" => Ok(Self::Signum),
+            "min" => Ok(Self::Min),
+            "max" => Ok(Self::Max),
+            "clamp" => Ok(Self::Clamp),
+
+            "pow" => Ok(Self::Pow),
+            "add" => Ok(Self::Add),
+            "sub" => Ok(Self::Sub),
+            "mul" => Ok(Self::Mul),
+            "div" => Ok(Self::Div),
             _ => Err(EquationParseError::UnknownFunction),
         }
     }
 }
 
-#[derive(Debug, Clone, Copy)]
+impl Display for FunctionType {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        f.write_str(match self {
+            Self::Sin => "sin",
+            Self::Cos => "cos",
+            Self::Tan => "tan",
+            Self::Cot => "cot",
+            Self::Sec => "sec",
+            Self::Csc => "csc",
+            Self::Asin => "asin",
+            Self::Acos => "acos",
+            Self::Atan => "atan",
+            Self::Atan2 => "atan2",
+            Self::Sinh => "sinh",
+            Self::Cosh => "cosh",
+            Self::Tanh => "tanh",
+            Self::Coth => "coth",
+            Self::Sech => "sech",
+            Self::Csch => "csch",
+            Self::Asinh => "asinh",
+            Self::Acosh => "acosh",
+            Self::Atanh => "atanh",
+
+            Self::Log => "log",
+            Self::Ln => "ln",
+            Self::Log2 => "log2",
+
+            Self::Sqrt => "sqrt",
+            Self::Root => "root",
+            Self::Hypot => "hypot",
+
+            Self::Exp => "exp",
+            Self::Exp2 => "exp2",
+            Self::Exp10 => "exp10",
+            Self::Mod => "mod",
+            Self::Gcd => "gcd",
+            Self::Lcm => "lcm",
+            Self::Gamma => "gamma",
+            Self::Lgamma => "lgamma",
+
+            Self::Ceil => "ceil",
+            Self::Floor => "floor",
+            Self::Round => "round",
+            Self::Trunc => "trunc",
+            Self::Fract => "fract",
+            Self::Abs => "abs",
+            Self::Signum => "sign",
+            Self::Min => "min",
+            Self::Max => "max",
+            Self::Clamp => "clamp",
+
+            Self::Pow => "pow",
+            Self::Add => "add",
+            Self::Sub => "sub",
+            Self::Mul => "mul",
+            Self::Div => "div",
+        })
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
 /// A cheaply clonable high level 3rd stage AST.
 /// At this stage, the parser has fully thought out the relationship between symbols.
 /// No parentheses are present in this 3rd stage, as the order of operations has been fully parsed.
+///
+/// Floats aren't [`Hash`], so neither is this. To use a tree as a map key, wrap it in a
+/// [`HashableEntity`].
 pub enum Entity<'a, T> {
     ValueLit(T),
     Value(&'a str),
     Operation(Operation<'a, T>),
-    Function(Function<'a, T>)
+    Function(Function<'a, T>),
 }
 
-#[derive(Debug, Clone, Copy)]
+#[derive(Debug, Clone, Copy, PartialEq)]
 pub enum Operation<'a, T> {
     Add(&'a Entity<'a, T>, &'a Entity<'a, T>),
     Sub(&'a Entity<'a, T>, &'a Entity<'a, T>),
     Mul(&'a Entity<'a, T>, &'a Entity<'a, T>),
     Div(&'a Entity<'a, T>, &'a Entity<'a, T>),
+    /// `a//b`, which is `floor(a/b)`
+    FloorDiv(&'a Entity<'a, T>, &'a Entity<'a, T>),
     Pow(&'a Entity<'a, T>, &'a Entity<'a, T>),
+    Rem(&'a Entity<'a, T>, &'a Entity<'a, T>),
+    Neg(&'a Entity<'a, T>),
+    Factorial(&'a Entity<'a, T>),
 }
 
-#[derive(Debug, Clone, Copy)]
+#[derive(Debug, Clone, Copy, PartialEq)]
 pub enum Function<'a, T> {
     Sin(&'a Entity<'a, T>),
     Cos(&'a Entity<'a, T>),
@@ -199,25 +601,621 @@ pub enum Function<'a, T> {
     Cot(&'a Entity<'a, T>),
     Sec(&'a Entity<'a, T>),
     Csc(&'a Entity<'a, T>),
+    Asin(&'a Entity<'a, T>),
+    Acos(&'a Entity<'a, T>),
+    Atan(&'a Entity<'a, T>),
+    /// `atan2(y, x)`
+    Atan2(&'a Entity<'a, T>, &'a Entity<'a, T>),
     Sinh(&'a Entity<'a, T>),
     Cosh(&'a Entity<'a, T>),
     Tanh(&'a Entity<'a, T>),
     Coth(&'a Entity<'a, T>),
     Sech(&'a Entity<'a, T>),
     Csch(&'a Entity<'a, T>),
+    Asinh(&'a Entity<'a, T>),
+    Acosh(&'a Entity<'a, T>),
+    Atanh(&'a Entity<'a, T>),
 
+    /// `log(base, x)`
     Log(&'a Entity<'a, T>, &'a Entity<'a, T>),
     Log10(&'a Entity<'a, T>),
     Ln(&'a Entity<'a, T>),
+    Log2(&'a Entity<'a, T>),
 
     Sqrt(&'a Entity<'a, T>),
+    /// `root(n, x)`, the `n`th root of `x`
     Root(&'a Entity<'a, T>, &'a Entity<'a, T>),
+    Hypot(&'a Entity<'a, T>, &'a Entity<'a, T>),
 
     Exp(&'a Entity<'a, T>),
+    Exp2(&'a Entity<'a, T>),
+    Exp10(&'a Entity<'a, T>),
     Mod(&'a Entity<'a, T>, &'a Entity<'a, T>),
+    /// `gcd(a, b)`, the greatest common divisor of `a` and `b` rounded to the nearest integers,
+    /// like with `round`. It's never negative, and `gcd(0, 0)` is 0.
+    Gcd(&'a Entity<'a, T>, &'a Entity<'a, T>),
+    /// `lcm(a, b)`, the least common multiple of `a` and `b` rounded to the nearest integers,
+    /// like with `round`. It's never negative, and 0 if either is.
+    Lcm(&'a Entity<'a, T>, &'a Entity<'a, T>),
+    /// `gamma(x)`, the gamma function, so `gamma(n)` is `(n-1)!` for positive integers.
+    Gamma(&'a Entity<'a, T>),
+    /// `lgamma(x)`, the natural logarithm of the absolute value of `gamma(x)`,
+    /// which stays finite where `gamma(x)` overflows.
+    Lgamma(&'a Entity<'a, T>),
 
     Ceil(&'a Entity<'a, T>),
     Floor(&'a Entity<'a, T>),
     Round(&'a Entity<'a, T>),
+    /// `trunc(x)`, `x` rounded towards zero
+    Trunc(&'a Entity<'a, T>),
+    /// `fract(x)`, `x - trunc(x)`. It has the sign of `x`, so `fract(-3.7)` is `-0.7`.
+    Fract(&'a Entity<'a, T>),
     Abs(&'a Entity<'a, T>),
-}
\ No newline at end of file
+    /// `sign(x)`: -1, 0 or 1. Unlike [`num_traits::Float::signum`], the sign of zero is zero.
+    Signum(&'a Entity<'a, T>),
+    Min(&'a Entity<'a, T>, &'a Entity<'a, T>),
+    Max(&'a Entity<'a, T>, &'a Entity<'a, T>),
+    /// `clamp(x, lo, hi)`
+    Clamp(&'a Entity<'a, T>, &'a Entity<'a, T>, &'a Entity<'a, T>),
+}
+
+impl<'a, T> Entity<'a, T> {
+    /// The direct children of this node, in order.
+    pub fn children(&self) -> Vec<&'a Entity<'a, T>> {
+        match self {
+            Self::ValueLit(_) | Self::Value(_) => Vec::new(),
+            Self::Operation(op) => op.operands(),
+            Self::Function(func) => func.arguments(),
+        }
+    }
+}
+
+impl<'a, T> Operation<'a, T> {
+    /// The operator this operation was parsed from.
+    pub fn operator(&self) -> Operator {
+        match self {
+            Self::Add(..) => Operator::Add,
+            Self::Sub(..) => Operator::Sub,
+            Self::Mul(..) => Operator::Mul,
+            Self::Div(..) => Operator::Div,
+            Self::FloorDiv(..) => Operator::FloorDiv,
+            Self::Pow(..) => Operator::Pow,
+            Self::Rem(..) => Operator::Rem,
+            Self::Neg(_) => Operator::Neg,
+            Self::Factorial(_) => Operator::Factorial,
+        }
+    }
+
+    /// The operands of this operation, in order.
+    pub fn operands(&self) -> Vec<&'a Entity<'a, T>> {
+        match *self {
+            Self::Add(lhs, rhs)
+            | Self::Sub(lhs, rhs)
+            | Self::Mul(lhs, rhs)
+            | Self::Div(lhs, rhs)
+            | Self::FloorDiv(lhs, rhs)
+            | Self::Pow(lhs, rhs)
+            | Self::Rem(lhs, rhs) => vec![lhs, rhs],
+            Self::Neg(x) | Self::Factorial(x) => vec![x],
+        }
+    }
+
+    /// Rebuilds this operation with every operand replaced by `f(operand)`.
+    pub fn map<'b, U>(
+        &self,
+        mut f: impl FnMut(&'a Entity<'a, T>) -> &'b Entity<'b, U>,
+    ) -> Operation<'b, U> {
+        match *self {
+            Self::Add(lhs, rhs) => Operation::Add(f(lhs), f(rhs)),
+            Self::Sub(lhs, rhs) => Operation::Sub(f(lhs), f(rhs)),
+            Self::Mul(lhs, rhs) => Operation::Mul(f(lhs), f(rhs)),
+            Self::Div(lhs, rhs) => Operation::Div(f(lhs), f(rhs)),
+            Self::FloorDiv(lhs, rhs) => Operation::FloorDiv(f(lhs), f(rhs)),
+            Self::Pow(lhs, rhs) => Operation::Pow(f(lhs), f(rhs)),
+            Self::Rem(lhs, rhs) => Operation::Rem(f(lhs), f(rhs)),
+            Self::Neg(x) => Operation::Neg(f(x)),
+            Self::Factorial(x) => Operation::Factorial(f(x)),
+        }
+    }
+}
+
+impl<'a, T> Function<'a, T> {
+    /// `function` applied to `args`, or `None` if it doesn't take that many arguments.
+    /// `log` with only one argument is [`Function::Log10`].
+    pub fn from_arguments(function: FunctionType, args: &[&'a Entity<'a, T>]) -> Option<Self> {
+        Some(match (function, args) {
+            (FunctionType::Sin, [x]) => Self::Sin(x),
+            (FunctionType::Cos, [x]) => Self::Cos(x),
+            (FunctionType::Tan, [x]) => Self::Tan(x),
+            (FunctionType::Cot, [x]) => Self::Cot(x),
+            (FunctionType::Sec, [x]) => Self::Sec(x),
+            (FunctionType::Csc, [x]) => Self::Csc(x),
+            (FunctionType::Asin, [x]) => Self::Asin(x),
+            (FunctionType::Acos, [x]) => Self::Acos(x),
+            (FunctionType::Atan, [x]) => Self::Atan(x),
+            (FunctionType::Atan2, [y, x]) => Self::Atan2(y, x),
+            (FunctionType::Sinh, [x]) => Self::Sinh(x),
+            (FunctionType::Cosh, [x]) => Self::Cosh(x),
+            (FunctionType::Tanh, [x]) => Self::Tanh(x),
+            (FunctionType::Coth, [x]) => Self::Coth(x),
+            (FunctionType::Sech, [x]) => Self::Sech(x),
+            (FunctionType::Csch, [x]) => Self::Csch(x),
+            (FunctionType::Asinh, [x]) => Self::Asinh(x),
+            (FunctionType::Acosh, [x]) => Self::Acosh(x),
+            (FunctionType::Atanh, [x]) => Self::Atanh(x),
+
+            (FunctionType::Log, [x]) => Self::Log10(x),
+            (FunctionType::Log, [base, x]) => Self::Log(base, x),
+            (FunctionType::Ln, [x]) => Self::Ln(x),
+            (FunctionType::Log2, [x]) => Self::Log2(x),
+
+            (FunctionType::Sqrt, [x]) => Self::Sqrt(x),
+            (FunctionType::Root, [n, x]) => Self::Root(n, x),
+            (FunctionType::Hypot, [x, y]) => Self::Hypot(x, y),
+
+            (FunctionType::Exp, [x]) => Self::Exp(x),
+            (FunctionType::Exp2, [x]) => Self::Exp2(x),
+            (FunctionType::Exp10, [x]) => Self::Exp10(x),
+            (FunctionType::Mod, [x, y]) => Self::Mod(x, y),
+            (FunctionType::Gcd, [x, y]) => Self::Gcd(x, y),
+            (FunctionType::Lcm, [x, y]) => Self::Lcm(x, y),
+            (FunctionType::Gamma, [x]) => Self::Gamma(x),
+            (FunctionType::Lgamma, [x]) => Self::Lgamma(x),
+
+            (FunctionType::Ceil, [x]) => Self::Ceil(x),
+            (FunctionType::Floor, [x]) => Self::Floor(x),
+            (FunctionType::Round, [x]) => Self::Round(x),
+            (FunctionType::Trunc, [x]) => Self::Trunc(x),
+            (FunctionType::Fract, [x]) => Self::Fract(x),
+            (FunctionType::Abs, [x]) => Self::Abs(x),
+            (FunctionType::Signum, [x]) => Self::Signum(x),
+            (FunctionType::Min, [x, y]) => Self::Min(x, y),
+            (FunctionType::Max, [x, y]) => Self::Max(x, y),
+            (FunctionType::Clamp, [x, lo, hi]) => Self::Clamp(x, lo, hi),
+            _ => return None,
+        })
+    }
+
+    /// The function this was parsed from. [`Function::Log10`] is `log` with its base left out.
+    pub fn function_type(&self) -> FunctionType {
+        match self {
+            Self::Sin(..) => FunctionType::Sin,
+            Self::Cos(..) => FunctionType::Cos,
+            Self::Tan(..) => FunctionType::Tan,
+            Self::Cot(..) => FunctionType::Cot,
+            Self::Sec(..) => FunctionType::Sec,
+            Self::Csc(..) => FunctionType::Csc,
+            Self::Asin(..) => FunctionType::Asin,
+            Self::Acos(..) => FunctionType::Acos,
+            Self::Atan(..) => FunctionType::Atan,
+            Self::Atan2(..) => FunctionType::Atan2,
+            Self::Sinh(..) => FunctionType::Sinh,
+            Self::Cosh(..) => FunctionType::Cosh,
+            Self::Tanh(..) => FunctionType::Tanh,
+            Self::Coth(..) => FunctionType::Coth,
+            Self::Sech(..) => FunctionType::Sech,
+            Self::Csch(..) => FunctionType::Csch,
+            Self::Asinh(..) => FunctionType::Asinh,
+            Self::Acosh(..) => FunctionType::Acosh,
+            Self::Atanh(..) => FunctionType::Atanh,
+            Self::Log(..) => FunctionType::Log,
+            Self::Log10(..) => FunctionType::Log,
+            Self::Ln(..) => FunctionType::Ln,
+            Self::Log2(..) => FunctionType::Log2,
+            Self::Sqrt(..) => FunctionType::Sqrt,
+            Self::Root(..) => FunctionType::Root,
+            Self::Hypot(..) => FunctionType::Hypot,
+            Self::Exp(..) => FunctionType::Exp,
+            Self::Exp2(..) => FunctionType::Exp2,
+            Self::Exp10(..) => FunctionType::Exp10,
+            Self::Mod(..) => FunctionType::Mod,
+            Self::Gcd(..) => FunctionType::Gcd,
+            Self::Lcm(..) => FunctionType::Lcm,
+            Self::Gamma(..) => FunctionType::Gamma,
+            Self::Lgamma(..) => FunctionType::Lgamma,
+            Self::Ceil(..) => FunctionType::Ceil,
+            Self::Floor(..) => FunctionType::Floor,
+            Self::Round(..) => FunctionType::Round,
+            Self::Trunc(..) => FunctionType::Trunc,
+            Self::Fract(..) => FunctionType::Fract,
+            Self::Abs(..) => FunctionType::Abs,
+            Self::Signum(..) => FunctionType::Signum,
+            Self::Min(..) => FunctionType::Min,
+            Self::Max(..) => FunctionType::Max,
+            Self::Clamp(..) => FunctionType::Clamp,
+        }
+    }
+
+    /// The arguments of this function, in the order they were written.
+    pub fn arguments(&self) -> Vec<&'a Entity<'a, T>> {
+        match *self {
+            Self::Sin(x) => vec![x],
+            Self::Cos(x) => vec![x],
+            Self::Tan(x) => vec![x],
+            Self::Cot(x) => vec![x],
+            Self::Sec(x) => vec![x],
+            Self::Csc(x) => vec![x],
+            Self::Asin(x) => vec![x],
+            Self::Acos(x) => vec![x],
+            Self::Atan(x) => vec![x],
+            Self::Atan2(a, b) => vec![a, b],
+            Self::Sinh(x) => vec![x],
+            Self::Cosh(x) => vec![x],
+            Self::Tanh(x) => vec![x],
+            Self::Coth(x) => vec![x],
+            Self::Sech(x) => vec![x],
+            Self::Csch(x) => vec![x],
+            Self::Asinh(x) => vec![x],
+            Self::Acosh(x) => vec![x],
+            Self::Atanh(x) => vec![x],
+            Self::Log(a, b) => vec![a, b],
+            Self::Log10(x) => vec![x],
+            Self::Ln(x) => vec![x],
+            Self::Log2(x) => vec![x],
+            Self::Sqrt(x) => vec![x],
+            Self::Root(a, b) => vec![a, b],
+            Self::Hypot(a, b) => vec![a, b],
+            Self::Exp(x) => vec![x],
+            Self::Exp2(x) => vec![x],
+            Self::Exp10(x) => vec![x],
+            Self::Mod(a, b) => vec![a, b],
+            Self::Gcd(a, b) => vec![a, b],
+            Self::Lcm(a, b) => vec![a, b],
+            Self::Gamma(x) => vec![x],
+            Self::Lgamma(x) => vec![x],
+            Self::Ceil(x) => vec![x],
+            Self::Floor(x) => vec![x],
+            Self::Round(x) => vec![x],
+            Self::Trunc(x) => vec![x],
+            Self::Fract(x) => vec![x],
+            Self::Abs(x) => vec![x],
+            Self::Signum(x) => vec![x],
+            Self::Min(a, b) => vec![a, b],
+            Self::Max(a, b) => vec![a, b],
+            Self::Clamp(a, b, c) => vec![a, b, c],
+        }
+    }
+
+    /// Rebuilds this function with every argument replaced by `f(argument)`.
+    pub fn map<'b, U>(
+        &self,
+        mut f: impl FnMut(&'a Entity<'a, T>) -> &'b Entity<'b, U>,
+    ) -> Function<'b, U> {
+        match *self {
+            Self::Sin(x) => Function::Sin(f(x)),
+            Self::Cos(x) => Function::Cos(f(x)),
+            Self::Tan(x) => Function::Tan(f(x)),
+            Self::Cot(x) => Function::Cot(f(x)),
+            Self::Sec(x) => Function::Sec(f(x)),
+            Self::Csc(x) => Function::Csc(f(x)),
+            Self::Asin(x) => Function::Asin(f(x)),
+            Self::Acos(x) => Function::Acos(f(x)),
+            Self::Atan(x) => Function::Atan(f(x)),
+            Self::Atan2(a, b) => Function::Atan2(f(a), f(b)),
+            Self::Sinh(x) => Function::Sinh(f(x)),
+            Self::Cosh(x) => Function::Cosh(f(x)),
+            Self::Tanh(x) => Function::Tanh(f(x)),
+            Self::Coth(x) => Function::Coth(f(x)),
+            Self::Sech(x) => Function::Sech(f(x)),
+            Self::Csch(x) => Function::Csch(f(x)),
+            Self::Asinh(x) => Function::Asinh(f(x)),
+            Self::Acosh(x) => Function::Acosh(f(x)),
+            Self::Atanh(x) => Function::Atanh(f(x)),
+            Self::Log(a, b) => Function::Log(f(a), f(b)),
+            Self::Log10(x) => Function::Log10(f(x)),
+            Self::Ln(x) => Function::Ln(f(x)),
+            Self::Log2(x) => Function::Log2(f(x)),
+            Self::Sqrt(x) => Function::Sqrt(f(x)),
+            Self::Root(a, b) => Function::Root(f(a), f(b)),
+            Self::Hypot(a, b) => Function::Hypot(f(a), f(b)),
+            Self::Exp(x) => Function::Exp(f(x)),
+            Self::Exp2(x) => Function::Exp2(f(x)),
+            Self::Exp10(x) => Function::Exp10(f(x)),
+            Self::Mod(a, b) => Function::Mod(f(a), f(b)),
+            Self::Gcd(a, b) => Function::Gcd(f(a), f(b)),
+            Self::Lcm(a, b) => Function::Lcm(f(a), f(b)),
+            Self::Gamma(x) => Function::Gamma(f(x)),
+            Self::Lgamma(x) => Function::Lgamma(f(x)),
+            Self::Ceil(x) => Function::Ceil(f(x)),
+            Self::Floor(x) => Function::Floor(f(x)),
+            Self::Round(x) => Function::Round(f(x)),
+            Self::Trunc(x) => Function::Trunc(f(x)),
+            Self::Fract(x) => Function::Fract(f(x)),
+            Self::Abs(x) => Function::Abs(f(x)),
+            Self::Signum(x) => Function::Signum(f(x)),
+            Self::Min(a, b) => Function::Min(f(a), f(b)),
+            Self::Max(a, b) => Function::Max(f(a), f(b)),
+            Self::Clamp(a, b, c) => Function::Clamp(f(a), f(b), f(c)),
+        }
+    }
+}
+
+/// An [`Entity`] tree that is [`Hash`] and [`Eq`], to be used as a map key.
+///
+/// Literals are compared and hashed by their bits, so unlike with `==` on the tree itself,
+/// `NaN` equals `NaN`, and `0` and `-0` are different.
+#[derive(Debug, Clone, Copy)]
+pub struct HashableEntity<'a, T>(pub Entity<'a, T>);
+
+impl<T: num_traits::Float> PartialEq for HashableEntity<'_, T> {
+    fn eq(&self, other: &Self) -> bool {
+        same_tree(&self.0, &other.0)
+    }
+}
+
+impl<T: num_traits::Float> Eq for HashableEntity<'_, T> {}
+
+impl<T: num_traits::Float> core::hash::Hash for HashableEntity<'_, T> {
+    fn hash<H: core::hash::Hasher>(&self, state: &mut H) {
+        hash_tree(&self.0, state);
+    }
+}
+
+fn same_tree<T: num_traits::Float>(a: &Entity<'_, T>, b: &Entity<'_, T>) -> bool {
+    match (a, b) {
+        (Entity::ValueLit(a), Entity::ValueLit(b)) => a.integer_decode() == b.integer_decode(),
+        (Entity::Value(a), Entity::Value(b)) => a == b,
+        (Entity::Operation(a), Entity::Operation(b)) => {
+            a.operator() == b.operator()
+                && a.operands()
+                    .into_iter()
+                    .zip(b.operands())
+                    .all(|(a, b)| same_tree(a, b))
+        }
+        // `log(x)` and `log(10, x)` are both `log`, but differ in their number of arguments
+        (Entity::Function(a), Entity::Function(b)) => {
+            let (a_args, b_args) = (a.arguments(), b.arguments());
+            a.function_type() == b.function_type()
+                && a_args.len() == b_args.len()
+                && a_args.into_iter().zip(b_args).all(|(a, b)| same_tree(a, b))
+        }
+        _ => false,
+    }
+}
+
+fn hash_tree<T: num_traits::Float, H: core::hash::Hasher>(entity: &Entity<'_, T>, state: &mut H) {
+    use core::hash::Hash;
+
+    core::mem::discriminant(entity).hash(state);
+    match entity {
+        Entity::ValueLit(val) => val.integer_decode().hash(state),
+        Entity::Value(name) => name.hash(state),
+        Entity::Operation(op) => {
+            op.operator().hash(state);
+            for operand in op.operands() {
+                hash_tree(operand, state);
+            }
+        }
+        Entity::Function(func) => {
+            func.function_type().hash(state);
+            for argument in func.arguments() {
+                hash_tree(argument, state);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    extern crate std;
+
+    use super::*;
+    use crate::{EntityArena, parse};
+    use alloc::string::ToString;
+    use std::collections::{HashMap, HashSet};
+
+    #[test]
+    fn operators_are_ordered_by_precedence() {
+        use Operator::*;
+
+        assert_eq!(Add.precedence(), Sub.precedence());
+        assert!(Sub.precedence() < Mul.precedence());
+        assert_eq!(Mul.precedence(), Div.precedence());
+        assert!(Div.precedence() < Pow.precedence());
+        assert!(Pow.precedence() < Factorial.precedence());
+    }
+
+    #[test]
+    fn function_type_as_map_key() {
+        let mut counts = HashMap::new();
+        for function in [FunctionType::Sin, FunctionType::Cos, FunctionType::Sin] {
+            *counts.entry(function).or_insert(0) += 1;
+        }
+        assert_eq!(counts[&FunctionType::Sin], 2);
+        assert_eq!(counts[&FunctionType::Cos], 1);
+        assert!(!counts.contains_key(&FunctionType::Tan));
+    }
+
+    #[test]
+    fn hashable_entity_dedupes_equal_trees() {
+        let arena = EntityArena::new();
+        let mut trees = HashSet::new();
+        for equation in ["sin(x)+2", "sin(x) + 2", "sin(x)+3", "cos(x)+2"] {
+            trees.insert(HashableEntity(parse::<f64>(equation, &arena).unwrap()));
+        }
+        assert_eq!(trees.len(), 3);
+    }
+
+    #[test]
+    fn hashable_entity_compares_argument_counts() {
+        let arena = EntityArena::new();
+        let log10 = HashableEntity(parse::<f64>("log(x)", &arena).unwrap());
+        let log = HashableEntity(parse::<f64>("log(10,x)", &arena).unwrap());
+        assert_ne!(log10, log);
+        assert_eq!(HashSet::from([log10, log]).len(), 2);
+    }
+
+    #[test]
+    fn hashable_entity_compares_literal_bits() {
+        let arena = EntityArena::new();
+        let nan = HashableEntity(Entity::ValueLit(f64::NAN));
+        assert_eq!(nan, nan);
+        let zero = HashableEntity(parse::<f64>("0", &arena).unwrap());
+        assert_ne!(zero, HashableEntity(Entity::ValueLit(-0.0)));
+    }
+
+    /// Every [`FunctionType`], in declaration order.
+    const FUNCTIONS: [FunctionType; 48] = [
+        FunctionType::Sin,
+        FunctionType::Cos,
+        FunctionType::Tan,
+        FunctionType::Cot,
+        FunctionType::Sec,
+        FunctionType::Csc,
+        FunctionType::Asin,
+        FunctionType::Acos,
+        FunctionType::Atan,
+        FunctionType::Atan2,
+        FunctionType::Sinh,
+        FunctionType::Cosh,
+        FunctionType::Tanh,
+        FunctionType::Coth,
+        FunctionType::Sech,
+        FunctionType::Csch,
+        FunctionType::Asinh,
+        FunctionType::Acosh,
+        FunctionType::Atanh,
+        FunctionType::Log,
+        FunctionType::Ln,
+        FunctionType::Log2,
+        FunctionType::Sqrt,
+        FunctionType::Root,
+        FunctionType::Hypot,
+        FunctionType::Exp,
+        FunctionType::Exp2,
+        FunctionType::Exp10,
+        FunctionType::Mod,
+        FunctionType::Gcd,
+        FunctionType::Lcm,
+        FunctionType::Gamma,
+        FunctionType::Lgamma,
+        FunctionType::Ceil,
+        FunctionType::Floor,
+        FunctionType::Round,
+        FunctionType::Trunc,
+        FunctionType::Fract,
+        FunctionType::Abs,
+        FunctionType::Signum,
+        FunctionType::Min,
+        FunctionType::Max,
+        FunctionType::Clamp,
+        FunctionType::Pow,
+        FunctionType::Add,
+        FunctionType::Sub,
+        FunctionType::Mul,
+        FunctionType::Div,
+    ];
+
+    #[test]
+    fn function_names_round_trip() {
+        for function in FUNCTIONS {
+            assert_eq!(
+                FunctionType::from_str(&function.to_string()).unwrap(),
+                function
+            );
+        }
+        assert_eq!(FunctionType::from_str("sin").unwrap().to_string(), "sin");
+        assert!(FunctionType::from_str("sine").is_err());
+    }
+
+    #[test]
+    fn operators_and_parentheses_display() {
+        let operators = [
+            Operator::Add,
+            Operator::Sub,
+            Operator::Mul,
+            Operator::Div,
+            Operator::Pow,
+        ];
+        for (op, text) in operators.into_iter().zip(["+", "-", "*", "/", "^"]) {
+            assert_eq!(op.to_string(), text);
+            assert_eq!(
+                Operator::try_from(text.chars().next().unwrap()).unwrap(),
+                op
+            );
+        }
+
+        for text in ['(', ')', '[', ']', '{', '}'] {
+            let paren = ParenthesisType::try_from(text).unwrap();
+            assert_eq!(paren.to_string(), text.to_string());
+        }
+    }
+
+    #[test]
+    fn every_function_has_its_arity() {
+        for function in FUNCTIONS {
+            let expected = match function {
+                FunctionType::Clamp => 3,
+                FunctionType::Log
+                | FunctionType::Root
+                | FunctionType::Mod
+                | FunctionType::Atan2
+                | FunctionType::Min
+                | FunctionType::Max
+                | FunctionType::Gcd
+                | FunctionType::Lcm
+                | FunctionType::Hypot
+                | FunctionType::Pow
+                | FunctionType::Add
+                | FunctionType::Sub
+                | FunctionType::Mul
+                | FunctionType::Div => 2,
+                _ => 1,
+            };
+            assert_eq!(function.arity(), expected, "{function}");
+        }
+        assert_eq!(FunctionType::Sin.arity(), 1);
+        assert_eq!(FunctionType::Log.arity(), 2);
+    }
+
+    #[test]
+    fn pow_binds_tightest_and_groups_right() {
+        assert!(Operator::Pow.precedence() > Operator::Mul.precedence());
+        assert!(Operator::Mul.precedence() > Operator::Add.precedence());
+        assert_eq!(Operator::Mul.precedence(), Operator::Div.precedence());
+        assert_eq!(Operator::Add.precedence(), Operator::Sub.precedence());
+
+        let right_associative = [
+            Operator::Add,
+            Operator::Sub,
+            Operator::Mul,
+            Operator::Div,
+            Operator::FloorDiv,
+            Operator::Pow,
+            Operator::Rem,
+        ]
+        .into_iter()
+        .filter(Operator::is_right_associative);
+        assert!(right_associative.eq([Operator::Pow]));
+    }
+
+    #[test]
+    fn classifies_transcendental_functions() {
+        use FunctionType::*;
+
+        let transcendental: Vec<_> = FUNCTIONS
+            .into_iter()
+            .filter(FunctionType::is_transcendental)
+            .collect();
+        assert_eq!(
+            transcendental,
+            [
+                Sin, Cos, Tan, Cot, Sec, Csc, Asin, Acos, Atan, Atan2, Sinh, Cosh, Tanh, Coth,
+                Sech, Csch, Asinh, Acosh, Atanh, Log, Ln, Log2, Root, Exp, Exp2, Exp10, Gamma,
+                Lgamma, Pow,
+            ]
+        );
+
+        let inline: Vec<_> = FUNCTIONS
+            .into_iter()
+            .filter(|function| !function.is_transcendental())
+            .collect();
+        assert_eq!(
+            inline,
+            [
+                Sqrt, Hypot, Mod, Gcd, Lcm, Ceil, Floor, Round, Trunc, Fract, Abs, Signum, Min,
+                Max, Clamp, Add, Sub, Mul, Div,
+            ]
+        );
+    }
+}
@@ -0,0 +1,189 @@
+use crate::arena::EntityArena;
+use crate::ast::{Entity, RawSyntax, RelationOp};
+use crate::{EquationParseError, Numeric, Parser, substitute, tokenize};
+
+use alloc::string::ToString;
+use alloc::vec::Vec;
+
+/// Parses an equation with bindings for shared subexpressions after a `where`,
+/// like `a*b where a=2+x, b=x-1`, into a single tree with the bindings expanded:
+/// `(2+x)*(x-1)`. Bindings can use the ones before or after them, but not themselves,
+/// directly or through others, which fails with [`EquationParseError::CyclicBinding`].
+///
+/// Without a `where`, this parses exactly like [`parse`](crate::parse).
+pub fn parse_with_bindings<'a, T: Numeric>(
+    equation: &'a str,
+    arena: &'a EntityArena<'a, T>,
+) -> Result<Entity<'a, T>, EquationParseError> {
+    let first = tokenize(equation)?;
+
+    let options = Parser::default();
+    let parse =
+        |tokens: &[RawSyntax]| options.build_tree(&options.resolve(tokens, equation)?, arena);
+
+    let split = first.iter().position(|token| match *token {
+        RawSyntax::ValueIdent { start, end } => &equation[start..end] == "where",
+        _ => false,
+    });
+    let Some(split) = split else {
+        return parse(&first[..]);
+    };
+
+    let mut bindings: Vec<Binding<'a, T>> = Vec::new();
+    for tokens in split_bindings(&first[split + 1..]) {
+        let index = match tokens.first() {
+            Some(token) => token.span().start,
+            None => first[split].span().end,
+        };
+        let (name, value) = match tokens {
+            [
+                RawSyntax::ValueIdent { start, end },
+                RawSyntax::Relation {
+                    kind: RelationOp::Eq,
+                    ..
+                },
+                value @ ..,
+            ] => (&equation[*start..*end], value),
+            _ => return Err(EquationParseError::MalformedBinding { index }),
+        };
+        if bindings.iter().any(|binding| binding.name == name) {
+            return Err(EquationParseError::MalformedBinding { index });
+        }
+
+        bindings.push(Binding {
+            name,
+            value: parse(value)?,
+            state: State::Unexpanded,
+        });
+    }
+
+    for i in 0..bindings.len() {
+        expand(&mut bindings, i, arena)?;
+    }
+
+    let mut root = parse(&first[..split])?;
+    for binding in &bindings {
+        root = *substitute(&root, binding.name, &binding.value, arena);
+    }
+    Ok(root)
+}
+
+struct Binding<'a, T> {
+    name: &'a str,
+    value: Entity<'a, T>,
+    state: State,
+}
+
+#[derive(PartialEq)]
+enum State {
+    Unexpanded,
+    /// Its value is being expanded, so finding it again means it refers to itself.
+    Expanding,
+    /// Its value no longer uses other bindings.
+    Expanded,
+}
+
+/// Substitutes the bindings used by the value of binding `i`, expanding them first.
+fn expand<'a, T: Copy>(
+    bindings: &mut [Binding<'a, T>],
+    i: usize,
+    arena: &'a EntityArena<'a, T>,
+) -> Result<(), EquationParseError> {
+    match bindings[i].state {
+        State::Expanded => return Ok(()),
+        State::Expanding => {
+            return Err(EquationParseError::CyclicBinding {
+                name: bindings[i].name.to_string(),
+            });
+        }
+        State::Unexpanded => bindings[i].state = State::Expanding,
+    }
+
+    for j in 0..bindings.len() {
+        if !uses(&bindings[i].value, bindings[j].name) {
+            continue;
+        }
+        expand(bindings, j, arena)?;
+        let value = substitute(
+            &bindings[i].value,
+            bindings[j].name,
+            &bindings[j].value,
+            arena,
+        );
+        bindings[i].value = *value;
+    }
+
+    bindings[i].state = State::Expanded;
+    Ok(())
+}
+
+/// Whether the variable `name` appears in `entity`.
+fn uses<T>(entity: &Entity<'_, T>, name: &str) -> bool {
+    match entity {
+        Entity::Value(value) => *value == name,
+        _ => entity.children().into_iter().any(|child| uses(child, name)),
+    }
+}
+
+/// Splits the tokens after `where` on the commas between bindings,
+/// leaving the ones that separate function arguments.
+fn split_bindings(tokens: &[RawSyntax]) -> Vec<&[RawSyntax]> {
+    let mut bindings = Vec::new();
+    let mut depth = 0usize;
+    let mut start = 0;
+
+    for (i, token) in tokens.iter().enumerate() {
+        match *token {
+            RawSyntax::Parenthesis { kind, .. } if kind.is_open() => depth += 1,
+            RawSyntax::Parenthesis { .. } => depth = depth.saturating_sub(1),
+            RawSyntax::Comma { .. } if depth == 0 => {
+                bindings.push(&tokens[start..i]);
+                start = i + 1;
+            }
+            _ => {}
+        }
+    }
+
+    bindings.push(&tokens[start..]);
+    bindings
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parse;
+
+    fn expands_to(equation: &str, expected: &str) {
+        let arena = EntityArena::new();
+        assert_eq!(
+            parse_with_bindings::<f64>(equation, &arena).unwrap(),
+            parse(expected, &arena).unwrap()
+        );
+    }
+
+    #[test]
+    fn expands_bindings() {
+        expands_to("a*b where a=2+x, b=x-1", "(2+x)*(x-1)");
+        expands_to("a^2 where a=b+1, b=2x", "((2x)+1)^2");
+        expands_to("x+1", "x+1");
+    }
+
+    #[test]
+    fn rejects_cyclic_bindings() {
+        let arena = EntityArena::new();
+        for equation in ["a where a=b, b=a", "a where a=a+1"] {
+            assert!(matches!(
+                parse_with_bindings::<f64>(equation, &arena),
+                Err(EquationParseError::CyclicBinding { name }) if name == "a"
+            ));
+        }
+        assert!(matches!(
+            parse_with_bindings::<f64>("a where 2=a", &arena),
+            Err(EquationParseError::MalformedBinding { index: 8 })
+        ));
+        assert!(matches!(
+            parse_with_bindings::<f64>("a where a=1, a=2", &arena),
+            Err(EquationParseError::MalformedBinding { index: 13 })
+        ));
+    }
+}
@@ -0,0 +1,155 @@
+use crate::arena::EntityArena;
+use crate::ast::{Entity, Function, FunctionType, Operation};
+
+use alloc::vec::Vec;
+use core::ops;
+
+/// Builds [`Entity`] trees in Rust instead of parsing them, allocating every node in an arena.
+/// Its [`Expr`]s combine with the usual operators, so `x^2+1` is
+/// `x.pow(b.lit(2.0)) + b.lit(1.0)`, with `x` being `b.var("x")`.
+pub struct Builder<'a, T> {
+    arena: &'a EntityArena<'a, T>,
+}
+
+impl<'a, T> Builder<'a, T> {
+    pub fn new(arena: &'a EntityArena<'a, T>) -> Self {
+        Self { arena }
+    }
+
+    /// A literal, like `2`.
+    pub fn lit(&self, val: T) -> Expr<'a, T> {
+        self.expr(Entity::ValueLit(val))
+    }
+
+    /// A variable or constant, like `x` or `pi`.
+    pub fn var(&self, name: &'a str) -> Expr<'a, T> {
+        self.expr(Entity::Value(name))
+    }
+
+    /// `function` applied to `args`, or `None` if it doesn't take that many arguments,
+    /// like [`Function::from_arguments`].
+    pub fn function(&self, function: FunctionType, args: &[Expr<'a, T>]) -> Option<Expr<'a, T>> {
+        let args: Vec<_> = args.iter().map(|arg| arg.entity).collect();
+        let function = Function::from_arguments(function, &args)?;
+        Some(self.expr(Entity::Function(function)))
+    }
+
+    fn expr(&self, entity: Entity<'a, T>) -> Expr<'a, T> {
+        Expr {
+            entity: self.arena.alloc(entity),
+            arena: self.arena,
+        }
+    }
+}
+
+/// A node of a tree made with a [`Builder`], which operators like `+` combine into bigger ones.
+pub struct Expr<'a, T> {
+    entity: &'a Entity<'a, T>,
+    arena: &'a EntityArena<'a, T>,
+}
+
+// derived, these would need `T: Copy`, though only references are copied
+impl<T> Clone for Builder<'_, T> {
+    fn clone(&self) -> Self {
+        *self
+    }
+}
+
+impl<T> Copy for Builder<'_, T> {}
+
+impl<T> Clone for Expr<'_, T> {
+    fn clone(&self) -> Self {
+        *self
+    }
+}
+
+impl<T> Copy for Expr<'_, T> {}
+
+impl<'a, T> Expr<'a, T> {
+    /// The tree built so far.
+    pub fn entity(self) -> &'a Entity<'a, T> {
+        self.entity
+    }
+
+    /// `self^exponent`. `^` is the bitwise xor operator in Rust, so it isn't overloaded.
+    pub fn pow(self, exponent: Self) -> Self {
+        self.operation(Operation::Pow(self.entity, exponent.entity))
+    }
+
+    /// `self!`
+    pub fn factorial(self) -> Self {
+        self.operation(Operation::Factorial(self.entity))
+    }
+
+    fn operation(self, op: Operation<'a, T>) -> Self {
+        Builder::new(self.arena).expr(Entity::Operation(op))
+    }
+}
+
+macro_rules! binary_operator {
+    ($trait:ident, $method:ident, $operation:ident) => {
+        impl<'a, T> ops::$trait for Expr<'a, T> {
+            type Output = Self;
+
+            fn $method(self, rhs: Self) -> Self {
+                self.operation(Operation::$operation(self.entity, rhs.entity))
+            }
+        }
+    };
+}
+
+binary_operator!(Add, add, Add);
+binary_operator!(Sub, sub, Sub);
+binary_operator!(Mul, mul, Mul);
+binary_operator!(Div, div, Div);
+binary_operator!(Rem, rem, Rem);
+
+impl<T> ops::Neg for Expr<'_, T> {
+    type Output = Self;
+
+    fn neg(self) -> Self {
+        self.operation(Operation::Neg(self.entity))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parse;
+
+    #[test]
+    fn builds_the_parsed_tree() {
+        let arena = EntityArena::new();
+        let b = Builder::new(&arena);
+        let x = b.var("x");
+        let built = x.pow(b.lit(2.0)) + b.lit(1.0);
+        assert_eq!(*built.entity(), parse("x^2+1", &arena).unwrap());
+
+        let y = b.var("y");
+        let built = -(x - y) * (x / y) % b.lit(3.0).factorial();
+        assert_eq!(*built.entity(), parse("-(x-y)*(x/y)%3!", &arena).unwrap());
+    }
+
+    #[test]
+    fn builds_functions_with_their_arity() {
+        let arena = EntityArena::new();
+        let b = Builder::new(&arena);
+        let x = b.var("x");
+        let clamped = b
+            .function(
+                FunctionType::Clamp,
+                &[
+                    b.function(FunctionType::Sin, &[x]).unwrap(),
+                    b.lit(0.0),
+                    b.lit(1.0),
+                ],
+            )
+            .unwrap();
+        assert_eq!(
+            *clamped.entity(),
+            parse("clamp(sin(x), 0, 1)", &arena).unwrap()
+        );
+        assert!(b.function(FunctionType::Sin, &[x, x]).is_none());
+        assert!(b.function(FunctionType::Atan2, &[x]).is_none());
+    }
+}
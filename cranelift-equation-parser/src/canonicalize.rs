@@ -0,0 +1,105 @@
+use crate::arena::EntityArena;
+use crate::ast::{Entity, Operation};
+
+/// Rewrites an [`Entity`] tree into a canonical form with only additions and multiplications,
+/// allocating the new nodes in `arena`: `a-b` becomes `a+(-1*b)`, `a/b` becomes `a*b^-1`,
+/// and `-a` becomes `-1*a`. Rules for symbolic manipulation then only need to handle `+` and `*`.
+///
+/// The tree evaluates the same, but reads worse, so display the original one instead.
+pub fn canonicalize<'a, T: num_traits::Float>(
+    entity: &Entity<'a, T>,
+    arena: &'a EntityArena<'a, T>,
+) -> &'a Entity<'a, T> {
+    let minus_one = arena.alloc(Entity::ValueLit(-T::one()));
+    Canonicalization { minus_one, arena }.canonicalize(entity)
+}
+
+struct Canonicalization<'a, T> {
+    /// Shared by every rewritten node
+    minus_one: &'a Entity<'a, T>,
+    arena: &'a EntityArena<'a, T>,
+}
+
+impl<'a, T: Copy> Canonicalization<'a, T> {
+    fn canonicalize(&self, entity: &Entity<'a, T>) -> &'a Entity<'a, T> {
+        let op = match entity {
+            Entity::Operation(op) => op.map(|operand| self.canonicalize(operand)),
+            Entity::Function(func) => {
+                let func = func.map(|arg| self.canonicalize(arg));
+                return self.arena.alloc(Entity::Function(func));
+            }
+            Entity::ValueLit(_) | Entity::Value(_) => return self.arena.alloc(*entity),
+        };
+
+        let op = match op {
+            Operation::Sub(lhs, rhs) => Operation::Add(lhs, self.negate(rhs)),
+            Operation::Div(lhs, rhs) => {
+                Operation::Mul(lhs, self.alloc(Operation::Pow(rhs, self.minus_one)))
+            }
+            Operation::Neg(x) => return self.negate(x),
+            op => op,
+        };
+        self.alloc(op)
+    }
+
+    /// `-1*x`
+    fn negate(&self, x: &'a Entity<'a, T>) -> &'a Entity<'a, T> {
+        self.alloc(Operation::Mul(self.minus_one, x))
+    }
+
+    fn alloc(&self, op: Operation<'a, T>) -> &'a Entity<'a, T> {
+        self.arena.alloc(Entity::Operation(op))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{eval, parse};
+
+    #[test]
+    fn normalizes_subtraction_and_division() {
+        let arena = EntityArena::new();
+        let (a, b) = (&Entity::Value("a"), &Entity::Value("b"));
+        let minus_one = &Entity::ValueLit(-1.0);
+
+        let difference = parse::<f64>("a-b", &arena).unwrap();
+        assert_eq!(
+            *canonicalize(&difference, &arena),
+            Entity::Operation(Operation::Add(
+                a,
+                &Entity::Operation(Operation::Mul(minus_one, b))
+            ))
+        );
+
+        let quotient = parse::<f64>("a/b", &arena).unwrap();
+        assert_eq!(
+            *canonicalize(&quotient, &arena),
+            Entity::Operation(Operation::Mul(
+                a,
+                &Entity::Operation(Operation::Pow(b, minus_one))
+            ))
+        );
+
+        let negation = parse::<f64>("-a", &arena).unwrap();
+        assert_eq!(
+            *canonicalize(&negation, &arena),
+            Entity::Operation(Operation::Mul(minus_one, a))
+        );
+    }
+
+    #[test]
+    fn evaluates_the_same() {
+        let arena = EntityArena::new();
+        let vars = [("x", 1.5), ("y", -4.0)];
+        for equation in ["x-y/2", "sin(x-y)/(x-1)", "-(x/y)^2 - -x"] {
+            let entity = parse::<f64>(equation, &arena).unwrap();
+            let canonical = canonicalize(&entity, &arena);
+            let (expected, actual) = (eval(&entity, &vars[..]), eval(canonical, &vars[..]));
+            assert!(
+                (expected.unwrap() - actual.unwrap()).abs() < 1e-12,
+                "{equation}"
+            );
+        }
+    }
+}
@@ -0,0 +1,183 @@
+use crate::ast::{Entity, FunctionType, Operation, Operator};
+use crate::eval::{
+    EvalError, Variables, apply_function, apply_operator, fold, integer_exponent, known_constant,
+};
+
+use alloc::string::{String, ToString};
+use alloc::vec::Vec;
+use core::convert::Infallible;
+use num_traits::FloatConst;
+
+/// A step of a compiled tree, which works on a stack of values: operands are pushed,
+/// and operators and functions replace their operands on top of it with their result.
+enum Instruction<T> {
+    Literal(T),
+    Value(String),
+    /// An operator and how many operands it takes.
+    Operator(Operator, usize),
+    /// `x^n`, for an integer literal `n`. The literal is pushed too, and ignored.
+    Powi(i32),
+    /// A function and how many arguments it's called with.
+    Function(FunctionType, usize),
+}
+
+/// Compiles an [`Entity`] tree into a closure, to evaluate it many times with different variables.
+///
+/// The tree is walked once, here, into a flat list of instructions. The closure owns them, so it
+/// outlives both the tree and its [`EntityArena`](crate::EntityArena), and runs them without
+/// recursion, so no tree is too deep. It gives the same results as [`eval`](crate::eval).
+pub fn compile<'c, T, V>(entity: &Entity<'_, T>) -> impl Fn(&V) -> Result<T, EvalError> + 'c
+where
+    T: num_traits::Float + FloatConst + 'c,
+    V: Variables<T> + ?Sized + 'c,
+{
+    let mut program = Vec::new();
+    // the tree is folded children first, so every instruction comes after its operands'
+    let Ok(()) = fold(entity, |entity, _| {
+        program.push(instruction(entity));
+        Ok::<_, Infallible>(())
+    });
+
+    move |vars: &V| {
+        let mut stack: Vec<T> = Vec::new();
+        // no function takes more than three arguments
+        let mut args = [T::zero(); 3];
+        for instruction in &program {
+            let val = match instruction {
+                Instruction::Literal(val) => *val,
+                Instruction::Value(name) => value(vars, name)?,
+                Instruction::Operator(op, operands) => {
+                    apply_operator(*op, pop(&mut stack, &mut args, *operands))
+                }
+                Instruction::Powi(n) => pop(&mut stack, &mut args, 2)[0].powi(*n),
+                Instruction::Function(function, arguments) => {
+                    apply_function(*function, pop(&mut stack, &mut args, *arguments))
+                }
+            };
+            stack.push(val);
+        }
+        Ok(stack.pop().expect("a compiled tree has a value"))
+    }
+}
+
+/// Moves the top `count` values of `stack` into `args`.
+fn pop<'a, T: Copy>(stack: &mut Vec<T>, args: &'a mut [T; 3], count: usize) -> &'a [T] {
+    let start = stack.len() - count;
+    args[..count].copy_from_slice(&stack[start..]);
+    stack.truncate(start);
+    &args[..count]
+}
+
+/// Evaluates an [`Entity`] tree once for every value of the variable `var` in `values`,
+/// like for the points of a plot: `x^2` over `[0, 1, 2, 3]` gives `[0, 1, 4, 9]`.
+/// The tree is [`compile`]d first, so it's only walked once.
+///
+/// Fails with the first error, like when the tree has other variables than `var`.
+pub fn eval_batch<T: num_traits::Float + FloatConst>(
+    entity: &Entity<'_, T>,
+    var: &str,
+    values: &[T],
+) -> Result<Vec<T>, EvalError> {
+    let compiled = compile::<T, [(&str, T)]>(entity);
+    values
+        .iter()
+        .map(|value| compiled(&[(var, *value)]))
+        .collect()
+}
+
+fn instruction<T: num_traits::Float>(entity: &Entity<'_, T>) -> Instruction<T> {
+    match entity {
+        Entity::ValueLit(val) => Instruction::Literal(*val),
+        Entity::Value(name) => Instruction::Value(name.to_string()),
+        Entity::Operation(Operation::Pow(_, exponent)) => match integer_exponent(exponent) {
+            Some(n) => Instruction::Powi(n),
+            None => Instruction::Operator(Operator::Pow, 2),
+        },
+        Entity::Operation(op) => Instruction::Operator(op.operator(), op.operands().len()),
+        Entity::Function(func) => {
+            Instruction::Function(func.function_type(), func.arguments().len())
+        }
+    }
+}
+
+fn value<T: num_traits::Float + FloatConst, V: Variables<T> + ?Sized>(
+    vars: &V,
+    name: &str,
+) -> Result<T, EvalError> {
+    vars.get(name)
+        .or_else(|| known_constant(name))
+        .ok_or_else(|| EvalError::UndefinedVariable(String::from(name)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{EntityArena, eval, parse};
+
+    #[test]
+    fn evaluates_a_quadratic_over_many_inputs() {
+        // the closure outlives the tree and its arena
+        let quadratic = {
+            let arena = EntityArena::new();
+            compile::<f64, [(&str, f64)]>(&parse("a*x^2+b*x+c", &arena).unwrap())
+        };
+
+        for i in -500..500 {
+            let x = f64::from(i) / 10.0;
+            let vars = [("a", 2.0), ("b", -3.0), ("c", 0.5), ("x", x)];
+            assert_eq!(quadratic(&vars).unwrap(), 2.0 * x * x - 3.0 * x + 0.5);
+        }
+    }
+
+    #[test]
+    fn matches_eval() {
+        let arena = EntityArena::new();
+        let vars = [("x", 0.7), ("y", -2.5)];
+        for equation in [
+            "sin(x)^2 + cos(x)^2",
+            "x^y + y^3 - 4!",
+            "max(x, y) * clamp(y, -1, 1) // 0.3 % 2",
+            "-|y|^0.5 + log(2, x) + atan2(y, x)",
+            "pi*e/tau",
+        ] {
+            let entity = parse::<f64>(equation, &arena).unwrap();
+            let compiled = compile::<f64, [(&str, f64)]>(&entity);
+            assert_eq!(
+                compiled(&vars).unwrap().to_bits(),
+                eval(&entity, &vars[..]).unwrap().to_bits(),
+                "{equation}"
+            );
+        }
+
+        let entity = parse::<f64>("x + z", &arena).unwrap();
+        assert!(matches!(
+            compile::<f64, [(&str, f64)]>(&entity)(&vars),
+            Err(EvalError::UndefinedVariable(name)) if name == "z"
+        ));
+    }
+
+    #[test]
+    fn evaluates_batches() {
+        let arena = EntityArena::new();
+        let square = parse::<f64>("x^2", &arena).unwrap();
+        assert_eq!(
+            eval_batch(&square, "x", &[0.0, 1.0, 2.0, 3.0]).unwrap(),
+            [0.0, 1.0, 4.0, 9.0]
+        );
+        assert!(eval_batch(&square, "x", &[]).unwrap().is_empty());
+
+        let line = parse::<f64>("2t+pi", &arena).unwrap();
+        let values = [-1.0, 0.5];
+        let expected: Vec<f64> = values
+            .iter()
+            .map(|t| eval(&line, &[("t", *t)][..]).unwrap())
+            .collect();
+        assert_eq!(eval_batch(&line, "t", &values).unwrap(), expected);
+
+        let plane = parse::<f64>("x+y", &arena).unwrap();
+        assert!(matches!(
+            eval_batch(&plane, "x", &[1.0]),
+            Err(EvalError::UndefinedVariable(name)) if name == "y"
+        ));
+    }
+}
@@ -0,0 +1,299 @@
+use crate::arena::EntityArena;
+use crate::ast::Entity;
+use crate::tokens::Tokens;
+use crate::{EquationParseError, Numeric, Parser, second_parse, validate_parens};
+
+use alloc::string::String;
+use alloc::vec::Vec;
+use core::fmt::Write;
+use core::ops::Range;
+
+/// How bad a [`Diagnostic`] is.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Severity {
+    /// The equation can't be parsed.
+    Error,
+    /// The equation parses, but maybe not the way it was meant to, like the bars of `|a|b|c|`,
+    /// which could be `|a|*b*|c|` or `|a*|b|*c|`.
+    Warning,
+}
+
+/// A problem found by [`parse_diagnostics`].
+#[derive(Debug, Clone)]
+pub struct Diagnostic {
+    pub severity: Severity,
+    /// The byte range of the problem in the equation.
+    pub span: Range<usize>,
+    pub error: EquationParseError,
+}
+
+/// Where the parser stages send the errors they can recover from.
+pub(crate) trait Report {
+    /// Handles an error spanning the `span` bytes of the equation.
+    /// Returning `Ok` lets the parser keep scanning.
+    fn report(
+        &mut self,
+        error: EquationParseError,
+        span: Range<usize>,
+    ) -> Result<(), EquationParseError>;
+}
+
+/// Stops at the first error, like [`parse`](crate::parse).
+pub(crate) struct Bail;
+
+impl Report for Bail {
+    fn report(
+        &mut self,
+        error: EquationParseError,
+        _: Range<usize>,
+    ) -> Result<(), EquationParseError> {
+        Err(error)
+    }
+}
+
+impl Report for Vec<Diagnostic> {
+    fn report(
+        &mut self,
+        error: EquationParseError,
+        span: Range<usize>,
+    ) -> Result<(), EquationParseError> {
+        // the parser reads an ambiguous bar as closing and keeps going, so it's only a warning
+        let severity = match error {
+            EquationParseError::AmbiguousAbs { .. } => Severity::Warning,
+            _ => Severity::Error,
+        };
+        self.push(Diagnostic {
+            severity,
+            span,
+            error,
+        });
+        Ok(())
+    }
+}
+
+/// Parses an equation like [`parse`](crate::parse), but keeps going after errors it can recover
+/// from, like a bad literal or a dangling operator, to report all of them at once.
+///
+/// Diagnostics are sorted by where they start, and the tree is only returned if none of them
+/// is a [`Severity::Error`].
+/// Errors that only show up while building the tree, like a function with the wrong number of
+/// arguments, have no precise position and span the whole equation.
+pub fn parse_diagnostics<'a, T: Numeric>(
+    equation: &'a str,
+    arena: &'a EntityArena<'a, T>,
+) -> (Option<Entity<'a, T>>, Vec<Diagnostic>) {
+    let mut diagnostics = Vec::new();
+
+    // unexpected characters are skipped, so the rest of the equation is still checked
+    let mut first = Vec::new();
+    for token in Tokens::new(equation) {
+        match token {
+            Ok(token) => first.push(token),
+            Err(error) => {
+                let span = error_span(equation, &error).unwrap_or(0..equation.len());
+                let _ = diagnostics.report(error, span);
+            }
+        }
+    }
+
+    let options = Parser::default();
+    let _ = validate_parens(&first[..], options.max_depth, &mut diagnostics);
+    let second = second_parse(&first[..], equation, &options, &mut diagnostics);
+
+    // stages report in their own order, but an editor wants them in reading order
+    diagnostics.sort_by_key(|diagnostic| diagnostic.span.start);
+
+    let errors = diagnostics
+        .iter()
+        .any(|diagnostic| diagnostic.severity == Severity::Error);
+    let tree = match second {
+        Ok(second) if !errors => options.build_tree(&second[..], arena),
+        _ => return (None, diagnostics),
+    };
+
+    match tree {
+        Ok(tree) => (Some(tree), diagnostics),
+        Err(error) => {
+            let _ = diagnostics.report(error, 0..equation.len());
+            (None, diagnostics)
+        }
+    }
+}
+
+/// Renders an error from parsing `equation` like rustc does, with the equation on the first line
+/// and the error below it, underlined with carets:
+///
+/// ```text
+///   2+1.2.3+x
+///     ^^^^^ Bad literal 1.2.3 at 2..7
+/// ```
+///
+/// Errors without a position, like [`EquationParseError::MissingRelation`], are rendered
+/// below the equation without carets.
+pub fn render_error(equation: &str, err: &EquationParseError) -> String {
+    // line breaks and tabs would shift the carets out of place
+    let line: String = equation
+        .chars()
+        .map(|value| match value.is_whitespace() {
+            true => ' ',
+            false => value,
+        })
+        .collect();
+
+    let mut rendered = String::new();
+    let _ = writeln!(rendered, "  {line}");
+    match error_span(equation, err) {
+        Some(span) => {
+            let column = equation[..span.start].chars().count();
+            let width = equation[span].chars().count().max(1);
+            let _ = write!(rendered, "  {:column$}{:^<width$} {err}", "", "");
+        }
+        None => {
+            let _ = write!(rendered, "  {err}");
+        }
+    }
+    rendered
+}
+
+/// The byte range of `equation` an error points at, if it has a position.
+fn error_span(equation: &str, err: &EquationParseError) -> Option<Range<usize>> {
+    let index = match *err {
+        // the error could be from another equation, so check the range fits this one
+        EquationParseError::LiteralParseError { start, end, .. } => {
+            equation.get(start..end)?;
+            return Some(start..end);
+        }
+        EquationParseError::UnbalancedParenthesis { index }
+        | EquationParseError::UnbalancedAbs { index }
+        | EquationParseError::AmbiguousAbs { index }
+        | EquationParseError::UnexpectedCharacter { index, .. }
+        | EquationParseError::DanglingOperator { index }
+        | EquationParseError::UnexpectedRelation { index }
+        | EquationParseError::UnsupportedOperator { index }
+        | EquationParseError::TooDeep { index }
+        | EquationParseError::UnsupportedBracket { index }
+        | EquationParseError::EmptyParentheses { index }
+        | EquationParseError::MalformedBinding { index }
+        | EquationParseError::DisallowedFunction { index, .. }
+        | EquationParseError::MissingOperator { index } => index,
+        _ => return None,
+    };
+
+    // the index is a byte offset of a character, underline all of it
+    let width = equation
+        .get(index..)?
+        .chars()
+        .next()
+        .map_or(0, char::len_utf8);
+    Some(index..index + width)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use alloc::format;
+
+    #[test]
+    fn reports_every_error() {
+        let arena = EntityArena::new();
+        let (tree, diagnostics) = parse_diagnostics::<f64>("1.2.3 + x * $ + 2 *", &arena);
+        assert!(tree.is_none());
+        assert_eq!(diagnostics.len(), 3, "{diagnostics:?}");
+        assert!(
+            diagnostics
+                .iter()
+                .all(|diagnostic| diagnostic.severity == Severity::Error)
+        );
+
+        assert_eq!(diagnostics[0].span, 0..5);
+        assert!(matches!(
+            &diagnostics[0].error,
+            EquationParseError::LiteralParseError { text, .. } if text == "1.2.3"
+        ));
+        assert_eq!(diagnostics[1].span, 12..13);
+        assert!(matches!(
+            diagnostics[1].error,
+            EquationParseError::UnexpectedCharacter { ch: '$', index: 12 }
+        ));
+        assert_eq!(diagnostics[2].span, 18..19);
+        assert!(matches!(
+            diagnostics[2].error,
+            EquationParseError::DanglingOperator { index: 18 }
+        ));
+    }
+
+    #[test]
+    fn returns_the_tree_with_warnings() {
+        let arena = EntityArena::new();
+        let (tree, diagnostics) = parse_diagnostics::<f64>("|a|b|c|", &arena);
+        assert_eq!(tree, Some(crate::parse("|a|*b*|c|", &arena).unwrap()));
+        assert_eq!(diagnostics.len(), 1, "{diagnostics:?}");
+        assert_eq!(diagnostics[0].severity, Severity::Warning);
+        assert_eq!(diagnostics[0].span, 2..3);
+        assert!(matches!(
+            diagnostics[0].error,
+            EquationParseError::AmbiguousAbs { index: 2 }
+        ));
+    }
+
+    #[test]
+    fn returns_the_tree_without_errors() {
+        let arena = EntityArena::new();
+        let (tree, diagnostics) = parse_diagnostics::<f64>("2*x", &arena);
+        assert!(diagnostics.is_empty());
+        assert_eq!(tree, Some(crate::parse("2*x", &arena).unwrap()));
+
+        let (tree, diagnostics) = parse_diagnostics::<f64>("sin(1, 2)", &arena);
+        assert!(tree.is_none());
+        assert_eq!(diagnostics[0].span, 0..9);
+    }
+
+    fn render(equation: &str) -> String {
+        render_error(equation, &crate::parse_tokens::<f64>(equation).unwrap_err())
+    }
+
+    #[test]
+    fn underlines_bad_literals() {
+        assert_eq!(
+            render("2+1.2.3+x"),
+            "  2+1.2.3+x\n    ^^^^^ Bad literal 1.2.3 at 2..7"
+        );
+    }
+
+    #[test]
+    fn points_at_unbalanced_parentheses() {
+        let equation = "(2+3))*x";
+        let err = crate::parse_tokens::<f64>(equation).unwrap_err();
+        assert!(matches!(
+            err,
+            EquationParseError::UnbalancedParenthesis { index: 5 }
+        ));
+        assert_eq!(
+            render_error(equation, &err),
+            format!("  (2+3))*x\n       ^ {err}")
+        );
+
+        // columns count characters, not bytes
+        let equation = "√(x";
+        let err = crate::parse_tokens::<f64>(equation).unwrap_err();
+        assert_eq!(render_error(equation, &err), format!("  √(x\n   ^ {err}"));
+    }
+
+    #[test]
+    fn renders_errors_from_other_equations_without_position() {
+        // a bad literal at 2..7
+        let err = crate::parse_tokens::<f64>("2+1.2.3+x").unwrap_err();
+        assert_eq!(render_error("√x", &err), format!("  √x\n  {err}"));
+        // 2 is inside the `√`
+        assert_eq!(
+            render_error("2√x+1234", &err),
+            format!("  2√x+1234\n  {err}")
+        );
+    }
+
+    #[test]
+    fn renders_errors_without_position_below() {
+        let err = EquationParseError::MissingRelation;
+        assert_eq!(render_error("x\t+ 1", &err), format!("  x + 1\n  {err}"));
+    }
+}
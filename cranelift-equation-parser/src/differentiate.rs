@@ -0,0 +1,397 @@
+use crate::arena::EntityArena;
+use crate::ast::{Entity, Function, Operation};
+
+use thiserror::Error;
+
+/// Symbolically differentiates `entity` with respect to the variable `var`,
+/// allocating the derivative's nodes in `arena`.
+///
+/// Subtrees that don't mention `var` are treated as constants. The result is correct but not
+/// tidied up, so `d/dx sin(x^2)` comes out as `cos(x^2)*(2*x^1)`; run it through
+/// [`simplify`](crate::simplify) to clean it up.
+pub fn differentiate<'a, T: num_traits::Float>(
+    entity: &Entity<'a, T>,
+    var: &str,
+    arena: &'a EntityArena<'a, T>,
+) -> Result<&'a Entity<'a, T>, DifferentiateError> {
+    Differentiator { var, arena }.derive(entity)
+}
+
+struct Differentiator<'a, 'v, T> {
+    var: &'v str,
+    arena: &'a EntityArena<'a, T>,
+}
+
+impl<'a, T: num_traits::Float> Differentiator<'a, '_, T> {
+    fn derive(&self, entity: &Entity<'a, T>) -> Result<&'a Entity<'a, T>, DifferentiateError> {
+        if !self.depends(entity) {
+            return Ok(self.lit(T::zero()));
+        }
+
+        match entity {
+            // only the variable itself depends on the variable
+            Entity::ValueLit(_) | Entity::Value(_) => Ok(self.lit(T::one())),
+            Entity::Operation(op) => self.operation(entity, op),
+            Entity::Function(func) => self.function(func),
+        }
+    }
+
+    fn operation(
+        &self,
+        entity: &Entity<'a, T>,
+        op: &Operation<'a, T>,
+    ) -> Result<&'a Entity<'a, T>, DifferentiateError> {
+        Ok(match *op {
+            Operation::Add(lhs, rhs) => self.add(self.derive(lhs)?, self.derive(rhs)?),
+            Operation::Sub(lhs, rhs) => self.sub(self.derive(lhs)?, self.derive(rhs)?),
+            Operation::Neg(x) => self.neg(self.derive(x)?),
+            Operation::Mul(lhs, rhs) => match (self.depends(lhs), self.depends(rhs)) {
+                (false, _) => self.mul(lhs, self.derive(rhs)?),
+                (_, false) => self.mul(self.derive(lhs)?, rhs),
+                _ => self.add(
+                    self.mul(self.derive(lhs)?, rhs),
+                    self.mul(lhs, self.derive(rhs)?),
+                ),
+            },
+            Operation::Div(lhs, rhs) if !self.depends(rhs) => self.div(self.derive(lhs)?, rhs),
+            Operation::Div(lhs, rhs) => self.div(
+                self.sub(
+                    self.mul(self.derive(lhs)?, rhs),
+                    self.mul(lhs, self.derive(rhs)?),
+                ),
+                self.pow(rhs, self.lit(T::one() + T::one())),
+            ),
+            Operation::Pow(base, exponent) => self.power(entity, base, exponent)?,
+            Operation::Rem(lhs, rhs) => self.remainder(lhs, rhs)?,
+            // piecewise constant, like `floor`
+            Operation::FloorDiv(..) => self.lit(T::zero()),
+            Operation::Factorial(_) => {
+                return Err(DifferentiateError::NotDifferentiable("factorial"));
+            }
+        })
+    }
+
+    /// `d/dx u^v`
+    fn power(
+        &self,
+        entity: &Entity<'a, T>,
+        base: &'a Entity<'a, T>,
+        exponent: &'a Entity<'a, T>,
+    ) -> Result<&'a Entity<'a, T>, DifferentiateError> {
+        if !self.depends(exponent) {
+            // v * u^(v-1) * u'
+            let reduced = match *exponent {
+                Entity::ValueLit(n) => self.lit(n - T::one()),
+                _ => self.sub(exponent, self.lit(T::one())),
+            };
+            let outer = self.mul(exponent, self.pow(base, reduced));
+            return Ok(self.chain(outer, self.derive(base)?));
+        }
+
+        let entity = self.arena.alloc(*entity);
+        let ln_base = self.func(Function::Ln(base));
+        if !self.depends(base) {
+            // u^v * ln(u) * v'
+            let outer = self.mul(entity, ln_base);
+            return Ok(self.chain(outer, self.derive(exponent)?));
+        }
+
+        // u^v * (v' * ln(u) + v * u' / u)
+        Ok(self.mul(
+            entity,
+            self.add(
+                self.mul(self.derive(exponent)?, ln_base),
+                self.div(self.mul(exponent, self.derive(base)?), base),
+            ),
+        ))
+    }
+
+    /// `d/dx u % v`. The remainder is `u - v * trunc(u / v)`, and `trunc` is flat wherever it's
+    /// differentiable, so this is `u' - v' * trunc(u / v)`.
+    fn remainder(
+        &self,
+        lhs: &'a Entity<'a, T>,
+        rhs: &'a Entity<'a, T>,
+    ) -> Result<&'a Entity<'a, T>, DifferentiateError> {
+        let dlhs = self.derive(lhs)?;
+        if !self.depends(rhs) {
+            return Ok(dlhs);
+        }
+
+        // trunc(w) = sign(w) * floor(|w|)
+        let quotient = self.div(lhs, rhs);
+        let truncated = self.mul(
+            self.func(Function::Signum(quotient)),
+            self.func(Function::Floor(self.func(Function::Abs(quotient)))),
+        );
+        Ok(self.sub(dlhs, self.mul(self.derive(rhs)?, truncated)))
+    }
+
+    fn function(&self, func: &Function<'a, T>) -> Result<&'a Entity<'a, T>, DifferentiateError> {
+        let one = || self.lit(T::one());
+        let two = || self.lit(T::one() + T::one());
+        let square = |x| self.pow(x, two());
+
+        let (outer, x) = match *func {
+            Function::Sin(x) => (self.func(Function::Cos(x)), x),
+            Function::Cos(x) => (self.neg(self.func(Function::Sin(x))), x),
+            Function::Tan(x) => (square(self.func(Function::Sec(x))), x),
+            Function::Cot(x) => (self.neg(square(self.func(Function::Csc(x)))), x),
+            Function::Sec(x) => (
+                self.mul(self.func(Function::Sec(x)), self.func(Function::Tan(x))),
+                x,
+            ),
+            Function::Csc(x) => (
+                self.neg(self.mul(self.func(Function::Csc(x)), self.func(Function::Cot(x)))),
+                x,
+            ),
+            Function::Asin(x) => (
+                self.div(one(), self.func(Function::Sqrt(self.sub(one(), square(x))))),
+                x,
+            ),
+            Function::Acos(x) => (
+                self.neg(self.div(one(), self.func(Function::Sqrt(self.sub(one(), square(x)))))),
+                x,
+            ),
+            Function::Atan(x) => (self.div(one(), self.add(one(), square(x))), x),
+            Function::Atan2(y, x) => {
+                // (x * y' - y * x') / (x^2 + y^2)
+                return Ok(self.div(
+                    self.sub(self.mul(x, self.derive(y)?), self.mul(y, self.derive(x)?)),
+                    self.add(square(x), square(y)),
+                ));
+            }
+            Function::Sinh(x) => (self.func(Function::Cosh(x)), x),
+            Function::Cosh(x) => (self.func(Function::Sinh(x)), x),
+            Function::Tanh(x) => (square(self.func(Function::Sech(x))), x),
+            Function::Coth(x) => (self.neg(square(self.func(Function::Csch(x)))), x),
+            Function::Sech(x) => (
+                self.neg(self.mul(self.func(Function::Sech(x)), self.func(Function::Tanh(x)))),
+                x,
+            ),
+            Function::Csch(x) => (
+                self.neg(self.mul(self.func(Function::Csch(x)), self.func(Function::Coth(x)))),
+                x,
+            ),
+            Function::Asinh(x) => (
+                self.div(one(), self.func(Function::Sqrt(self.add(square(x), one())))),
+                x,
+            ),
+            Function::Acosh(x) => (
+                self.div(one(), self.func(Function::Sqrt(self.sub(square(x), one())))),
+                x,
+            ),
+            Function::Atanh(x) => (self.div(one(), self.sub(one(), square(x))), x),
+
+            Function::Log(base, x) if !self.depends(base) => (
+                self.div(one(), self.mul(x, self.func(Function::Ln(base)))),
+                x,
+            ),
+            // log(b, x) = ln(x) / ln(b)
+            Function::Log(base, x) => {
+                return self.derive(&Entity::Operation(Operation::Div(
+                    self.func(Function::Ln(x)),
+                    self.func(Function::Ln(base)),
+                )));
+            }
+            Function::Log10(x) => {
+                let ten = self.lit(T::from(10).unwrap());
+                (
+                    self.div(one(), self.mul(x, self.func(Function::Ln(ten)))),
+                    x,
+                )
+            }
+            Function::Log2(x) => (
+                self.div(one(), self.mul(x, self.func(Function::Ln(two())))),
+                x,
+            ),
+            Function::Ln(x) => (self.div(one(), x), x),
+
+            Function::Sqrt(x) => (
+                self.div(one(), self.mul(two(), self.func(Function::Sqrt(x)))),
+                x,
+            ),
+            // root(n, x) = x^(1/n)
+            Function::Root(n, x) => {
+                return self.derive(&Entity::Operation(Operation::Pow(x, self.div(one(), n))));
+            }
+            Function::Hypot(a, b) => {
+                // (a * a' + b * b') / hypot(a, b)
+                return Ok(self.div(
+                    self.add(self.mul(a, self.derive(a)?), self.mul(b, self.derive(b)?)),
+                    self.func(Function::Hypot(a, b)),
+                ));
+            }
+
+            Function::Exp(x) => (self.func(Function::Exp(x)), x),
+            Function::Exp2(x) => (
+                self.mul(self.func(Function::Exp2(x)), self.func(Function::Ln(two()))),
+                x,
+            ),
+            Function::Exp10(x) => {
+                let ten = self.lit(T::from(10).unwrap());
+                (
+                    self.mul(self.func(Function::Exp10(x)), self.func(Function::Ln(ten))),
+                    x,
+                )
+            }
+            Function::Mod(lhs, rhs) => return self.remainder(lhs, rhs),
+
+            // piecewise constant, so flat wherever they're differentiable
+            Function::Ceil(_)
+            | Function::Floor(_)
+            | Function::Round(_)
+            | Function::Trunc(_)
+            | Function::Signum(_)
+            | Function::Gcd(..)
+            | Function::Lcm(..) => {
+                return Ok(self.lit(T::zero()));
+            }
+            // their derivatives need the digamma function
+            Function::Gamma(_) => return Err(DifferentiateError::NotDifferentiable("gamma")),
+            Function::Lgamma(_) => return Err(DifferentiateError::NotDifferentiable("lgamma")),
+            // fract(x) = x - trunc(x)
+            Function::Fract(x) => (one(), x),
+            Function::Abs(x) => (self.func(Function::Signum(x)), x),
+            // min(a, b) = (a + b - |a - b|) / 2, max(a, b) = (a + b + |a - b|) / 2
+            Function::Min(a, b) | Function::Max(a, b) => {
+                let (da, db) = (self.derive(a)?, self.derive(b)?);
+                let jump = self.mul(
+                    self.func(Function::Signum(self.sub(a, b))),
+                    self.sub(da, db),
+                );
+                let sum = match func {
+                    Function::Min(..) => self.sub(self.add(da, db), jump),
+                    _ => self.add(self.add(da, db), jump),
+                };
+                return Ok(self.div(sum, two()));
+            }
+            // clamp(x, lo, hi) = min(max(x, lo), hi)
+            Function::Clamp(x, lo, hi) => {
+                let inner = self.func(Function::Max(x, lo));
+                return self.derive(&Entity::Function(Function::Min(inner, hi)));
+            }
+        };
+
+        Ok(self.chain(outer, self.derive(x)?))
+    }
+
+    /// Whether `entity` mentions the variable we're differentiating by.
+    fn depends(&self, entity: &Entity<'a, T>) -> bool {
+        match entity {
+            Entity::ValueLit(_) => false,
+            Entity::Value(name) => *name == self.var,
+            _ => entity
+                .children()
+                .into_iter()
+                .any(|child| self.depends(child)),
+        }
+    }
+
+    /// The chain rule: `outer * inner`, leaving out a factor of exactly one.
+    fn chain(&self, outer: &'a Entity<'a, T>, inner: &'a Entity<'a, T>) -> &'a Entity<'a, T> {
+        match *inner {
+            Entity::ValueLit(val) if val == T::one() => outer,
+            _ => self.mul(outer, inner),
+        }
+    }
+
+    fn lit(&self, val: T) -> &'a Entity<'a, T> {
+        self.arena.alloc(Entity::ValueLit(val))
+    }
+
+    fn func(&self, func: Function<'a, T>) -> &'a Entity<'a, T> {
+        self.arena.alloc(Entity::Function(func))
+    }
+
+    fn op(&self, op: Operation<'a, T>) -> &'a Entity<'a, T> {
+        self.arena.alloc(Entity::Operation(op))
+    }
+
+    fn add(&self, lhs: &'a Entity<'a, T>, rhs: &'a Entity<'a, T>) -> &'a Entity<'a, T> {
+        self.op(Operation::Add(lhs, rhs))
+    }
+
+    fn sub(&self, lhs: &'a Entity<'a, T>, rhs: &'a Entity<'a, T>) -> &'a Entity<'a, T> {
+        self.op(Operation::Sub(lhs, rhs))
+    }
+
+    fn mul(&self, lhs: &'a Entity<'a, T>, rhs: &'a Entity<'a, T>) -> &'a Entity<'a, T> {
+        self.op(Operation::Mul(lhs, rhs))
+    }
+
+    fn div(&self, lhs: &'a Entity<'a, T>, rhs: &'a Entity<'a, T>) -> &'a Entity<'a, T> {
+        self.op(Operation::Div(lhs, rhs))
+    }
+
+    fn pow(&self, lhs: &'a Entity<'a, T>, rhs: &'a Entity<'a, T>) -> &'a Entity<'a, T> {
+        self.op(Operation::Pow(lhs, rhs))
+    }
+
+    fn neg(&self, x: &'a Entity<'a, T>) -> &'a Entity<'a, T> {
+        self.op(Operation::Neg(x))
+    }
+}
+
+#[derive(Debug, Clone, Error)]
+pub enum DifferentiateError {
+    #[error("Can't differentiate {0} symbolically")]
+    NotDifferentiable(&'static str),
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{eval, parse};
+
+    /// The derivative of `equation` by `x`, at `x`.
+    fn slope(equation: &str, x: f64) -> f64 {
+        let arena = EntityArena::new();
+        let entity = parse(equation, &arena).unwrap();
+        let derivative = differentiate(&entity, "x", &arena).unwrap();
+        eval(derivative, &[("x", x)][..]).unwrap()
+    }
+
+    #[test]
+    fn differentiates_polynomials() {
+        assert_eq!(slope("3x^2 + 2x + 1", 2.0), 14.0);
+        assert_eq!(slope("x^3 - x", 1.0), 2.0);
+        assert_eq!(slope("(x+1)(x-1)", 3.0), 6.0);
+        assert_eq!(slope("1/x", 2.0), -0.25);
+        assert_eq!(slope("7", 2.0), 0.0);
+    }
+
+    #[test]
+    fn differentiates_functions() {
+        assert_eq!(slope("sin(x)", 0.0), 1.0);
+        assert!((slope("exp(x)", 1.0) - core::f64::consts::E).abs() < 1e-12);
+        assert_eq!(slope("exp(2x)", 0.0), 2.0);
+        assert_eq!(slope("ln(x)", 4.0), 0.25);
+    }
+
+    #[test]
+    fn applies_the_chain_rule() {
+        let arena = EntityArena::new();
+        let entity = parse::<f64>("sin(x^2)", &arena).unwrap();
+        assert_eq!(
+            *differentiate(&entity, "x", &arena).unwrap(),
+            parse("cos(x^2)*(2*x^1)", &arena).unwrap()
+        );
+        assert_eq!(slope("sin(x^2)", 0.0), 0.0);
+    }
+
+    #[test]
+    fn treats_other_variables_as_constants() {
+        let arena = EntityArena::new();
+        let entity = parse::<f64>("y^2", &arena).unwrap();
+        assert_eq!(
+            *differentiate(&entity, "x", &arena).unwrap(),
+            Entity::ValueLit(0.0)
+        );
+        let entity = parse::<f64>("x!", &arena).unwrap();
+        assert!(matches!(
+            differentiate(&entity, "x", &arena),
+            Err(DifferentiateError::NotDifferentiable("factorial"))
+        ));
+    }
+}
@@ -0,0 +1,75 @@
+use crate::ast::Entity;
+
+use core::mem::discriminant;
+use num_traits::Signed;
+
+/// Whether two [`Entity`] trees have the same structure, with literals that differ by at most
+/// `epsilon`, so a simplified `2.0+3.0` equals a literal `5.0` despite rounding.
+/// The derived [`PartialEq`] compares literals exactly, which is too strict after transformations.
+///
+/// Trees have to match node for node: `a+b` doesn't equal `b+a`, and `x*2` doesn't equal `2*x`.
+/// Infinite literals only equal the same infinity, and `NaN` equals nothing.
+pub fn entities_equal<T: Signed + PartialOrd + Copy>(
+    a: &Entity<'_, T>,
+    b: &Entity<'_, T>,
+    epsilon: T,
+) -> bool {
+    let same_node = match (a, b) {
+        (Entity::ValueLit(a), Entity::ValueLit(b)) => {
+            return *a == *b || (*a - *b).abs() <= epsilon;
+        }
+        (Entity::Value(a), Entity::Value(b)) => return a == b,
+        (Entity::Operation(a), Entity::Operation(b)) => discriminant(a) == discriminant(b),
+        // `log(x)` and `log(10, x)` are both `log`, but differ in their number of children
+        (Entity::Function(a), Entity::Function(b)) => a.function_type() == b.function_type(),
+        _ => false,
+    };
+
+    let (a, b) = (a.children(), b.children());
+    same_node && a.len() == b.len() && a.iter().zip(&b).all(|(a, b)| entities_equal(a, b, epsilon))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{EntityArena, parse, simplify};
+
+    #[test]
+    fn compares_literals_within_epsilon() {
+        let arena = EntityArena::new();
+        let sum = simplify(&parse::<f64>("0.1+0.2", &arena).unwrap(), &arena);
+        let literal = Entity::ValueLit(0.3);
+        assert_ne!(*sum, literal);
+        assert!(entities_equal(sum, &literal, 1e-12));
+        assert!(!entities_equal(sum, &literal, 0.0));
+
+        let sum = simplify(&parse::<f64>("2.0+3.0", &arena).unwrap(), &arena);
+        assert!(entities_equal(sum, &Entity::ValueLit(5.0), 1e-12));
+        assert!(!entities_equal(sum, &Entity::ValueLit(5.1), 1e-12));
+    }
+
+    #[test]
+    fn compares_structure() {
+        let arena = EntityArena::new();
+        let equal = |a, b| {
+            entities_equal(
+                &parse::<f64>(a, &arena).unwrap(),
+                &parse::<f64>(b, &arena).unwrap(),
+                1e-9,
+            )
+        };
+        assert!(equal("sin(x)*2", "sin(x)*2.0000000001"));
+        assert!(!equal("a+b", "b+a"));
+        assert!(!equal("x*2", "2*x"));
+        assert!(!equal("sin(x)", "cos(x)"));
+        assert!(!equal("x+1", "x-1"));
+        assert!(!equal("log(x)", "log(10, x)"));
+        assert!(equal("1/0", "1/0"));
+
+        let nan = Entity::ValueLit(f64::NAN);
+        assert!(!entities_equal(&nan, &nan, 1.0));
+        let inf = Entity::ValueLit(f64::INFINITY);
+        assert!(entities_equal(&inf, &inf, 0.0));
+        assert!(!entities_equal(&inf, &Entity::ValueLit(f64::MAX), f64::MAX));
+    }
+}
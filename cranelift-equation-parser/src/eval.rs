@@ -0,0 +1,835 @@
+use crate::ast::{Entity, FunctionType, Operation, Operator};
+
+use alloc::collections::BTreeMap;
+use alloc::string::{String, ToString};
+use alloc::vec::Vec;
+use core::convert::Infallible;
+use core::marker::PhantomData;
+use num_traits::FloatConst;
+use thiserror::Error;
+
+#[derive(Debug, Clone, Copy)]
+/// Knobs for [`eval_with_options`].
+pub struct EvalOptions {
+    /// Resolve well-known constants like `pi` (see [`known_constant`]) when they aren't in the
+    /// variable map. Disable this to treat every identifier, including `e`, as a free variable.
+    pub constants: bool,
+    /// Fail with [`EvalError::NotFinite`] as soon as any intermediate result is infinite or NaN,
+    /// like `1/0` or `sqrt(-1)`, instead of letting it propagate. See [`eval_checked`].
+    pub require_finite: bool,
+    /// Work in degrees: `sin`, `cos`, `tan`, `cot`, `sec` and `csc` take their argument in degrees,
+    /// and `asin`, `acos`, `atan` and `atan2` return degrees. See [`eval_degrees`].
+    pub degrees: bool,
+}
+
+impl Default for EvalOptions {
+    fn default() -> Self {
+        Self {
+            constants: true,
+            require_finite: false,
+            degrees: false,
+        }
+    }
+}
+
+/// A source of variable values for [`eval`].
+/// Implemented for `HashMap` (with the `std` feature), `BTreeMap`, and slices of `(name, value)` pairs.
+pub trait Variables<T> {
+    fn get(&self, name: &str) -> Option<T>;
+}
+
+#[cfg(feature = "std")]
+impl<T: Copy, S: core::hash::BuildHasher> Variables<T> for std::collections::HashMap<&str, T, S> {
+    fn get(&self, name: &str) -> Option<T> {
+        std::collections::HashMap::get(self, name).copied()
+    }
+}
+
+impl<T: Copy> Variables<T> for BTreeMap<&str, T> {
+    fn get(&self, name: &str) -> Option<T> {
+        BTreeMap::get(self, name).copied()
+    }
+}
+
+impl<T: Copy> Variables<T> for [(&str, T)] {
+    fn get(&self, name: &str) -> Option<T> {
+        self.iter()
+            .find(|(key, _)| *key == name)
+            .map(|(_, val)| *val)
+    }
+}
+
+/// The value of a well-known constant: `pi`, `tau` or `e`.
+pub fn known_constant<T: num_traits::Float + FloatConst>(name: &str) -> Option<T> {
+    match name {
+        "pi" => Some(T::PI()),
+        "tau" => Some(T::TAU()),
+        "e" => Some(T::E()),
+        _ => None,
+    }
+}
+
+/// Whether `name` is one of the constants [`known_constant`] knows. Unlike it, this works
+/// for any `T`, even rationals, which can't represent them.
+pub(crate) fn is_known_constant(name: &str) -> bool {
+    matches!(name, "pi" | "tau" | "e")
+}
+
+/// The distinct variables in an [`Entity`] tree, in order of first appearance,
+/// so `a*x+b` gives `["a", "x", "b"]`. These are the values [`eval`] needs:
+/// well-known constants like `pi` (see [`known_constant`]) are left out.
+pub fn variables<'a, T: num_traits::Float + FloatConst>(entity: &Entity<'a, T>) -> Vec<&'a str> {
+    let mut names = Vec::new();
+    // leaves are folded left to right, so names come in the order they're written
+    let Ok(()) = fold(entity, |entity, _| {
+        if let Entity::Value(name) = entity
+            && known_constant::<T>(name).is_none()
+            && !names.contains(name)
+        {
+            names.push(*name);
+        }
+        Ok::<_, Infallible>(())
+    });
+    names
+}
+
+/// Whether an [`Entity`] tree has no variables, only literals and well-known constants like `pi`,
+/// so `2+3*pi` is constant but `2+x` isn't. A constant tree evaluates to the same value with any
+/// variables, so it can be evaluated once ahead of time.
+pub fn is_constant<T: num_traits::Float + FloatConst>(entity: &Entity<'_, T>) -> bool {
+    // the first variable stops the fold
+    fold(entity, |entity, _| match entity {
+        Entity::Value(name) if known_constant::<T>(name).is_none() => Err(()),
+        _ => Ok(()),
+    })
+    .is_ok()
+}
+
+/// Evaluates an [`Entity`] tree, looking up every [`Entity::Value`] in `vars`.
+/// Well-known constants like `pi` are resolved unless `vars` shadows them.
+pub fn eval<T: num_traits::Float + FloatConst, V: Variables<T> + ?Sized>(
+    entity: &Entity<'_, T>,
+    vars: &V,
+) -> Result<T, EvalError> {
+    eval_with_options(entity, vars, &EvalOptions::default())
+}
+
+/// Evaluates an [`Entity`] tree like [`eval`], but fails with [`EvalError::NotFinite`]
+/// if any intermediate result is infinite or NaN, like `1/0`, `sqrt(-1)` or `log(-1)`.
+pub fn eval_checked<T: num_traits::Float + FloatConst, V: Variables<T> + ?Sized>(
+    entity: &Entity<'_, T>,
+    vars: &V,
+) -> Result<T, EvalError> {
+    let options = EvalOptions {
+        require_finite: true,
+        ..EvalOptions::default()
+    };
+    eval_with_options(entity, vars, &options)
+}
+
+/// Evaluates an [`Entity`] tree like [`eval`], but with angles in degrees, like a calculator
+/// in degree mode: `sin(90)` is 1 and `asin(1)` is 90. Hyperbolic functions are unaffected.
+pub fn eval_degrees<T: num_traits::Float + FloatConst, V: Variables<T> + ?Sized>(
+    entity: &Entity<'_, T>,
+    vars: &V,
+) -> Result<T, EvalError> {
+    let options = EvalOptions {
+        degrees: true,
+        ..EvalOptions::default()
+    };
+    eval_with_options(entity, vars, &options)
+}
+
+/// Evaluates an [`Entity`] tree like [`eval`], with custom [`EvalOptions`].
+pub fn eval_with_options<T: num_traits::Float + FloatConst, V: Variables<T> + ?Sized>(
+    entity: &Entity<'_, T>,
+    vars: &V,
+    options: &EvalOptions,
+) -> Result<T, EvalError> {
+    let context = Context {
+        vars,
+        options,
+        _value: PhantomData,
+    };
+    fold(entity, |entity, args| context.node(entity, args))
+}
+
+/// Folds an [`Entity`] tree bottom-up: `node` is called on every node, children first,
+/// with the values it returned for its children. The tree is walked with a stack on the heap
+/// rather than by recursion, so no tree is too deep, like the long sums of `1+2+3+…`
+/// or trees built by substituting into each other over and over.
+pub fn fold<'a, T, R, E>(
+    root: &Entity<'a, T>,
+    mut node: impl FnMut(&Entity<'a, T>, &[R]) -> Result<R, E>,
+) -> Result<R, E> {
+    // every node is entered to queue its children, and left once they all have a value
+    enum Step<'r, 'a, T> {
+        Enter(&'r Entity<'a, T>),
+        Leave(&'r Entity<'a, T>, usize),
+    }
+
+    let mut steps = Vec::from([Step::Enter(root)]);
+    let mut values: Vec<R> = Vec::new();
+    while let Some(step) = steps.pop() {
+        match step {
+            Step::Enter(entity) => {
+                let children = entity.children();
+                steps.push(Step::Leave(entity, children.len()));
+                // popped from the end, so the leftmost child goes last
+                steps.extend(children.into_iter().rev().map(Step::Enter));
+            }
+            Step::Leave(entity, children) => {
+                let start = values.len() - children;
+                let value = node(entity, &values[start..])?;
+                values.truncate(start);
+                values.push(value);
+            }
+        }
+    }
+
+    Ok(values.pop().expect("the root has a value"))
+}
+
+struct Context<'v, T, V: ?Sized> {
+    vars: &'v V,
+    options: &'v EvalOptions,
+    _value: PhantomData<T>,
+}
+
+impl<T: num_traits::Float + FloatConst, V: Variables<T> + ?Sized> Context<'_, T, V> {
+    /// The value of `entity`, given the values of its children in `args`.
+    fn node(&self, entity: &Entity<'_, T>, args: &[T]) -> Result<T, EvalError> {
+        let val = match entity {
+            Entity::ValueLit(val) => *val,
+            Entity::Value(name) => self.value(name)?,
+            Entity::Operation(Operation::Pow(_, exponent))
+                if let Some(n) = integer_exponent(exponent) =>
+            {
+                args[0].powi(n)
+            }
+            Entity::Operation(op) => apply_operator(op.operator(), args),
+            Entity::Function(func) => self.function(func.function_type(), args),
+        };
+
+        match self.options.require_finite && !val.is_finite() {
+            true => Err(EvalError::NotFinite),
+            false => Ok(val),
+        }
+    }
+
+    fn value(&self, name: &str) -> Result<T, EvalError> {
+        let constant = || match self.options.constants {
+            true => known_constant(name),
+            false => None,
+        };
+
+        self.vars
+            .get(name)
+            .or_else(constant)
+            .ok_or_else(|| EvalError::UndefinedVariable(name.to_string()))
+    }
+
+    fn function(&self, function: FunctionType, args: &[T]) -> T {
+        if !self.options.degrees {
+            return apply_function(function, args);
+        }
+
+        match function {
+            FunctionType::Sin
+            | FunctionType::Cos
+            | FunctionType::Tan
+            | FunctionType::Cot
+            | FunctionType::Sec
+            | FunctionType::Csc => apply_function(function, &[args[0].to_radians()]),
+            FunctionType::Asin | FunctionType::Acos | FunctionType::Atan | FunctionType::Atan2 => {
+                apply_function(function, args).to_degrees()
+            }
+            _ => apply_function(function, args),
+        }
+    }
+}
+
+/// Applies `op` to the already evaluated values of its operands.
+pub(crate) fn apply_operator<T: num_traits::Float + FloatConst>(op: Operator, args: &[T]) -> T {
+    match (op, args) {
+        (Operator::Add, &[lhs, rhs]) => lhs + rhs,
+        (Operator::Sub, &[lhs, rhs]) => lhs - rhs,
+        (Operator::Mul, &[lhs, rhs]) => lhs * rhs,
+        (Operator::Div, &[lhs, rhs]) => lhs / rhs,
+        (Operator::FloorDiv, &[lhs, rhs]) => (lhs / rhs).floor(),
+        (Operator::Pow, &[lhs, rhs]) => lhs.powf(rhs),
+        (Operator::Rem, &[lhs, rhs]) => lhs % rhs,
+        (Operator::Neg, &[x]) => -x,
+        (Operator::Factorial, &[x]) => factorial(x),
+        _ => unreachable!("an operation has a value for each of its operands"),
+    }
+}
+
+/// The exponent of a power if it's an integer literal, like the `3` in `x^3` or the `-2` in
+/// `x^-2`. These are raised with `powi`, which is exact where `powf` can be off by an ulp.
+pub fn integer_exponent<T: num_traits::Float>(exponent: &Entity<'_, T>) -> Option<i32> {
+    let (val, sign) = match exponent {
+        Entity::ValueLit(val) => (*val, 1),
+        Entity::Operation(Operation::Neg(Entity::ValueLit(val))) => (*val, -1),
+        _ => return None,
+    };
+
+    match val.fract().is_zero() {
+        true => val.to_i32().map(|n| sign * n),
+        false => None,
+    }
+}
+
+/// Applies `function` to already evaluated arguments.
+/// `log` with a single argument is the base 10 logarithm, like in [`Function::from_arguments`](crate::Function::from_arguments).
+pub(crate) fn apply_function<T: num_traits::Float + FloatConst>(
+    function: FunctionType,
+    args: &[T],
+) -> T {
+    match (function, args) {
+        (FunctionType::Sin, &[x]) => x.sin(),
+        (FunctionType::Cos, &[x]) => x.cos(),
+        (FunctionType::Tan, &[x]) => x.tan(),
+        (FunctionType::Cot, &[x]) => x.tan().recip(),
+        (FunctionType::Sec, &[x]) => x.cos().recip(),
+        (FunctionType::Csc, &[x]) => x.sin().recip(),
+        (FunctionType::Asin, &[x]) => x.asin(),
+        (FunctionType::Acos, &[x]) => x.acos(),
+        (FunctionType::Atan, &[x]) => x.atan(),
+        (FunctionType::Atan2, &[y, x]) => y.atan2(x),
+        (FunctionType::Sinh, &[x]) => x.sinh(),
+        (FunctionType::Cosh, &[x]) => x.cosh(),
+        (FunctionType::Tanh, &[x]) => x.tanh(),
+        (FunctionType::Coth, &[x]) => x.tanh().recip(),
+        (FunctionType::Sech, &[x]) => x.cosh().recip(),
+        (FunctionType::Csch, &[x]) => x.sinh().recip(),
+        (FunctionType::Asinh, &[x]) => x.asinh(),
+        (FunctionType::Acosh, &[x]) => x.acosh(),
+        (FunctionType::Atanh, &[x]) => x.atanh(),
+
+        (FunctionType::Log, &[base, x]) => x.log(base),
+        (FunctionType::Log, &[x]) => x.log10(),
+        (FunctionType::Ln, &[x]) => x.ln(),
+        (FunctionType::Log2, &[x]) => x.log2(),
+
+        (FunctionType::Sqrt, &[x]) => x.sqrt(),
+        (FunctionType::Root, &[n, x]) => root(n, x),
+        (FunctionType::Hypot, &[x, y]) => x.hypot(y),
+
+        (FunctionType::Exp, &[x]) => x.exp(),
+        (FunctionType::Exp2, &[x]) => x.exp2(),
+        (FunctionType::Exp10, &[x]) => T::from(10).unwrap().powf(x),
+        (FunctionType::Mod, &[x, y]) => x % y,
+        (FunctionType::Gcd, &[x, y]) => gcd(x, y),
+        (FunctionType::Lcm, &[x, y]) => lcm(x, y),
+        // exact for small positive integers, like `factorial`
+        (FunctionType::Gamma, &[x]) if x > T::zero() && x.fract().is_zero() => {
+            factorial(x - T::one())
+        }
+        (FunctionType::Gamma, &[x]) => gamma(x),
+        (FunctionType::Lgamma, &[x]) => ln_gamma(x),
+
+        (FunctionType::Ceil, &[x]) => x.ceil(),
+        (FunctionType::Floor, &[x]) => x.floor(),
+        (FunctionType::Round, &[x]) => x.round(),
+        (FunctionType::Trunc, &[x]) => x.trunc(),
+        (FunctionType::Fract, &[x]) => x.fract(),
+        (FunctionType::Abs, &[x]) => x.abs(),
+        (FunctionType::Signum, &[x]) => signum(x),
+        (FunctionType::Min, &[x, y]) => x.min(y),
+        (FunctionType::Max, &[x, y]) => x.max(y),
+        (FunctionType::Clamp, &[x, lo, hi]) => x.max(lo).min(hi),
+        _ => unreachable!("{function} takes {} arguments", function.arity()),
+    }
+}
+
+/// `n!`, computed exactly for non-negative integers and as `gamma(n + 1)` otherwise.
+pub(crate) fn factorial<T: num_traits::Float + FloatConst>(n: T) -> T {
+    if n >= T::zero() && n.fract().is_zero() {
+        let mut result = T::one();
+        let mut i = T::one() + T::one();
+        // stops at the overflow, like after 170! for an f64, however wide `T` is
+        while i <= n && result.is_finite() {
+            result = result * i;
+            i = i + T::one();
+        }
+        return result;
+    }
+
+    gamma(n + T::one())
+}
+
+/// The gamma function, using the Lanczos approximation.
+fn gamma<T: num_traits::Float + FloatConst>(x: T) -> T {
+    // poles, where the reflection formula would only be off by rounding
+    if x < T::zero() && x.fract().is_zero() {
+        return T::nan();
+    }
+    if x < T::from(0.5).unwrap() {
+        // reflection formula
+        return T::PI() / ((T::PI() * x).sin() * gamma(T::one() - x));
+    }
+
+    // past where it overflows, t^(x + 1/2) would too, and make inf * 0 = NaN below
+    if ln_gamma(x) > T::max_value().ln() {
+        return T::infinity();
+    }
+
+    let (x, t, sum) = lanczos(x);
+    // half of the power at a time, so it doesn't overflow before e^-t brings it back down
+    let power = t.powf((x + T::from(0.5).unwrap()) / (T::one() + T::one()));
+    T::TAU().sqrt() * sum * (power * (-t).exp()) * power
+}
+
+/// The natural logarithm of the absolute value of the gamma function,
+/// which stays finite where the gamma function itself overflows, like for `x > 171`.
+fn ln_gamma<T: num_traits::Float + FloatConst>(x: T) -> T {
+    let half = T::from(0.5).unwrap();
+    if x <= T::zero() && x.fract().is_zero() {
+        return T::infinity();
+    }
+    if x < half {
+        // reflection formula
+        return (T::PI() / (T::PI() * x).sin().abs()).ln() - ln_gamma(T::one() - x);
+    }
+
+    let (x, t, sum) = lanczos(x);
+    half * T::TAU().ln() + (x + half) * t.ln() - t + sum.ln()
+}
+
+/// The Lanczos approximation of `gamma(x)` for `x >= 0.5`, as `(x - 1, t, sum)`,
+/// where `gamma(x) = sqrt(2pi) * t^(x - 1/2) * e^-t * sum`.
+/// Its coefficients are `f64`s, so it's only that precise, even for a wider `T`.
+fn lanczos<T: num_traits::Float>(x: T) -> (T, T, T) {
+    const G: f64 = 7.0;
+    const COEFFICIENTS: [f64; 9] = [
+        0.999_999_999_999_809_9,
+        676.520_368_121_885_1,
+        -1_259.139_216_722_402_8,
+        771.323_428_777_653_1,
+        -176.615_029_162_140_6,
+        12.507_343_278_686_905,
+        -0.138_571_095_265_720_12,
+        9.984_369_578_019_572e-6,
+        1.505_632_735_149_311_6e-7,
+    ];
+    let c = |val: f64| T::from(val).unwrap();
+
+    let x = x - T::one();
+    let t = x + c(G + 0.5);
+    let sum = COEFFICIENTS
+        .iter()
+        .enumerate()
+        .skip(1)
+        .fold(c(COEFFICIENTS[0]), |sum, (i, coefficient)| {
+            sum + c(*coefficient) / (x + c(i as f64))
+        });
+
+    (x, t, sum)
+}
+
+/// The greatest common divisor of `a` and `b`, after rounding them to the nearest integers.
+/// Infinite or NaN arguments give NaN.
+fn gcd<T: num_traits::Float>(a: T, b: T) -> T {
+    if !a.is_finite() || !b.is_finite() {
+        return T::nan();
+    }
+
+    // floats are exact integers up to 2^53, so Euclid's algorithm works on them directly
+    let (mut a, mut b) = (a.round().abs(), b.round().abs());
+    while !b.is_zero() {
+        (a, b) = (b, a % b);
+    }
+    a
+}
+
+/// The least common multiple of `a` and `b`, after rounding them to the nearest integers.
+fn lcm<T: num_traits::Float>(a: T, b: T) -> T {
+    let divisor = gcd(a, b);
+    if divisor.is_zero() {
+        return divisor;
+    }
+    (a.round() / divisor * b.round()).abs()
+}
+
+/// The sign of `x`, with `sign(±0) = ±0` instead of `±1` like [`num_traits::Float::signum`].
+fn signum<T: num_traits::Float>(x: T) -> T {
+    if x.is_zero() { x } else { x.signum() }
+}
+
+/// The `n`th root of `x`. Odd roots of negative numbers are real, so they're handled explicitly
+/// instead of letting `powf` return NaN.
+fn root<T: num_traits::Float>(n: T, x: T) -> T {
+    let two = T::one() + T::one();
+    if x < T::zero() && n.fract().is_zero() && !(n % two).is_zero() {
+        -(-x).powf(n.recip())
+    } else {
+        x.powf(n.recip())
+    }
+}
+
+#[derive(Debug, Clone, Error)]
+pub enum EvalError {
+    #[error("Undefined variable {0}")]
+    UndefinedVariable(String),
+    #[error("Result is not finite")]
+    NotFinite,
+    /// Only from `eval_rational`, with the `rational` feature: the result isn't a rational
+    /// number, like `sqrt(2)` or `pi`, or it overflows.
+    #[error("Result is not an exact rational number")]
+    Inexact,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ast::Function;
+    use crate::{EntityArena, compile, eval_batch, parse};
+
+    /// `1+1+…+1`, `depth - 1` additions deep.
+    fn deep_sum<'a>(arena: &'a EntityArena<'a, f64>, depth: usize) -> Entity<'a, f64> {
+        let one = arena.alloc(Entity::ValueLit(1.0));
+        let mut sum = Entity::ValueLit(1.0);
+        for _ in 1..depth {
+            sum = Entity::Operation(Operation::Add(arena.alloc(sum), one));
+        }
+        sum
+    }
+
+    #[test]
+    fn evaluates_arithmetic() {
+        let arena = EntityArena::new();
+        let entity = parse("x*x+1", &arena).unwrap();
+        assert_eq!(eval(&entity, &[("x", 3.0)][..]).unwrap(), 10.0);
+
+        let entity = parse("2^-2 + 7 // 2 + 7 % 4 + 4!", &arena).unwrap();
+        assert_eq!(eval(&entity, &[][..]).unwrap(), 0.25 + 3.0 + 3.0 + 24.0);
+    }
+
+    #[test]
+    fn evaluates_trees_of_any_depth() {
+        let equation = ["1"; 1200].join("+");
+        let arena = EntityArena::new();
+        let flat = parse(&equation, &arena).unwrap();
+        assert_eq!(eval(&flat, &[][..]).unwrap(), 1200.0);
+
+        let deep = deep_sum(&arena, 100_000);
+        assert_eq!(eval(&deep, &[][..]).unwrap(), 100_000.0);
+
+        let mut negated = Entity::ValueLit(2.0);
+        for _ in 0..100_000 {
+            negated = Entity::Operation(Operation::Neg(arena.alloc(negated)));
+        }
+        assert_eq!(eval(&negated, &[][..]).unwrap(), 2.0);
+    }
+
+    #[test]
+    fn compiles_trees_of_any_depth() {
+        let arena = EntityArena::new();
+        let deep = deep_sum(&arena, 100_000);
+        let compiled = compile::<f64, [(&str, f64)]>(&deep);
+        assert_eq!(compiled(&[]).unwrap(), 100_000.0);
+
+        let x = arena.alloc(Entity::Value("x"));
+        let scaled = Entity::Operation(Operation::Mul(arena.alloc(deep), x));
+        assert_eq!(
+            eval_batch(&scaled, "x", &[0.0, 2.0]).unwrap(),
+            [0.0, 200_000.0]
+        );
+    }
+
+    #[test]
+    fn finds_variables_in_trees_of_any_depth() {
+        let arena = EntityArena::new();
+        let deep = deep_sum(&arena, 100_000);
+        assert!(is_constant(&deep));
+        assert!(variables(&deep).is_empty());
+
+        let x = arena.alloc(Entity::Value("x"));
+        let scaled = Entity::Operation(Operation::Mul(arena.alloc(deep), x));
+        assert!(!is_constant(&scaled));
+        assert_eq!(variables(&scaled), ["x"]);
+    }
+
+    #[test]
+    fn fails_on_undefined_variables() {
+        let arena = EntityArena::new();
+        let entity = parse("x + y", &arena).unwrap();
+        assert!(matches!(
+            eval(&entity, &[("x", 1.0)][..]),
+            Err(EvalError::UndefinedVariable(name)) if name == "y"
+        ));
+    }
+
+    #[cfg(feature = "std")]
+    #[test]
+    fn evaluates_with_variable_maps() {
+        use std::collections::HashMap;
+
+        let arena = EntityArena::new();
+        let entity = parse("2*x+1", &arena).unwrap();
+        let vars = HashMap::from([("x", 3.0)]);
+        assert_eq!(eval(&entity, &vars).unwrap(), 7.0);
+        let vars = BTreeMap::from([("x", -1.0)]);
+        assert_eq!(eval(&entity, &vars).unwrap(), -1.0);
+
+        let entity = parse("sin(0)", &arena).unwrap();
+        assert_eq!(eval(&entity, &HashMap::new()).unwrap(), 0.0);
+    }
+
+    #[test]
+    fn resolves_well_known_constants() {
+        let arena = EntityArena::new();
+        let no_vars: &[(&str, f64)] = &[];
+        let pi = parse("pi", &arena).unwrap();
+        assert_eq!(eval(&pi, no_vars).unwrap(), core::f64::consts::PI);
+        let tau = parse("2*pi", &arena).unwrap();
+        assert_eq!(eval(&tau, no_vars).unwrap(), core::f64::consts::TAU);
+        let e = parse("e", &arena).unwrap();
+        assert_eq!(eval(&e, no_vars).unwrap(), core::f64::consts::E);
+
+        // variables shadow constants, and constants can be turned off
+        assert_eq!(eval(&e, &[("e", 2.0)][..]).unwrap(), 2.0);
+        let options = EvalOptions {
+            constants: false,
+            ..EvalOptions::default()
+        };
+        assert!(matches!(
+            eval_with_options(&e, no_vars, &options),
+            Err(EvalError::UndefinedVariable(name)) if name == "e"
+        ));
+    }
+
+    /// The value of an equation without variables.
+    fn value(equation: &str) -> f64 {
+        let arena = EntityArena::new();
+        let no_vars: &[(&str, f64)] = &[];
+        eval(&parse(equation, &arena).unwrap(), no_vars).unwrap()
+    }
+
+    fn assert_near(actual: f64, expected: f64) {
+        assert!(
+            (actual - expected).abs() < 1e-12,
+            "{actual} isn't close to {expected}"
+        );
+    }
+
+    #[test]
+    fn evaluates_inverse_trigonometry() {
+        use core::f64::consts::{FRAC_PI_2, FRAC_PI_4};
+
+        assert_near(value("asin(1)"), FRAC_PI_2);
+        assert_near(value("acos(0)"), FRAC_PI_2);
+        assert_near(value("atan(1)"), FRAC_PI_4);
+        assert_near(value("atan2(1,1)"), FRAC_PI_4);
+        assert_near(value("atan2(1,-1)"), 3.0 * FRAC_PI_4);
+    }
+
+    #[test]
+    fn evaluates_inverse_hyperbolics() {
+        assert_near(value("asinh(sinh(1))"), 1.0);
+        assert_near(value("acosh(cosh(2))"), 2.0);
+        assert_near(value("atanh(tanh(0.5))"), 0.5);
+        assert!(value("acosh(0)").is_nan());
+    }
+
+    #[test]
+    fn evaluates_min_max_and_clamp() {
+        assert_eq!(value("max(3,5)"), 5.0);
+        assert_eq!(value("min(3,5)"), 3.0);
+        assert_eq!(value("clamp(10,0,5)"), 5.0);
+        assert_eq!(value("clamp(-1,0,5)"), 0.0);
+        assert_eq!(value("clamp(2,0,5)"), 2.0);
+
+        let arena = EntityArena::new();
+        assert_eq!(
+            parse::<f64>("clamp(x,0,1)", &arena).unwrap(),
+            Entity::Function(Function::Clamp(
+                &Entity::Value("x"),
+                &Entity::ValueLit(0.0),
+                &Entity::ValueLit(1.0)
+            ))
+        );
+        assert!(parse::<f64>("clamp(1,2)", &arena).is_err());
+    }
+
+    #[test]
+    fn evaluates_signum() {
+        assert_eq!(value("sign(3.5)"), 1.0);
+        assert_eq!(value("signum(-2)"), -1.0);
+        assert_eq!(value("sign(0)"), 0.0);
+        // the sign of zero keeps its own sign
+        assert!(value("sign(-0)").is_sign_negative());
+        assert!(value("sign(0)").is_sign_positive());
+    }
+
+    #[test]
+    fn evaluates_logarithms_by_base() {
+        assert_near(value("log(100)"), 2.0);
+        assert_near(value("log(2,8)"), 3.0);
+        assert_near(value("log2(8)"), 3.0);
+        assert_near(value("ln(e)"), 1.0);
+
+        let arena = EntityArena::new();
+        let x = &Entity::Value("x");
+        assert_eq!(
+            parse::<f64>("log(x)", &arena).unwrap(),
+            Entity::Function(Function::Log10(x))
+        );
+        assert_eq!(
+            parse::<f64>("log(2, x)", &arena).unwrap(),
+            Entity::Function(Function::Log(&Entity::ValueLit(2.0), x))
+        );
+    }
+
+    #[test]
+    fn fails_on_non_finite_results_when_checked() {
+        let arena = EntityArena::new();
+        let no_vars: &[(&str, f64)] = &[];
+        for equation in ["1/0", "sqrt(-1)", "log(-1)", "(1/0)-(1/0)"] {
+            let entity = parse(equation, &arena).unwrap();
+            assert!(!eval(&entity, no_vars).unwrap().is_finite());
+            assert!(
+                matches!(eval_checked(&entity, no_vars), Err(EvalError::NotFinite)),
+                "{equation} should fail"
+            );
+        }
+
+        // an intermediate infinity fails even if the result would be finite
+        let entity = parse("1/(1/0)", &arena).unwrap();
+        assert_eq!(eval(&entity, no_vars).unwrap(), 0.0);
+        assert!(matches!(
+            eval_checked(&entity, no_vars),
+            Err(EvalError::NotFinite)
+        ));
+
+        let entity = parse("sqrt(4)/2", &arena).unwrap();
+        assert_eq!(eval_checked(&entity, no_vars).unwrap(), 1.0);
+    }
+
+    #[test]
+    fn evaluates_gcd_and_lcm() {
+        assert_eq!(value("gcd(12,18)"), 6.0);
+        assert_eq!(value("lcm(4,6)"), 12.0);
+        assert_eq!(value("gcd(-12,18)"), 6.0);
+        assert_eq!(value("lcm(-4,6)"), 12.0);
+        assert_eq!(value("gcd(0,5)"), 5.0);
+        assert_eq!(value("lcm(0,5)"), 0.0);
+        // arguments are rounded first
+        assert_eq!(value("gcd(11.6,18.2)"), 6.0);
+        assert!(value("gcd(1/0,2)").is_nan());
+    }
+
+    #[test]
+    fn evaluates_trunc_and_fract() {
+        assert_eq!(value("trunc(3.7)"), 3.0);
+        assert_near(value("fract(3.7)"), 0.7);
+        assert_eq!(value("trunc(-3.7)"), -3.0);
+        assert_near(value("fract(-3.7)"), -0.7);
+        assert_eq!(value("trunc(2.25)+fract(2.25)"), 2.25);
+    }
+
+    #[test]
+    fn lists_variables_in_order() {
+        let arena = EntityArena::new();
+        let entity = parse::<f64>("a*x+b", &arena).unwrap();
+        assert_eq!(variables(&entity), ["a", "x", "b"]);
+
+        let entity = parse::<f64>("2*pi*r + x*e^x + r", &arena).unwrap();
+        assert_eq!(variables(&entity), ["r", "x"]);
+
+        let entity = parse::<f64>("sin(1)", &arena).unwrap();
+        assert!(variables(&entity).is_empty());
+    }
+
+    #[test]
+    fn evaluates_in_degrees() {
+        let arena = EntityArena::new();
+        let degrees = |equation| {
+            let no_vars: &[(&str, f64)] = &[];
+            eval_degrees(&parse(equation, &arena).unwrap(), no_vars).unwrap()
+        };
+        assert_near(degrees("sin(90)"), 1.0);
+        assert_near(degrees("cos(180)"), -1.0);
+        assert_near(degrees("tan(45)"), 1.0);
+        assert_near(degrees("asin(1)"), 90.0);
+        assert_near(degrees("atan2(1, 1)"), 45.0);
+        // hyperbolic functions don't take angles
+        assert_eq!(degrees("sinh(0)"), 0.0);
+        assert_near(degrees("cosh(1)"), value("cosh(1)"));
+        // radians are still the default
+        assert_near(value("sin(90)"), 90f64.sin());
+    }
+
+    #[test]
+    fn evaluates_exp2_and_exp10() {
+        assert_eq!(value("exp2(10)"), 1024.0);
+        assert_eq!(value("exp10(3)"), 1000.0);
+        assert_eq!(value("exp2(-1)"), 0.5);
+        assert_near(value("log2(exp2(3.5))"), 3.5);
+    }
+
+    #[test]
+    fn evaluates_hypot() {
+        assert_eq!(value("hypot(3,4)"), 5.0);
+        assert_eq!(value("hypot(-5,12)"), 13.0);
+        // no intermediate overflow, unlike `sqrt(a^2+b^2)`
+        assert_near(value("hypot(3e200,4e200)") / 1e200, 5.0);
+        assert!(value("sqrt((3e200)^2+(4e200)^2)").is_infinite());
+    }
+
+    #[test]
+    fn raises_integer_exponents_exactly() {
+        assert_eq!(value("2^10"), 1024.0);
+        assert_eq!(value("2^-2"), 0.25);
+        assert_eq!(value("(-2)^3"), -8.0);
+
+        let arena = EntityArena::new();
+        let cube = parse::<f64>("x^3", &arena).unwrap();
+        assert_eq!(eval(&cube, &[("x", 1.1)][..]).unwrap(), 1.1f64.powi(3));
+        let Entity::Operation(Operation::Pow(_, exponent)) = cube else {
+            panic!("expected a power, got {cube:?}");
+        };
+        assert_eq!(integer_exponent(exponent), Some(3));
+
+        // fractional exponents still go through `powf`
+        let root = parse::<f64>("x^0.5", &arena).unwrap();
+        assert_near(
+            eval(&root, &[("x", 2.0)][..]).unwrap(),
+            core::f64::consts::SQRT_2,
+        );
+        assert_eq!(integer_exponent(&Entity::ValueLit(0.5)), None);
+        assert_eq!(integer_exponent(&Entity::<f64>::Value("n")), None);
+    }
+
+    #[test]
+    fn detects_constant_trees() {
+        let arena = EntityArena::new();
+        let constant = |equation| is_constant(&parse::<f64>(equation, &arena).unwrap());
+        assert!(constant("2+3*pi"));
+        assert!(constant("sin(e)^2 + tau"));
+        assert!(!constant("2+x"));
+        assert!(!constant("max(1, 2*y)"));
+        assert!(!constant("pie"));
+    }
+
+    #[test]
+    fn evaluates_gamma() {
+        use core::f64::consts::PI;
+
+        assert_near(value("gamma(5)"), 24.0);
+        assert_near(value("gamma(0.5)"), PI.sqrt());
+        assert_near(value("lgamma(5)"), 24f64.ln());
+        // it generalizes the factorial
+        assert_near(value("gamma(7)"), value("6!"));
+    }
+
+    #[test]
+    fn gamma_only_overflows_past_the_largest_float() {
+        // just under f64::MAX, but t^(x + 1/2) alone would already overflow
+        let large = value("gamma(171.5)");
+        assert!(large.is_finite());
+        assert!((large / 9.483367566824795e307 - 1.0).abs() < 1e-9);
+        assert_eq!(value("gamma(1000.5)"), f64::INFINITY);
+    }
+}
@@ -0,0 +1,103 @@
+use crate::ast::Entity;
+use crate::eval::{EvalError, EvalOptions, Variables, eval_with_options};
+
+use num_traits::FloatConst;
+
+/// Evaluates trees one after the other, like the lines of a calculator session, keeping the
+/// last result as the variable `ans`: after `2+3`, `ans*2` is `10`.
+///
+/// `ans` is undefined until something was evaluated, and isn't changed by a failed evaluation.
+/// Like well-known constants, it's shadowed by a variable of the same name.
+#[derive(Debug, Clone, Default)]
+pub struct Evaluator<T> {
+    ans: Option<T>,
+    options: EvalOptions,
+}
+
+impl<T: num_traits::Float + FloatConst> Evaluator<T> {
+    pub fn new() -> Self {
+        Self::with_options(EvalOptions::default())
+    }
+
+    /// An evaluator that evaluates like [`eval_with_options`].
+    pub fn with_options(options: EvalOptions) -> Self {
+        Self { ans: None, options }
+    }
+
+    /// The result of the last successful evaluation.
+    pub fn ans(&self) -> Option<T> {
+        self.ans
+    }
+
+    /// Evaluates an [`Entity`] tree like [`eval`](crate::eval), with `ans` as the previous result,
+    /// which is then replaced by this one.
+    pub fn eval<V: Variables<T> + ?Sized>(
+        &mut self,
+        entity: &Entity<'_, T>,
+        vars: &V,
+    ) -> Result<T, EvalError> {
+        let vars = WithAns {
+            ans: self.ans,
+            vars,
+        };
+        let val = eval_with_options(entity, &vars, &self.options)?;
+        self.ans = Some(val);
+        Ok(val)
+    }
+}
+
+struct WithAns<'v, T, V: ?Sized> {
+    ans: Option<T>,
+    vars: &'v V,
+}
+
+impl<T: Copy, V: Variables<T> + ?Sized> Variables<T> for WithAns<'_, T, V> {
+    fn get(&self, name: &str) -> Option<T> {
+        match name {
+            "ans" => self.vars.get(name).or(self.ans),
+            _ => self.vars.get(name),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{EntityArena, parse};
+
+    #[test]
+    fn carries_ans_across_a_session() {
+        let arena = EntityArena::new();
+        let no_vars: &[(&str, f64)] = &[];
+        let mut evaluator = Evaluator::new();
+        assert_eq!(evaluator.ans(), None);
+        assert!(matches!(
+            evaluator.eval(&parse("ans+1", &arena).unwrap(), no_vars),
+            Err(EvalError::UndefinedVariable(name)) if name == "ans"
+        ));
+
+        assert_eq!(
+            evaluator
+                .eval(&parse("2+3", &arena).unwrap(), no_vars)
+                .unwrap(),
+            5.0
+        );
+        assert_eq!(
+            evaluator
+                .eval(&parse("ans*2", &arena).unwrap(), no_vars)
+                .unwrap(),
+            10.0
+        );
+        assert_eq!(evaluator.ans(), Some(10.0));
+
+        // failures keep the last result, and variables shadow it
+        assert!(
+            evaluator
+                .eval(&parse("y", &arena).unwrap(), no_vars)
+                .is_err()
+        );
+        assert_eq!(evaluator.ans(), Some(10.0));
+        let entity = parse("ans", &arena).unwrap();
+        assert_eq!(evaluator.eval(&entity, &[("ans", 1.0)][..]).unwrap(), 1.0);
+    }
+}
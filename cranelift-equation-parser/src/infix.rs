@@ -0,0 +1,197 @@
+use crate::ast::{Entity, Operator};
+
+use alloc::string::String;
+use core::fmt::{Display, Write};
+
+/// Renders an [`Entity`] tree back to infix notation, like `2+3*4`.
+///
+/// Parentheses are only inserted where [`Operator::precedence`] and
+/// [`Operator::is_right_associative`] require them, so `Mul(Add(2, 3), 4)` becomes `(2+3)*4`.
+/// Multiplication is always written out, and parsing the output gives back an equal tree.
+pub fn to_infix<T: num_traits::Float + Display>(entity: &Entity<'_, T>) -> String {
+    let mut out = String::new();
+    write_infix(&mut out, entity, usize::MAX);
+    out
+}
+
+/// Renders an [`Entity`] tree like [`to_infix`], but cut off at `max_len` bytes, followed by `…`,
+/// if it's longer. Writing stops there too, so echoing a huge tree in a log line is cheap.
+pub fn to_infix_truncated<T: num_traits::Float + Display>(
+    entity: &Entity<'_, T>,
+    max_len: usize,
+) -> String {
+    let mut out = String::new();
+    write_infix(&mut out, entity, max_len);
+    if out.len() > max_len {
+        let mut end = max_len;
+        while !out.is_char_boundary(end) {
+            end -= 1;
+        }
+        out.truncate(end);
+        out.push('…');
+    }
+    out
+}
+
+/// Writes `entity` to `out`, giving up once `out` is longer than `limit`.
+fn write_infix<T: num_traits::Float + Display>(
+    out: &mut String,
+    entity: &Entity<'_, T>,
+    limit: usize,
+) {
+    if out.len() > limit {
+        return;
+    }
+
+    match entity {
+        Entity::ValueLit(val) => {
+            let _ = write!(out, "{val}");
+        }
+        Entity::Value(name) => out.push_str(name),
+        Entity::Operation(op) => {
+            let operator = op.operator();
+            match op.operands()[..] {
+                [x] if operator.is_prefix() => {
+                    let _ = write!(out, "{operator}");
+                    write_operand(out, x, operator.precedence() + 1, limit);
+                }
+                [x] => {
+                    write_operand(out, x, operator.precedence(), limit);
+                    let _ = write!(out, "{operator}");
+                }
+                [lhs, rhs] => {
+                    // the side an operator groups towards can take the same precedence bare
+                    let (left, right) = match operator.is_right_associative() {
+                        true => (operator.precedence() + 1, operator.precedence()),
+                        false => (operator.precedence(), operator.precedence() + 1),
+                    };
+                    write_operand(out, lhs, left, limit);
+                    let _ = write!(out, "{operator}");
+                    write_operand(out, rhs, right, limit);
+                }
+                _ => unreachable!("operations take one or two operands"),
+            }
+        }
+        Entity::Function(func) => {
+            let _ = write!(out, "{}(", func.function_type());
+            for (i, arg) in func.arguments().into_iter().enumerate() {
+                if i > 0 {
+                    out.push(',');
+                }
+                write_infix(out, arg, limit);
+            }
+            out.push(')');
+        }
+    }
+}
+
+/// Writes `entity`, wrapped in parentheses if it binds looser than `min_precedence`.
+fn write_operand<T: num_traits::Float + Display>(
+    out: &mut String,
+    entity: &Entity<'_, T>,
+    min_precedence: u8,
+    limit: usize,
+) {
+    let precedence = match entity {
+        Entity::Operation(op) => Some(op.operator().precedence()),
+        // a negative literal prints with a leading `-`, which parses back as a negation
+        Entity::ValueLit(val) if val.is_sign_negative() => Some(Operator::Neg.precedence()),
+        _ => None,
+    };
+
+    match precedence {
+        Some(precedence) if precedence < min_precedence => {
+            out.push('(');
+            write_infix(out, entity, limit);
+            out.push(')');
+        }
+        _ => write_infix(out, entity, limit),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ast::Operation;
+    use crate::{EntityArena, eval, parse};
+
+    fn infix(equation: &str) -> String {
+        let arena = EntityArena::new();
+        to_infix(&parse::<f64>(equation, &arena).unwrap())
+    }
+
+    #[test]
+    fn inserts_only_needed_parentheses() {
+        assert_eq!(infix("2+(3*4)"), "2+3*4");
+        assert_eq!(infix("(2+3)*4"), "(2+3)*4");
+        assert_eq!(infix("(1-2)-3"), "1-2-3");
+        assert_eq!(infix("1-(2-3)"), "1-(2-3)");
+        assert_eq!(infix("2^(3^4)"), "2^3^4");
+        assert_eq!(infix("(2^3)^4"), "(2^3)^4");
+        assert_eq!(infix("2x"), "2*x");
+        assert_eq!(infix("sin((x+1))"), "sin(x+1)");
+    }
+
+    #[test]
+    fn round_trips_through_parse() {
+        let arena = EntityArena::new();
+        for equation in [
+            "2+3*4",
+            "(2+3)*4",
+            "1-(2-3)",
+            "-(x+1)^2",
+            "(-x)^2",
+            "2^-x",
+            "max(a, b+1)*|x|",
+            "log(2, x)/(y*z)",
+            "(n+1)!",
+            "7//2%3",
+        ] {
+            let entity = parse::<f64>(equation, &arena).unwrap();
+            let rendered = to_infix(&entity);
+            let reparsed = EntityArena::new();
+            assert_eq!(
+                parse::<f64>(&rendered, &reparsed).unwrap(),
+                entity,
+                "{equation} rendered as {rendered}"
+            );
+        }
+    }
+
+    #[test]
+    fn keeps_floor_division_apart_from_division() {
+        let arena = EntityArena::new();
+        let (seven, two) = (&Entity::ValueLit(7.0), &Entity::ValueLit(2.0));
+        let floor_div = parse::<f64>("7//2", &arena).unwrap();
+        assert_eq!(
+            floor_div,
+            Entity::Operation(Operation::FloorDiv(seven, two))
+        );
+        assert_eq!(eval(&floor_div, &[][..]).unwrap(), 3.0);
+        assert_eq!(to_infix(&floor_div), "7//2");
+
+        let div = parse::<f64>("7/2", &arena).unwrap();
+        assert_eq!(div, Entity::Operation(Operation::Div(seven, two)));
+        assert_eq!(eval(&div, &[][..]).unwrap(), 3.5);
+        assert_eq!(to_infix(&div), "7/2");
+
+        assert_eq!(infix("7//(2//1)"), "7//(2//1)");
+        assert_eq!(infix("(7//2)*3"), "7//2*3");
+    }
+
+    #[test]
+    fn truncates_long_output() {
+        let equation = ["x"; 1000].join("+");
+        let arena = EntityArena::new();
+        let entity = parse::<f64>(&equation, &arena).unwrap();
+
+        let truncated = to_infix_truncated(&entity, 20);
+        assert_eq!(truncated, "x+x+x+x+x+x+x+x+x+x+…");
+        assert_eq!(to_infix_truncated(&entity, usize::MAX), equation);
+
+        // short enough output is left alone
+        let short = parse::<f64>("sin(x)", &arena).unwrap();
+        assert_eq!(to_infix_truncated(&short, 6), "sin(x)");
+        assert_eq!(to_infix_truncated(&short, 5), "sin(x…");
+    }
+}
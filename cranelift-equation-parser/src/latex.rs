@@ -0,0 +1,227 @@
+use crate::ast::{Entity, Function, FunctionType, Operation, Operator};
+
+use alloc::string::String;
+use core::fmt::{Display, Write};
+
+/// Renders an [`Entity`] tree as LaTeX math, like `\frac{a + b}{c}`.
+///
+/// Division becomes `\frac{}{}`, powers `{}^{}`, `sqrt` and `root` become `\sqrt{}` and
+/// `\sqrt[n]{}`, and `abs`, `floor` and `ceil` become the matching delimiters.
+/// Functions LaTeX knows, like `\sin`, use their command, and the rest use `\operatorname{}`.
+pub fn to_latex<T: num_traits::Float + Display>(entity: &Entity<'_, T>) -> String {
+    let mut out = String::new();
+    write_latex(&mut out, entity);
+    out
+}
+
+fn write_latex<T: num_traits::Float + Display>(out: &mut String, entity: &Entity<'_, T>) {
+    match entity {
+        Entity::ValueLit(val) => {
+            let _ = write!(out, "{val}");
+        }
+        Entity::Value(name) => write_name(out, name),
+        Entity::Operation(op) => write_operation(out, op),
+        Entity::Function(func) => write_function(out, func),
+    }
+}
+
+fn write_name(out: &mut String, name: &str) {
+    match name {
+        "pi" | "tau" => {
+            let _ = write!(out, "\\{name}");
+        }
+        _ if name.chars().count() > 1 => {
+            let _ = write!(out, "\\mathrm{{{name}}}");
+        }
+        _ => out.push_str(name),
+    }
+}
+
+fn write_operation<T: num_traits::Float + Display>(out: &mut String, op: &Operation<'_, T>) {
+    match *op {
+        Operation::Add(lhs, rhs) => write_binary(out, Operator::Add, lhs, " + ", rhs),
+        Operation::Sub(lhs, rhs) => write_binary(out, Operator::Sub, lhs, " - ", rhs),
+        Operation::Mul(lhs, rhs) => write_binary(out, Operator::Mul, lhs, " \\cdot ", rhs),
+        Operation::Rem(lhs, rhs) => write_binary(out, Operator::Rem, lhs, " \\bmod ", rhs),
+        Operation::Div(lhs, rhs) => {
+            out.push_str("\\frac{");
+            write_latex(out, lhs);
+            out.push_str("}{");
+            write_latex(out, rhs);
+            out.push('}');
+        }
+        Operation::FloorDiv(lhs, rhs) => {
+            out.push_str("\\left\\lfloor \\frac{");
+            write_latex(out, lhs);
+            out.push_str("}{");
+            write_latex(out, rhs);
+            out.push_str("} \\right\\rfloor");
+        }
+        Operation::Pow(base, exponent) => {
+            write_operand(out, base, Operator::Factorial.precedence());
+            out.push_str("^{");
+            write_latex(out, exponent);
+            out.push('}');
+        }
+        Operation::Neg(x) => {
+            out.push('-');
+            write_operand(out, x, Operator::Neg.precedence() + 1);
+        }
+        Operation::Factorial(x) => {
+            write_operand(out, x, Operator::Factorial.precedence());
+            out.push('!');
+        }
+    }
+}
+
+fn write_binary<T: num_traits::Float + Display>(
+    out: &mut String,
+    operator: Operator,
+    lhs: &Entity<'_, T>,
+    symbol: &str,
+    rhs: &Entity<'_, T>,
+) {
+    write_operand(out, lhs, operator.precedence());
+    out.push_str(symbol);
+    write_operand(out, rhs, operator.precedence() + 1);
+}
+
+/// Writes `entity`, wrapped in parentheses if it binds looser than `min_precedence`.
+fn write_operand<T: num_traits::Float + Display>(
+    out: &mut String,
+    entity: &Entity<'_, T>,
+    min_precedence: u8,
+) {
+    let precedence = match entity {
+        // a fraction is its own group next to `+` or `\cdot`, but not under `^` or `!`
+        Entity::Operation(Operation::Div(..)) => Some(Operator::Pow.precedence()),
+        // and the floor brackets group it everywhere
+        Entity::Operation(Operation::FloorDiv(..)) => None,
+        Entity::Operation(op) => Some(op.operator().precedence()),
+        Entity::ValueLit(val) if val.is_sign_negative() => Some(Operator::Neg.precedence()),
+        _ => None,
+    };
+
+    match precedence {
+        Some(precedence) if precedence < min_precedence => {
+            out.push_str("\\left(");
+            write_latex(out, entity);
+            out.push_str("\\right)");
+        }
+        _ => write_latex(out, entity),
+    }
+}
+
+fn write_function<T: num_traits::Float + Display>(out: &mut String, func: &Function<'_, T>) {
+    let (open, close) = match *func {
+        Function::Sqrt(x) => return write_braced(out, "\\sqrt{", x),
+        Function::Root(n, x) => {
+            out.push_str("\\sqrt[");
+            write_latex(out, n);
+            out.push(']');
+            return write_braced(out, "{", x);
+        }
+        Function::Log(base, x) => {
+            write_braced(out, "\\log_{", base);
+            return write_arguments(out, &[x]);
+        }
+        Function::Log10(x) => {
+            out.push_str("\\log_{10}");
+            return write_arguments(out, &[x]);
+        }
+        Function::Log2(x) => {
+            out.push_str("\\log_{2}");
+            return write_arguments(out, &[x]);
+        }
+        Function::Abs(_) => ("\\left|", "\\right|"),
+        Function::Floor(_) => ("\\left\\lfloor ", " \\right\\rfloor"),
+        Function::Ceil(_) => ("\\left\\lceil ", " \\right\\rceil"),
+        _ => {
+            write_function_name(out, func.function_type());
+            return write_arguments(out, &func.arguments());
+        }
+    };
+
+    out.push_str(open);
+    write_latex(out, func.arguments()[0]);
+    out.push_str(close);
+}
+
+fn write_function_name(out: &mut String, function: FunctionType) {
+    let command = match function {
+        FunctionType::Sin => "sin",
+        FunctionType::Cos => "cos",
+        FunctionType::Tan => "tan",
+        FunctionType::Cot => "cot",
+        FunctionType::Sec => "sec",
+        FunctionType::Csc => "csc",
+        FunctionType::Asin => "arcsin",
+        FunctionType::Acos => "arccos",
+        FunctionType::Atan => "arctan",
+        FunctionType::Sinh => "sinh",
+        FunctionType::Cosh => "cosh",
+        FunctionType::Tanh => "tanh",
+        FunctionType::Coth => "coth",
+        FunctionType::Ln => "ln",
+        FunctionType::Exp => "exp",
+        FunctionType::Gcd => "gcd",
+        FunctionType::Gamma => "Gamma",
+        FunctionType::Min => "min",
+        FunctionType::Max => "max",
+        FunctionType::Signum => {
+            out.push_str("\\operatorname{sgn}");
+            return;
+        }
+        _ => {
+            let _ = write!(out, "\\operatorname{{{function}}}");
+            return;
+        }
+    };
+
+    let _ = write!(out, "\\{command}");
+}
+
+fn write_arguments<T: num_traits::Float + Display>(out: &mut String, args: &[&Entity<'_, T>]) {
+    out.push_str("\\left(");
+    for (i, arg) in args.iter().enumerate() {
+        if i > 0 {
+            out.push_str(", ");
+        }
+        write_latex(out, arg);
+    }
+    out.push_str("\\right)");
+}
+
+fn write_braced<T: num_traits::Float + Display>(
+    out: &mut String,
+    open: &str,
+    entity: &Entity<'_, T>,
+) {
+    out.push_str(open);
+    write_latex(out, entity);
+    out.push('}');
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{EntityArena, parse};
+
+    fn latex(equation: &str) -> String {
+        let arena = EntityArena::new();
+        to_latex(&parse::<f64>(equation, &arena).unwrap())
+    }
+
+    #[test]
+    fn renders_latex() {
+        assert_eq!(latex("(a+b)/c"), "\\frac{a + b}{c}");
+        assert_eq!(latex("sqrt(x)"), "\\sqrt{x}");
+        assert_eq!(latex("sin(x)^2"), "\\sin\\left(x\\right)^{2}");
+        assert_eq!(latex("|x|"), "\\left|x\\right|");
+        assert_eq!(latex("root(3, x)"), "\\sqrt[3]{x}");
+        assert_eq!(latex("(a+b)*c"), "\\left(a + b\\right) \\cdot c");
+        assert_eq!(latex("2*pi*theta"), "2 \\cdot \\pi \\cdot \\mathrm{theta}");
+        assert_eq!(latex("(x/2)^2"), "\\left(\\frac{x}{2}\\right)^{2}");
+        assert_eq!(latex("sign(x)"), "\\operatorname{sgn}\\left(x\\right)");
+    }
+}
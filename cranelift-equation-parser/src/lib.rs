@@ -1,204 +1,1991 @@
+#![cfg_attr(not(feature = "std"), no_std)]
+//! A parser for mathematical equations, like `2x^2 + sin(y)`.
+//!
+//! # Features
+//!
+//! - `std` (default): implements [`Variables`] for `HashMap` and enables [`print()`].
+//!   Without it the crate is `no_std`, using `libm` for floating point math.
+//!   It still needs `alloc`, as the parser collects tokens in a `Vec`,
+//!   trees are allocated in an [`EntityArena`], and errors carry `String`s.
+//! - `f64` (default) or `f32`: picks [`DefaultFloat`], used by [`parse_default`].
+//!   Exactly one of them has to be enabled.
+//! - `serde`: implements `Serialize` and `Deserialize` for the token types, [`FunctionType`],
+//!   and the owned trees like [`OwnedEntity`].
+//! - `rational`: implements [`Numeric`] for `num_rational::Rational64`, so equations can be
+//!   parsed into exact rationals, and adds `eval_rational` to evaluate them exactly.
+
+extern crate alloc;
+
+mod arena;
 mod ast;
+mod bindings;
+mod builder;
+mod canonicalize;
+mod compile;
+mod diagnostic;
+mod differentiate;
+mod equal;
+mod eval;
+mod evaluator;
+mod infix;
+mod latex;
+mod metrics;
+mod numeric;
+mod owned;
+mod parser;
+#[cfg(feature = "rational")]
+mod rational;
+mod simplify;
+mod substitute;
+mod tokens;
+mod visit;
+
+pub use arena::EntityArena;
+pub use bindings::parse_with_bindings;
+pub use builder::{Builder, Expr};
+pub use canonicalize::canonicalize;
+pub use compile::{compile, eval_batch};
+pub use diagnostic::{Diagnostic, Severity, parse_diagnostics, render_error};
+pub use differentiate::{DifferentiateError, differentiate};
+pub use equal::entities_equal;
+pub use eval::{
+    EvalError, EvalOptions, Variables, eval, eval_checked, eval_degrees, eval_with_options, fold,
+    integer_exponent, is_constant, known_constant, variables,
+};
+pub use evaluator::Evaluator;
+pub use infix::{to_infix, to_infix_truncated};
+pub use latex::to_latex;
+pub use metrics::{ParseStats, Stage, node_count, tree_depth, used_functions, uses_function};
+pub use numeric::Numeric;
+pub use owned::{OwnedEntity, OwnedFunction, OwnedOperation};
+pub use parser::Parser;
+#[cfg(feature = "rational")]
+pub use rational::eval_rational;
+pub use simplify::{SimplifyOptions, simplify, simplify_with_options};
+pub use substitute::substitute;
+pub use tokens::Tokens;
+pub use visit::{Visitor, walk};
+
+#[cfg(all(feature = "f32", feature = "f64"))]
+compile_error!("the `f32` and `f64` features are mutually exclusive");
+#[cfg(not(any(feature = "f32", feature = "f64")))]
+compile_error!("either the `f32` or the `f64` feature has to be enabled");
+
+/// The float type picked by the `f32`/`f64` features.
+#[cfg(feature = "f64")]
+pub type DefaultFloat = f64;
+/// The float type picked by the `f32`/`f64` features.
+#[cfg(all(feature = "f32", not(feature = "f64")))]
+pub type DefaultFloat = f32;
+
+/// An [`Entity`] tree over [`DefaultFloat`].
+pub type Equation<'a> = Entity<'a, DefaultFloat>;
 
-use ast::{Operator, ParenthesisType, RawSyntax, Syntax, FunctionType};
-use std::str::FromStr;
+use alloc::borrow::Cow;
+use alloc::string::{String, ToString};
+use alloc::vec::Vec;
+pub use ast::{
+    Entity, Function, FunctionType, HashableEntity, Operation, Operator, ParenthesisType,
+    RawSyntax, Relation, RelationOp, Syntax,
+};
+use diagnostic::Report;
+use eval::is_known_constant;
 use thiserror::Error;
 
-pub fn parse<T: num_traits::Float + std::fmt::Debug + std::fmt::Display>(equation: &str) {
-    // first pass
-    let first = first_parse(equation).unwrap();
-    //dbg!(&first);
-    let second = second_parse::<T>(&first[..], equation).unwrap();
-    dbg!(&second);
+/// Parses an equation into an [`Entity`] tree.
+/// Every node below the returned root is allocated in `arena`.
+///
+/// This runs the three stages of the parser in turn: [`tokenize`], [`resolve`] and
+/// [`build_tree`]. Calling them separately allows transforming the tokens in between,
+/// like renaming variables before the tree is built.
+pub fn parse<'a, T: Numeric>(
+    equation: &'a str,
+    arena: &'a EntityArena<'a, T>,
+) -> Result<Entity<'a, T>, EquationParseError> {
+    Parser::default().parse(equation, arena)
+}
+
+/// Parses an equation like [`parse`], also counting what it's made of,
+/// like how many function calls it has.
+pub fn parse_with_stats<'a, T: Numeric>(
+    equation: &'a str,
+    arena: &'a EntityArena<'a, T>,
+) -> Result<(Entity<'a, T>, ParseStats), EquationParseError> {
+    Parser::default().parse_with_stats(equation, arena)
+}
 
-    print!("{} => ", equation);
-    print::<T>(&second[..]);
+/// Parses an equation like [`parse`], using [`DefaultFloat`].
+pub fn parse_default<'a>(
+    equation: &'a str,
+    arena: &'a EntityArena<'a, DefaultFloat>,
+) -> Result<Equation<'a>, EquationParseError> {
+    parse(equation, arena)
 }
 
-fn first_parse(equation: &str) -> Result<Vec<RawSyntax>, EquationParseError> {
-    let mut vec = Vec::new();
+/// Parses an equation like [`parse`], but also allocates the root in `arena`,
+/// so the whole tree is owned by it.
+pub fn parse_into<'a, T: Numeric>(
+    equation: &'a str,
+    arena: &'a EntityArena<'a, T>,
+) -> Result<&'a Entity<'a, T>, EquationParseError> {
+    parse(equation, arena).map(|root| arena.alloc(root))
+}
 
-    let mut last_start_index: Option<(usize, bool)> = None;
+/// Parses an equation into an [`OwnedEntity`] tree, which borrows neither an arena nor
+/// `equation`, so it can be moved around freely.
+pub fn parse_owned<T: Numeric>(equation: &str) -> Result<OwnedEntity<T>, EquationParseError> {
+    let arena = EntityArena::new();
+    parse(equation, &arena).map(|root| OwnedEntity::from(&root))
+}
 
-    for (index, value) in equation.chars().enumerate() {
-        if value.is_numeric() || value == '.' {
-            match last_start_index {
-                None => {}
-                Some((_, false)) => continue,
-                Some((start, true)) => {
-                    vec.push(RawSyntax::ValueIdent { start, end: index });
-                }
-            }
+/// Parses an equation or inequality with exactly one relation, like `x^2 = 4` or `2x <= y`.
+/// Both sides are parsed like with [`parse`], with their nodes allocated in `arena`.
+pub fn parse_equation<'a, T: Numeric>(
+    equation: &'a str,
+    arena: &'a EntityArena<'a, T>,
+) -> Result<Relation<'a, T>, EquationParseError> {
+    let first = tokenize(equation)?;
 
-            last_start_index = Some((index, false));
-            continue;
-        } else if value.is_alphabetic() {
-            match last_start_index {
-                None => {}
-                Some((_, true)) => continue,
-                Some((start, false)) => {
-                    vec.push(RawSyntax::ValueLit { start, end: index });
-                }
-            }
-            last_start_index = Some((index, true));
+    let mut relations = first
+        .iter()
+        .enumerate()
+        .filter_map(|(i, token)| match *token {
+            RawSyntax::Relation { kind, start, .. } => Some((i, kind, start)),
+            _ => None,
+        });
+    let (split, op, _) = relations
+        .next()
+        .ok_or(EquationParseError::MissingRelation)?;
+    if let Some((_, _, index)) = relations.next() {
+        return Err(EquationParseError::UnexpectedRelation { index });
+    }
+
+    let options = Parser::default();
+    let side =
+        |tokens: &[RawSyntax]| options.build_tree(&options.resolve(tokens, equation)?, arena);
+
+    Ok(Relation {
+        lhs: side(&first[..split])?,
+        op,
+        rhs: side(&first[split + 1..])?,
+    })
+}
+
+/// Runs only the first two stages of the parser, [`tokenize`] and [`resolve`],
+/// returning the flat [`Syntax`] token stream.
+pub fn parse_tokens<T: Numeric>(equation: &str) -> Result<Vec<Syntax<'_, T>>, EquationParseError> {
+    Parser::default().parse_tokens(equation)
+}
+
+/// Splits an equation into its stage-one [`RawSyntax`] tokens, the first stage of [`parse`].
+/// The `start..end` ranges carried by the tokens are byte offsets into `equation`.
+/// Whitespace is skipped, and so are comments, from a `#` to the end of its line.
+pub fn tokenize(equation: &str) -> Result<Vec<RawSyntax>, EquationParseError> {
+    Tokens::new(equation).collect()
+}
+
+/// Updates the tokens of an equation after it was edited, like [`tokenize`] on the new `equation`,
+/// but only splitting it again from the token before `edit_start`, the byte offset of the first
+/// character that changed. Editing `2+3` into `2+34` keeps the `2` and `+` tokens, and only reads
+/// `34` again.
+///
+/// On error, `tokens` is left with the tokens read until then.
+pub fn retokenize(
+    tokens: &mut Vec<RawSyntax>,
+    equation: &str,
+    edit_start: usize,
+) -> Result<(), EquationParseError> {
+    Parser::<DefaultFloat>::default().retokenize(tokens, equation, edit_start)
+}
+
+/// Resolves the stage-one tokens of `equation`, from [`tokenize`], into stage-two [`Syntax`]
+/// tokens, the second stage of [`parse`]. This checks the parentheses, parses literals,
+/// resolves function names and constants, and inserts implicit multiplications,
+/// so `2x` becomes `2 * x`.
+///
+/// [`Parser::resolve`] does the same with custom options.
+pub fn resolve<'a, T: Numeric>(
+    tokens: &[RawSyntax],
+    equation: &'a str,
+) -> Result<Vec<Syntax<'a, T>>, EquationParseError> {
+    Parser::default().resolve(tokens, equation)
+}
+
+/// Checks that every closing parenthesis matches the most recent open one of the same family,
+/// that none are left open, and that they're nested at most `max_depth` deep.
+fn validate_parens(
+    ast: &[RawSyntax],
+    max_depth: usize,
+    errors: &mut impl Report,
+) -> Result<(), EquationParseError> {
+    // (token, type) of every parenthesis still open
+    let mut stack: Vec<(RawSyntax, ParenthesisType)> = Vec::new();
+
+    for token in ast {
+        let RawSyntax::Parenthesis { kind, start, .. } = *token else {
             continue;
-        } else {
-            match last_start_index {
-                None => {}
-                Some((start, false)) => {
-                    vec.push(RawSyntax::ValueLit { start, end: index });
-                }
-                Some((start, true)) => {
-                    if value == '(' {
-                        vec.push(RawSyntax::Function { start, end: index });
-                    } else {
-                        vec.push(RawSyntax::ValueIdent { start, end: index });
-                    }
-                }
-            }
-            last_start_index = None;
-        }
+        };
 
-        match value {
-            ' ' => continue,
-            ',' => {
-                vec.push(RawSyntax::Comma);
-                continue;
-            }
-            '|' => {
-                vec.push(RawSyntax::Abs);
-                continue;
+        if kind.is_open() {
+            stack.push((*token, kind));
+            // only the parenthesis that crosses the limit is reported, not every one after it
+            if stack.len() == max_depth + 1 {
+                errors.report(EquationParseError::TooDeep { index: start }, token.span())?;
             }
-            _ => {}
-        }
-
-        if let Ok(val) = ParenthesisType::try_from(value) {
-            vec.push(RawSyntax::Parenthesis(val));
             continue;
         }
 
-        if let Ok(val) = Operator::try_from(value) {
-            vec.push(RawSyntax::Operator(val));
-            continue;
+        match stack.pop() {
+            Some((_, open)) if open.is_closed_by(kind) => {}
+            _ => errors.report(
+                EquationParseError::UnbalancedParenthesis { index: start },
+                token.span(),
+            )?,
         }
     }
 
-    match last_start_index {
-        None => {}
-        Some((start, false)) => {
-            vec.push(RawSyntax::ValueLit {
-                start,
-                end: equation.len(),
-            });
-        }
-        Some((start, true)) => {
-            vec.push(RawSyntax::ValueIdent {
-                start,
-                end: equation.len(),
-            });
-        }
+    for (token, _) in stack {
+        let index = token.span().start;
+        errors.report(
+            EquationParseError::UnbalancedParenthesis { index },
+            token.span(),
+        )?;
     }
 
-    Ok(vec)
+    Ok(())
 }
 
-fn second_parse<'a, T: num_traits::Float + std::fmt::Debug>(
+fn second_parse<'a, T: Numeric>(
     ast: &[RawSyntax],
     equation: &'a str,
+    options: &Parser<T>,
+    errors: &mut impl Report,
 ) -> Result<Vec<Syntax<'a, T>>, EquationParseError> {
     let mut vec = Vec::with_capacity(ast.len());
 
     // used for implicit multiplication
     let mut previous_token: Option<RawSyntax> = None;
+    // used to tell `()` from a call without arguments, like `sin()`
+    let mut before_previous: Option<RawSyntax> = None;
+    // `|x|` is rewritten as `abs(x)`. For every bar still open, innermost last:
+    // its start, and how many parentheses were open around it.
+    let mut open_abs: Vec<(usize, usize)> = Vec::new();
+    // whether the previous token was a bar that closed an absolute value
+    let mut closed_abs = false;
+    let mut depth = 0;
+    // how many functions are applied to the value being read, like the two in `sin cos x`,
+    // with `Parser::function_application`. Their parentheses are closed right after it
+    let mut applied = 0;
+    let mut after_application = false;
 
-    for (index, token) in ast.iter().enumerate() {
-        // implicit multiplication
-        match (previous_token, token) {
-            (None, _)
-            | (Some(RawSyntax::Operator(_)), _)
-            | (Some(RawSyntax::Comma), _)
-            | (_, RawSyntax::Comma)
-            | (Some(RawSyntax::Abs), _)
-            | (_, RawSyntax::Abs)
-            | (Some(RawSyntax::Parenthesis(_)), _)
-            | (_, RawSyntax::Operator(_))
-            | (Some(RawSyntax::Function { start: _, end: _ }), _)
-            | (_, RawSyntax::Parenthesis(ParenthesisType::Close))
-            | (_, RawSyntax::Parenthesis(ParenthesisType::CloseSquare))
-            | (_, RawSyntax::Parenthesis(ParenthesisType::CloseCurly)) => {}
-            _ => {
-                if index != ast.len() - 1 {
-                    vec.push(Syntax::Operator(Operator::Mul));
-                }
+    for (i, token) in ast.iter().enumerate() {
+        let next = ast.get(i + 1);
+        // in percent mode, `%` is a postfix operator instead of the remainder
+        let token = &match *token {
+            RawSyntax::Operator {
+                kind: Operator::Rem,
+                start,
+                end,
+            } if options.percent_mode => RawSyntax::Operator {
+                kind: Operator::Percent,
+                start,
+                end,
+            },
+            token => token,
+        };
+
+        // implicit multiplication, like `2x`, `x2`, `2(3)` or `(2)(3)`:
+        // a `*` goes between anything that ends an operand and anything that starts one
+        let ends_operand = match previous_token {
+            Some(
+                RawSyntax::ValueLit { .. }
+                | RawSyntax::ValueIdent { .. }
+                | RawSyntax::Superscript { .. },
+            ) => true,
+            Some(RawSyntax::Parenthesis { kind, .. }) => !kind.is_open(),
+            Some(RawSyntax::Operator { kind, .. }) => kind.is_postfix(),
+            // a bar that closed an absolute value
+            Some(RawSyntax::Abs { .. }) => closed_abs,
+            _ => false,
+        };
+        // a bar closes the innermost absolute value if it follows an operand, and that one was
+        // opened inside the same parentheses. Otherwise it opens one, like the second in `|a+|b||`
+        let closes_abs = matches!(token, RawSyntax::Abs { .. })
+            && ends_operand
+            && open_abs
+                .last()
+                .is_some_and(|(_, abs_depth)| *abs_depth == depth);
+        let starts_operand = match token {
+            RawSyntax::ValueLit { .. }
+            | RawSyntax::ValueIdent { .. }
+            | RawSyntax::Function { .. } => true,
+            RawSyntax::Parenthesis { kind, .. } => kind.is_open(),
+            // a bar that opens an absolute value
+            RawSyntax::Abs { .. } => !closes_abs,
+            // operators that can only be prefix, like the `√` in `2√3`
+            RawSyntax::Operator { kind, .. } => kind.is_prefix(),
+            _ => false,
+        };
+
+        if ends_operand && starts_operand && !after_application {
+            match options.implicit_multiplication {
+                true => vec.push(Syntax::Operator(Operator::Mul)),
+                false => errors.report(
+                    EquationParseError::MissingOperator {
+                        index: token.span().start,
+                    },
+                    token.span(),
+                )?,
             }
         }
 
+        // an operator that's still waiting for its right operand, like the `+` in `2+)`
+        let dangling = dangling_operator(previous_token);
+        let application = applied_function(token, next, equation, options);
+
         match token {
             RawSyntax::ValueLit { start, end } => {
-                match T::from_str_radix(&equation[*start..*end], 10) {
+                match parse_literal::<T>(&equation[*start..*end], options.decimal_separator) {
                     Ok(v) => vec.push(Syntax::ValueLit(v)),
-                    Err(_) => return Err(EquationParseError::LiteralParseError),
+                    Err(source) => {
+                        let error = EquationParseError::LiteralParseError {
+                            text: equation[*start..*end].to_string(),
+                            start: *start,
+                            end: *end,
+                            source,
+                        };
+                        errors.report(error, token.span())?;
+                        // keep the placeholder operand, so the rest can still be checked
+                        vec.push(Syntax::ValueLit(T::zero()));
+                    }
                 }
             }
             RawSyntax::ValueIdent { start, end } => {
-                vec.push(Syntax::ValueIdent(&equation[*start..*end]));
+                let name = match &equation[*start..*end] {
+                    "π" => "pi",
+                    name => name,
+                };
+                match application {
+                    Some(func) => {
+                        allowed(func, token.span(), options, errors)?;
+                        vec.push(Syntax::Function(func));
+                        vec.push(Syntax::Parenthesis(ParenthesisType::Open));
+                        applied += 1;
+                    }
+                    None => identifier(name, *start, options, &mut vec, errors)?,
+                }
+            }
+            RawSyntax::Operator {
+                kind: operator,
+                start,
+                ..
+            } => {
+                // an operator with nothing to its left is unary
+                let prefix = !ends_operand;
+
+                match operator {
+                    Operator::Sub if prefix => vec.push(Syntax::Operator(Operator::Neg)),
+                    Operator::Add if prefix => {}
+                    Operator::Sqrt => {
+                        allowed(FunctionType::Sqrt, token.span(), options, errors)?;
+                        vec.push(Syntax::Operator(Operator::Sqrt));
+                    }
+                    _ if operator.is_prefix() => vec.push(Syntax::Operator(*operator)),
+                    _ if prefix => errors.report(
+                        EquationParseError::DanglingOperator { index: *start },
+                        token.span(),
+                    )?,
+                    Operator::Factorial if !options.allow_factorial => errors.report(
+                        EquationParseError::UnsupportedOperator { index: *start },
+                        token.span(),
+                    )?,
+                    _ => vec.push(Syntax::Operator(*operator)),
+                }
             }
-            RawSyntax::Operator(operator) => vec.push(Syntax::Operator(*operator)),
-            RawSyntax::Parenthesis(parenthesis_type) => {
+            RawSyntax::Parenthesis {
+                kind: parenthesis_type,
+                start,
+                ..
+            } => {
+                if options.only_round_parens && !parenthesis_type.is_round() {
+                    errors.report(
+                        EquationParseError::UnsupportedBracket { index: *start },
+                        token.span(),
+                    )?;
+                }
+
+                if parenthesis_type.is_open() {
+                    depth += 1;
+                } else {
+                    // a call without arguments is left to the arity check instead
+                    if let Some(RawSyntax::Parenthesis { kind, start, .. }) = previous_token
+                        && kind.is_open()
+                        && !matches!(before_previous, Some(RawSyntax::Function { .. }))
+                    {
+                        errors.report(
+                            EquationParseError::EmptyParentheses { index: start },
+                            start..token.span().end,
+                        )?;
+                    }
+                    if let Some(operator) = dangling {
+                        let index = operator.start;
+                        errors.report(EquationParseError::DanglingOperator { index }, operator)?;
+                    }
+                    // the parenthesis would close around bars that are still open, like `(|x)|`
+                    while let Some(&(index, abs_depth)) = open_abs.last()
+                        && abs_depth == depth
+                    {
+                        errors.report(EquationParseError::UnbalancedAbs { index }, bar(index))?;
+                        open_abs.pop();
+                    }
+                    // parentheses are only unbalanced here if that was already reported
+                    depth = depth.saturating_sub(1);
+                }
                 vec.push(Syntax::Parenthesis(*parenthesis_type));
             }
-            RawSyntax::Function { start, end } => vec.push(Syntax::Function(
-                FunctionType::from_str(&equation[*start..*end])?,
-            )),
-            RawSyntax::Comma => vec.push(Syntax::Comma),
-            RawSyntax::Abs => vec.push(Syntax::Abs),
+            RawSyntax::Function { start, end } => {
+                let name = &equation[*start..*end];
+                // a variable written right before a function, like in `xsin(x)`,
+                // splits off the longest function name that fits
+                let split = name.char_indices().skip(1).find_map(|(offset, _)| {
+                    options
+                        .function(&name[offset..])
+                        .ok()
+                        .map(|func| (offset, func))
+                });
+
+                match (options.function(name), split) {
+                    (Ok(func), _) => {
+                        allowed(func, token.span(), options, errors)?;
+                        vec.push(Syntax::Function(func));
+                    }
+                    (Err(_), Some((offset, func))) => {
+                        identifier(&name[..offset], *start, options, &mut vec, errors)?;
+                        let index = start + offset;
+                        match options.implicit_multiplication {
+                            true => vec.push(Syntax::Operator(Operator::Mul)),
+                            false => errors.report(
+                                EquationParseError::MissingOperator { index },
+                                index..*end,
+                            )?,
+                        }
+                        allowed(func, index..*end, options, errors)?;
+                        vec.push(Syntax::Function(func));
+                    }
+                    (Err(error), None) => errors.report(error, token.span())?,
+                }
+            }
+            RawSyntax::Comma { .. } => {
+                if let Some(operator) = dangling {
+                    let index = operator.start;
+                    errors.report(EquationParseError::DanglingOperator { index }, operator)?;
+                }
+                vec.push(Syntax::Comma);
+            }
+            RawSyntax::Abs { start, .. } if closes_abs => {
+                if abs_could_open(&ast[i + 1..], next, &open_abs, depth) {
+                    errors.report(
+                        EquationParseError::AmbiguousAbs { index: *start },
+                        token.span(),
+                    )?;
+                }
+                vec.push(Syntax::Parenthesis(ParenthesisType::Close));
+                open_abs.pop();
+            }
+            RawSyntax::Abs { start, .. } => {
+                allowed(FunctionType::Abs, token.span(), options, errors)?;
+                vec.push(Syntax::Function(FunctionType::Abs));
+                vec.push(Syntax::Parenthesis(ParenthesisType::Open));
+                open_abs.push((*start, depth));
+            }
+            // `x²` is `x^2`
+            RawSyntax::Superscript { start, end } => {
+                if !ends_operand {
+                    errors.report(
+                        EquationParseError::DanglingOperator { index: *start },
+                        token.span(),
+                    )?;
+                }
+                let digits: String = equation[*start..*end]
+                    .chars()
+                    .filter_map(tokens::superscript_digit)
+                    .filter_map(|digit| char::from_digit(digit, 10))
+                    .collect();
+                // only a rational type can overflow here, with a long enough exponent
+                let exponent = match T::from_decimal(&digits) {
+                    Ok(exponent) => exponent,
+                    Err(source) => {
+                        let error = EquationParseError::LiteralParseError {
+                            text: equation[*start..*end].to_string(),
+                            start: *start,
+                            end: *end,
+                            source,
+                        };
+                        errors.report(error, token.span())?;
+                        T::zero()
+                    }
+                };
+                vec.push(Syntax::Operator(Operator::Pow));
+                vec.push(Syntax::ValueLit(exponent));
+            }
+            RawSyntax::Relation { start, .. } => errors.report(
+                EquationParseError::UnexpectedRelation { index: *start },
+                token.span(),
+            )?,
         }
 
+        if application.is_none() {
+            for _ in 0..applied {
+                vec.push(Syntax::Parenthesis(ParenthesisType::Close));
+            }
+            applied = 0;
+        }
+        after_application = application.is_some();
+
+        closed_abs = closes_abs;
+        before_previous = previous_token;
         previous_token = Some(*token);
     }
 
+    if let Some(operator) = dangling_operator(previous_token) {
+        let index = operator.start;
+        errors.report(EquationParseError::DanglingOperator { index }, operator)?;
+    }
+
+    for (index, _) in open_abs {
+        errors.report(EquationParseError::UnbalancedAbs { index }, bar(index))?;
+    }
+
     Ok(vec)
 }
 
-fn third_parse<T: num_traits::Float + std::fmt::Debug + std::fmt::Display>(
+/// Parses the text of a literal token: a decimal number like `1_000.5` or `2e-3`,
+/// or a hexadecimal or binary integer like `0xFF` or `0b1010`.
+/// With a `,` `decimal_separator`, `1.000,5` is read as `1_000.5`.
+///
+/// `T` is a float, so hexadecimal and binary literals are parsed as a `u64` integer first,
+/// then converted. Past `2^53` that conversion rounds, like any large `f64` literal would.
+fn parse_literal<T: Numeric>(literal: &str, decimal_separator: char) -> Result<T, LiteralError> {
+    let literal = match decimal_separator {
+        ',' => Cow::Owned(literal.replace('.', "_").replace(',', ".")),
+        _ => Cow::Borrowed(literal),
+    };
+    let literal = &*literal;
+    let (radix, digits) = match literal.get(..2) {
+        Some("0x" | "0X") => (16, &literal[2..]),
+        Some("0b" | "0B") => (2, &literal[2..]),
+        _ => (10, literal),
+    };
+    let digits = strip_separators(digits, radix).ok_or(LiteralError::Separator)?;
+
+    if radix != 10 {
+        let integer = u64::from_str_radix(&digits, radix)?;
+        return T::from_integer(integer).ok_or(LiteralError::Unrepresentable);
+    }
+    T::from_decimal(&digits)
+}
+
+/// Removes the `_` digit separators from a literal, like `1_000` → `1000`.
+/// Every `_` has to be between two digits, so `_1`, `1_`, `1__0` and `1_.5` give `None`.
+fn strip_separators(literal: &str, radix: u32) -> Option<Cow<'_, str>> {
+    if !literal.contains('_') {
+        return Some(Cow::Borrowed(literal));
+    }
+
+    let bytes = literal.as_bytes();
+    let digit = |byte: &u8| char::from(*byte).is_digit(radix);
+    let between_digits =
+        |i: usize| i > 0 && digit(&bytes[i - 1]) && bytes.get(i + 1).is_some_and(digit);
+    let valid = bytes
+        .iter()
+        .enumerate()
+        .all(|(i, byte)| *byte != b'_' || between_digits(i));
+
+    valid.then(|| Cow::Owned(literal.replace('_', "")))
+}
+
+/// Reports `function` if it isn't one of the [`Parser::allowed_functions`], at `span`,
+/// which is where it's called, or the `|` or `√` that stands for it.
+fn allowed<T>(
+    function: FunctionType,
+    span: core::ops::Range<usize>,
+    options: &Parser<T>,
+    errors: &mut impl Report,
+) -> Result<(), EquationParseError> {
+    match &options.allowed_functions {
+        Some(allowed) if !allowed.contains(&function) => {
+            let index = span.start;
+            errors.report(
+                EquationParseError::DisallowedFunction { function, index },
+                span,
+            )
+        }
+        _ => Ok(()),
+    }
+}
+
+/// The function a name like the `sin` in `sin 2` is applied to the value after it as,
+/// with [`Parser::function_application`].
+fn applied_function<T: Numeric>(
+    token: &RawSyntax,
+    next: Option<&RawSyntax>,
+    equation: &str,
+    options: &Parser<T>,
+) -> Option<FunctionType> {
+    let RawSyntax::ValueIdent { start, end } = *token else {
+        return None;
+    };
+    let value_next = matches!(
+        next,
+        Some(RawSyntax::ValueLit { .. } | RawSyntax::ValueIdent { .. })
+    );
+    let name = &equation[start..end];
+    if !options.function_application || !value_next || options.constants.contains_key(name) {
+        return None;
+    }
+
+    // functions of more arguments still need parentheses, but `log` can take a single one
+    let function = options.function(name).ok()?;
+    (function.arity() == 1 || function == FunctionType::Log).then_some(function)
+}
+
+/// Pushes the variable `name`, which starts at the byte offset `start`,
+/// or the literal it stands for if it's one of the [`Parser::constants`].
+fn identifier<'a, T: Numeric>(
+    name: &'a str,
+    start: usize,
+    options: &Parser<T>,
+    vec: &mut Vec<Syntax<'a, T>>,
+    errors: &mut impl Report,
+) -> Result<(), EquationParseError> {
+    let resolve = |name| match options.constants.get(name) {
+        Some(value) => Syntax::ValueLit(*value),
+        None => Syntax::ValueIdent(name),
+    };
+
+    let whole = options.constants.contains_key(name) || is_known_constant(name);
+    if !options.single_letter_variables || whole {
+        vec.push(resolve(name));
+        return Ok(());
+    }
+
+    // every letter is a variable of its own, so `xy` is `x*y`
+    for (i, (offset, letter)) in name.char_indices().enumerate() {
+        let index = start + offset;
+        if i > 0 {
+            match options.implicit_multiplication {
+                true => vec.push(Syntax::Operator(Operator::Mul)),
+                false => errors.report(
+                    EquationParseError::MissingOperator { index },
+                    index..index + letter.len_utf8(),
+                )?,
+            }
+        }
+        vec.push(resolve(&name[offset..offset + letter.len_utf8()]));
+    }
+    Ok(())
+}
+
+/// If `token` is an operator that needs an operand to its right, returns its span.
+fn dangling_operator(token: Option<RawSyntax>) -> Option<core::ops::Range<usize>> {
+    match token {
+        Some(token @ RawSyntax::Operator { kind, .. }) if !kind.is_postfix() => Some(token.span()),
+        _ => None,
+    }
+}
+
+/// Whether a bar that would close an absolute value could just as well open a nested one,
+/// like the second in `|a|b|c|`, which is either `|a|*b*|c|` or `|a*|b|*c|`.
+///
+/// That takes an operand right after it, as the bar then multiplies it implicitly, and enough
+/// bars after it in the same parentheses, `rest`, to also close the nested one.
+fn abs_could_open(
+    rest: &[RawSyntax],
+    next: Option<&RawSyntax>,
+    open_abs: &[(usize, usize)],
+    depth: usize,
+) -> bool {
+    let operand_next = match next {
+        Some(
+            RawSyntax::ValueLit { .. } | RawSyntax::ValueIdent { .. } | RawSyntax::Function { .. },
+        ) => true,
+        Some(RawSyntax::Parenthesis { kind, .. }) => kind.is_open(),
+        Some(RawSyntax::Operator { kind, .. }) => kind.is_prefix(),
+        _ => false,
+    };
+    if !operand_next {
+        return false;
+    }
+
+    let mut nesting = 0usize;
+    let mut bars = 0;
+    for token in rest {
+        match token {
+            RawSyntax::Parenthesis { kind, .. } if kind.is_open() => nesting += 1,
+            RawSyntax::Parenthesis { .. } if nesting == 0 => break,
+            RawSyntax::Parenthesis { .. } => nesting -= 1,
+            RawSyntax::Abs { .. } if nesting == 0 => bars += 1,
+            _ => {}
+        }
+    }
+
+    // opening one more means closing every one open here, and the new one, later
+    let open_here = open_abs
+        .iter()
+        .rev()
+        .take_while(|(_, abs_depth)| *abs_depth == depth)
+        .count();
+    bars > open_here
+}
+
+/// The span of the `|` at `index`.
+fn bar(index: usize) -> core::ops::Range<usize> {
+    index..index + '|'.len_utf8()
+}
+
+/// An entry on the operator stack of the shunting-yard pass.
+#[derive(Debug, Clone, Copy)]
+enum StackEntry {
+    Operator(Operator),
+    Parenthesis,
+    Function(FunctionType),
+}
+
+/// Bookkeeping for an open parenthesis: how many comma-separated arguments it has seen so far,
+/// and how many operands were on the stack when it was opened.
+struct Group {
+    arguments: usize,
+    operands: usize,
+}
+
+/// Builds an [`Entity`] tree out of stage-two [`Syntax`] tokens, from [`resolve`],
+/// the last stage of [`parse`]. Every node below the returned root is allocated in `arena`.
+///
+/// The tokens can come from anywhere, as long as they make a well-formed expression,
+/// otherwise this fails with [`EquationParseError::MalformedExpression`].
+///
+/// Running the stages one by one lets the tokens be changed between them, like renaming
+/// a variable before the tree is built:
+///
+/// ```
+/// use cranelift_equation_parser::{EntityArena, Syntax, build_tree, parse, resolve, tokenize};
+///
+/// let equation = "2*speed";
+/// let tokens: Vec<Syntax<f64>> = resolve(&tokenize(equation)?, equation)?
+///     .into_iter()
+///     .map(|token| match token {
+///         Syntax::ValueIdent("speed") => Syntax::ValueIdent("v"),
+///         token => token,
+///     })
+///     .collect();
+///
+/// let arena = EntityArena::new();
+/// assert_eq!(build_tree(&tokens, &arena)?, parse("2*v", &arena)?);
+/// # Ok::<(), cranelift_equation_parser::EquationParseError>(())
+/// ```
+pub fn build_tree<'a, T: Numeric>(
+    ast: &[Syntax<'a, T>],
+    arena: &'a EntityArena<'a, T>,
+) -> Result<Entity<'a, T>, EquationParseError> {
+    Parser::default().build_tree(ast, arena)
+}
+
+fn third_parse<'a, T: Numeric>(
+    ast: &[Syntax<'a, T>],
+    max_depth: usize,
+    arena: &'a EntityArena<'a, T>,
+) -> Result<Entity<'a, T>, EquationParseError> {
+    let mut tree = TreeBuilder {
+        operands: Vec::with_capacity(ast.len()),
+        arena,
+    };
+    shunting_yard(ast, max_depth, &mut tree)?;
+
+    match (tree.operands.pop(), tree.operands.is_empty()) {
+        (Some(root), true) => Ok(root),
+        _ => Err(EquationParseError::MalformedExpression),
+    }
+}
+
+/// Reorders a stage-two [`Syntax`] stream into Reverse Polish Notation, for stack machines.
+///
+/// Parentheses and commas are dropped, so `3+4*2` becomes `3 4 2 * +`. Every
+/// [`Syntax::Function`] takes exactly [`FunctionType::arity`] operands off the stack,
+/// so `log(x)` is written out with its default base as `10 x log`.
+pub fn to_rpn<'a, T: Numeric>(
+    ast: &[Syntax<'a, T>],
+) -> Result<Vec<Syntax<'a, T>>, EquationParseError> {
+    let mut rpn = RpnBuilder {
+        output: Vec::with_capacity(ast.len()),
+        starts: Vec::new(),
+    };
+    // the output is flat, so it can nest as deep as it likes
+    shunting_yard(ast, usize::MAX, &mut rpn)?;
+
+    match rpn.starts.len() {
+        1 => Ok(rpn.output),
+        _ => Err(EquationParseError::MalformedExpression),
+    }
+}
+
+/// Renders [`to_rpn`] as a space-separated string, like `3+4` → `3 4 +`.
+/// Unary minus is written `neg`, so it can't be mistaken for a subtraction: `-x^2` → `x 2 ^ neg`.
+pub fn to_postfix_string<T: Numeric + core::fmt::Display>(
     ast: &[Syntax<'_, T>],
-) {
+) -> Result<String, EquationParseError> {
+    use core::fmt::Write;
+
+    let mut out = String::new();
+    for (i, token) in to_rpn(ast)?.iter().enumerate() {
+        if i > 0 {
+            out.push(' ');
+        }
+        let _ = match token {
+            Syntax::ValueLit(val) => write!(out, "{val}"),
+            Syntax::ValueIdent(ident) => write!(out, "{ident}"),
+            Syntax::Operator(Operator::Neg) => write!(out, "neg"),
+            Syntax::Operator(op) => write!(out, "{op}"),
+            Syntax::Function(func) => write!(out, "{func}"),
+            Syntax::Parenthesis(_) | Syntax::Comma => unreachable!("RPN has no groupings"),
+        };
+    }
+    Ok(out)
+}
+
+/// Receives the output of the shunting-yard pass, in postfix order.
+trait Postfix<'a, T> {
+    /// A literal or identifier.
+    fn operand(&mut self, token: Syntax<'a, T>);
+    fn operator(&mut self, op: Operator) -> Result<(), EquationParseError>;
+    fn function(&mut self, func: FunctionType, arguments: usize) -> Result<(), EquationParseError>;
+    /// How many finished operands are waiting to be consumed.
+    fn operands(&self) -> usize;
+}
+
+/// Builds an [`Entity`] tree out of the shunting-yard output.
+struct TreeBuilder<'a, T> {
+    operands: Vec<Entity<'a, T>>,
+    arena: &'a EntityArena<'a, T>,
+}
+
+impl<'a, T: Numeric> Postfix<'a, T> for TreeBuilder<'a, T> {
+    fn operand(&mut self, token: Syntax<'a, T>) {
+        self.operands.push(match token {
+            Syntax::ValueLit(val) => Entity::ValueLit(val),
+            Syntax::ValueIdent(ident) => Entity::Value(ident),
+            _ => unreachable!("only literals and identifiers are operands"),
+        });
+    }
+
+    fn operator(&mut self, op: Operator) -> Result<(), EquationParseError> {
+        apply_operator(op, &mut self.operands, self.arena)
+    }
+
+    fn function(&mut self, func: FunctionType, arguments: usize) -> Result<(), EquationParseError> {
+        apply_function(func, arguments, &mut self.operands, self.arena)
+    }
+
+    fn operands(&self) -> usize {
+        self.operands.len()
+    }
+}
+
+/// Collects the shunting-yard output as a postfix token stream.
+struct RpnBuilder<'a, T> {
+    output: Vec<Syntax<'a, T>>,
+    /// Where each finished operand starts in `output`.
+    starts: Vec<usize>,
+}
+
+impl<T> RpnBuilder<'_, T> {
+    /// Merges the last `count` operands into one.
+    fn consume(&mut self, count: usize) -> Result<(), EquationParseError> {
+        if count > self.starts.len() {
+            return Err(EquationParseError::MalformedExpression);
+        }
+
+        let first = self.starts[self.starts.len() - count];
+        self.starts.truncate(self.starts.len() - count);
+        self.starts.push(first);
+        Ok(())
+    }
+}
+
+impl<'a, T: Numeric> Postfix<'a, T> for RpnBuilder<'a, T> {
+    fn operand(&mut self, token: Syntax<'a, T>) {
+        self.starts.push(self.output.len());
+        self.output.push(token);
+    }
+
+    fn operator(&mut self, op: Operator) -> Result<(), EquationParseError> {
+        match op.is_prefix() || op.is_postfix() {
+            true => self.consume(1)?,
+            false => self.consume(2)?,
+        }
+        self.output.push(Syntax::Operator(op));
+        Ok(())
+    }
+
+    fn function(
+        &mut self,
+        func: FunctionType,
+        mut arguments: usize,
+    ) -> Result<(), EquationParseError> {
+        if func == FunctionType::Log && arguments == 1 {
+            let start = self
+                .starts
+                .pop()
+                .ok_or(EquationParseError::MalformedExpression)?;
+            self.output
+                .insert(start, Syntax::ValueLit(T::from_integer(10).unwrap()));
+            self.starts.extend([start, start + 1]);
+            arguments = 2;
+        }
+
+        if arguments != func.arity() {
+            return Err(EquationParseError::MalformedExpression);
+        }
+
+        self.consume(arguments)?;
+        self.output.push(Syntax::Function(func));
+        Ok(())
+    }
+
+    fn operands(&self) -> usize {
+        self.starts.len()
+    }
 }
 
-fn print<T: num_traits::Float + std::fmt::Debug + std::fmt::Display>(ast: &[Syntax<'_, T>]) {
+/// Runs the shunting-yard pass over `ast`, failing with [`EquationParseError::NestedTooDeep`]
+/// once more than `max_depth` entries wait on the operator stack.
+fn shunting_yard<'a, T: Copy>(
+    ast: &[Syntax<'a, T>],
+    max_depth: usize,
+    output: &mut impl Postfix<'a, T>,
+) -> Result<(), EquationParseError> {
+    if ast.is_empty() {
+        return Err(EquationParseError::EmptyInput);
+    }
+
+    let mut operators: Vec<StackEntry> = Vec::new();
+    let mut groups: Vec<Group> = Vec::new();
+
+    for (i, token) in ast.iter().enumerate() {
+        // every entry is a level of the tree still to be built, everything else is reduced
+        if operators.len() > max_depth {
+            return Err(EquationParseError::NestedTooDeep);
+        }
+
+        match token {
+            Syntax::ValueLit(_) | Syntax::ValueIdent(_) => output.operand(*token),
+            Syntax::Function(func) => operators.push(StackEntry::Function(*func)),
+            // prefix operators have no left operand to reduce
+            Syntax::Operator(op) if op.is_prefix() => operators.push(StackEntry::Operator(*op)),
+            // postfix operators bind tighter than anything, so they apply right away
+            Syntax::Operator(op) if op.is_postfix() => output.operator(*op)?,
+            Syntax::Operator(op) => {
+                while let Some(StackEntry::Operator(top)) = operators.last() {
+                    let top = *top;
+                    if top.precedence() > op.precedence()
+                        || (top.precedence() == op.precedence() && !op.is_right_associative())
+                    {
+                        operators.pop();
+                        output.operator(top)?;
+                    } else {
+                        break;
+                    }
+                }
+                operators.push(StackEntry::Operator(*op));
+            }
+            Syntax::Parenthesis(p) if p.is_open() => {
+                operators.push(StackEntry::Parenthesis);
+                groups.push(Group {
+                    arguments: 1,
+                    operands: output.operands(),
+                });
+            }
+            Syntax::Parenthesis(_) => {
+                reduce_group(&mut operators, output)?;
+                operators.pop();
+
+                let group = groups
+                    .pop()
+                    .ok_or(EquationParseError::MalformedExpression)?;
+                // nothing between the parentheses, like in `sin()`
+                let empty = matches!(ast[..i].last(), Some(Syntax::Parenthesis(p)) if p.is_open());
+                let arguments = if empty { 0 } else { group.arguments };
+                if output.operands() != group.operands + arguments {
+                    return Err(EquationParseError::MalformedExpression);
+                }
+
+                if let Some(StackEntry::Function(func)) = operators.last() {
+                    let func = *func;
+                    operators.pop();
+
+                    // `log` can leave out its base
+                    let optional = func == FunctionType::Log && arguments == 1;
+                    if arguments != func.arity() && !optional {
+                        return Err(EquationParseError::WrongArgumentCount {
+                            function: func,
+                            expected: func.arity(),
+                            found: arguments,
+                        });
+                    }
+                    // `pow(x, 2)` is just `x^2`
+                    match func.operator() {
+                        Some(op) => output.operator(op)?,
+                        None => output.function(func, arguments)?,
+                    }
+                } else if arguments != 1 {
+                    return Err(EquationParseError::MalformedExpression);
+                }
+            }
+            Syntax::Comma => {
+                reduce_group(&mut operators, output)?;
+                match groups.last_mut() {
+                    Some(group) => group.arguments += 1,
+                    None => return Err(EquationParseError::MalformedExpression),
+                }
+            }
+        }
+    }
+
+    while let Some(entry) = operators.pop() {
+        match entry {
+            StackEntry::Operator(op) => output.operator(op)?,
+            _ => return Err(EquationParseError::MalformedExpression),
+        }
+    }
+
+    Ok(())
+}
+
+/// Applies operators until the innermost open parenthesis is at the top of the stack.
+fn reduce_group<'a, T>(
+    operators: &mut Vec<StackEntry>,
+    output: &mut impl Postfix<'a, T>,
+) -> Result<(), EquationParseError> {
+    loop {
+        match operators.last() {
+            Some(StackEntry::Operator(op)) => {
+                let op = *op;
+                operators.pop();
+                output.operator(op)?;
+            }
+            Some(StackEntry::Parenthesis) => return Ok(()),
+            _ => return Err(EquationParseError::MalformedExpression),
+        }
+    }
+}
+
+fn apply_operator<'a, T: Numeric>(
+    op: Operator,
+    operands: &mut Vec<Entity<'a, T>>,
+    arena: &'a EntityArena<'a, T>,
+) -> Result<(), EquationParseError> {
+    let rhs = operands
+        .pop()
+        .ok_or(EquationParseError::MalformedExpression)?;
+    let rhs = arena.alloc(rhs);
+
+    match op {
+        Operator::Neg => {
+            operands.push(Entity::Operation(Operation::Neg(rhs)));
+            return Ok(());
+        }
+        Operator::Factorial => {
+            operands.push(Entity::Operation(Operation::Factorial(rhs)));
+            return Ok(());
+        }
+        // there's no percent operation, so `x%` is `x/100`
+        Operator::Percent => {
+            let hundred = arena.alloc(Entity::ValueLit(T::from_integer(100).unwrap()));
+            operands.push(Entity::Operation(Operation::Div(rhs, hundred)));
+            return Ok(());
+        }
+        // there's no square root operation, so `√x` is `sqrt(x)`
+        Operator::Sqrt => {
+            operands.push(Entity::Function(Function::Sqrt(rhs)));
+            return Ok(());
+        }
+        _ => {}
+    }
+
+    let lhs = operands
+        .pop()
+        .ok_or(EquationParseError::MalformedExpression)?;
+    let lhs = arena.alloc(lhs);
+
+    operands.push(Entity::Operation(match op {
+        Operator::Add => Operation::Add(lhs, rhs),
+        Operator::Sub => Operation::Sub(lhs, rhs),
+        Operator::Mul => Operation::Mul(lhs, rhs),
+        Operator::Div => Operation::Div(lhs, rhs),
+        Operator::FloorDiv => Operation::FloorDiv(lhs, rhs),
+        Operator::Pow => Operation::Pow(lhs, rhs),
+        Operator::Rem => Operation::Rem(lhs, rhs),
+        Operator::Neg | Operator::Factorial | Operator::Percent | Operator::Sqrt => {
+            unreachable!()
+        }
+    }));
+
+    Ok(())
+}
+
+fn apply_function<'a, T>(
+    func: FunctionType,
+    arguments: usize,
+    operands: &mut Vec<Entity<'a, T>>,
+    arena: &'a EntityArena<'a, T>,
+) -> Result<(), EquationParseError> {
+    if arguments > operands.len() {
+        return Err(EquationParseError::MalformedExpression);
+    }
+
+    let args: Vec<&'a Entity<'a, T>> = operands
+        .drain(operands.len() - arguments..)
+        .map(|arg| arena.alloc(arg))
+        .collect();
+
+    let function =
+        Function::from_arguments(func, &args[..]).ok_or(EquationParseError::MalformedExpression)?;
+
+    operands.push(Entity::Function(function));
+    Ok(())
+}
+
+/// Prints a stage-two token stream to stdout. Mostly useful for debugging.
+#[cfg(feature = "std")]
+pub fn print<T: Numeric + core::fmt::Display>(ast: &[Syntax<'_, T>]) {
     for token in ast {
         match token {
             Syntax::ValueLit(val) => print!("{} ", val),
             Syntax::ValueIdent(ident) => print!("{} ", ident),
-            Syntax::Operator(op) => match op {
-                Operator::Add => print!("+ "),
-                Operator::Sub => print!("- "),
-                Operator::Mul => print!("* "),
-                Operator::Div => print!("/ "),
-                Operator::Pow => print!("^ "),
-            },
-            Syntax::Parenthesis(p) => match p {
-                ParenthesisType::Open => print!("( "),
-                ParenthesisType::Close => print!(") "),
-                ParenthesisType::OpenSquare => print!("[ "),
-                ParenthesisType::CloseSquare => print!("] "),
-                ParenthesisType::OpenCurly => print!("{{ "),
-                ParenthesisType::CloseCurly => print!("}} "),
-            },
+            Syntax::Operator(op) => print!("{} ", op),
+            Syntax::Parenthesis(p) => print!("{} ", p),
             Syntax::Comma => print!(", "),
-            Syntax::Abs => print!("| "),
-            Syntax::Function(func) => print!("{:?}", func),
+            Syntax::Function(func) => print!("{}", func),
         }
     }
     println!();
 }
 
-#[derive(Debug, Error)]
+#[derive(Debug, Clone, Error)]
 pub enum EquationParseError {
-    #[error("Bad literal")]
-    LiteralParseError,
+    /// `start..end` is the byte range of `text` in the equation,
+    /// and `source` is why it couldn't be parsed.
+    #[error("Bad literal {text} at {start}..{end}")]
+    LiteralParseError {
+        text: String,
+        start: usize,
+        end: usize,
+        source: LiteralError,
+    },
     #[error("Unknown function")]
     UnknownFunction,
     #[error("No character match")]
     NoMatch,
+    #[error("Malformed expression")]
+    MalformedExpression,
+    /// The equation is empty, or only whitespace and comments.
+    #[error("Empty equation")]
+    EmptyInput,
+    /// `index` is the byte offset of `ch`, a character that can't start or be part of any token,
+    /// like the `@` in `2@3`.
+    #[error("Unexpected character {ch:?} at {index}")]
+    UnexpectedCharacter { ch: char, index: usize },
+    /// `index` is the byte offset of the offending parenthesis.
+    #[error("Unbalanced parenthesis at {index}")]
+    UnbalancedParenthesis { index: usize },
+    /// `index` is the byte offset of the offending `|`.
+    #[error("Unbalanced absolute value bar at {index}")]
+    UnbalancedAbs { index: usize },
+    /// `index` is the byte offset of a `|` that could either close an absolute value, or open
+    /// a nested one, like the second in `|a|b|c|`, which could be `|a|*b*|c|` or `|a*|b|*c|`.
+    ///
+    /// A bar opens an absolute value unless it follows an operand, like the second in `|a+|b||`,
+    /// and closes the innermost one otherwise. It's only ambiguous when an operand follows it,
+    /// and enough bars come after it to close a nested one too. Writing the multiplication,
+    /// like in `|a|*b*|c|`, or using `abs()` resolves it.
+    #[error("Ambiguous absolute value bar at {index}")]
+    AmbiguousAbs { index: usize },
+    /// `index` is the byte offset of an operator that's missing one of its operands,
+    /// like in `2+` or `*3`.
+    #[error("Operator at {index} is missing an operand")]
+    DanglingOperator { index: usize },
+    /// [`parse_equation`] needs exactly one relation, like `=` or `<=`.
+    #[error("Expected a relation like = or <=")]
+    MissingRelation,
+    /// `index` is the byte offset of a relation where none can go: in [`parse`],
+    /// or a second one in [`parse_equation`].
+    #[error("Unexpected relation at {index}")]
+    UnexpectedRelation { index: usize },
+    /// `index` is the byte offset of an operator that was turned off in the [`Parser`],
+    /// like `!` with [`Parser::allow_factorial`].
+    #[error("Unsupported operator at {index}")]
+    UnsupportedOperator { index: usize },
+    /// `index` is the byte offset of the first parenthesis nested deeper than
+    /// [`Parser::max_depth`].
+    #[error("Parentheses nested too deep at {index}")]
+    TooDeep { index: usize },
+    /// The equation nests deeper than [`Parser::max_depth`] through more than parentheses,
+    /// like the minuses in `----x` or nested `|x|`. Unlike [`EquationParseError::TooDeep`],
+    /// it has no position, as it's only noticed while building the tree.
+    #[error("Expression nested too deep")]
+    NestedTooDeep,
+    /// `index` is the byte offset of a `[`, `]`, `{` or `}`, with [`Parser::only_round_parens`].
+    #[error("Unsupported bracket at {index}")]
+    UnsupportedBracket { index: usize },
+    /// `index` is the byte offset of the opening parenthesis of an empty pair, like in `2*()`.
+    /// A function called without arguments, like `sin()`, is an
+    /// [`EquationParseError::WrongArgumentCount`] instead.
+    #[error("Empty parentheses at {index}")]
+    EmptyParentheses { index: usize },
+    /// `index` is the byte offset of an operand that directly follows another one, like the `y`
+    /// in `x y`, when [`Parser::implicit_multiplication`] is disabled.
+    #[error("Missing operator before {index}")]
+    MissingOperator { index: usize },
+    /// A function was called with the wrong number of arguments, like `sin(1, 2)`.
+    /// `expected` is [`FunctionType::arity`], although `log` also takes a single argument.
+    #[error("{function} takes {expected} arguments, but {found} were given")]
+    WrongArgumentCount {
+        function: FunctionType,
+        expected: usize,
+        found: usize,
+    },
+    /// `index` is the byte offset of a call to `function`, which isn't one of the
+    /// [`Parser::allowed_functions`]. For `abs` and `sqrt`, it can also be a `|` or `√`.
+    #[error("Disallowed function {function} at {index}")]
+    DisallowedFunction {
+        function: FunctionType,
+        index: usize,
+    },
+    /// `index` is the byte offset of a binding in [`parse_with_bindings`] that isn't a name,
+    /// `=` and a value, like `2=x`, or that binds a name that's already bound.
+    #[error("Malformed binding at {index}")]
+    MalformedBinding { index: usize },
+    /// The binding `name` in [`parse_with_bindings`] refers to itself,
+    /// directly like `a=a+1`, or through other bindings like `a=b, b=a`.
+    #[error("Binding {name} refers to itself")]
+    CyclicBinding { name: String },
+}
+
+// errors own their data, so they can be collected and sent to other threads
+const _: () = {
+    const fn assert_error<E: Clone + Send + Sync + 'static>() {}
+    assert_error::<EquationParseError>();
+    assert_error::<EvalError>();
+};
+
+/// Why a literal couldn't be parsed, the [`source`](core::error::Error::source) of an
+/// [`EquationParseError::LiteralParseError`].
+#[derive(Debug, Clone, Error)]
+pub enum LiteralError {
+    /// A `_` digit separator that isn't between two digits, like in `1_` or `1__0`.
+    #[error("Misplaced digit separator")]
+    Separator,
+    /// A bad decimal literal, like `1.2.3`.
+    #[error("Bad decimal literal")]
+    Float(#[from] core::num::ParseFloatError),
+    /// A bad hexadecimal or binary literal, like one too large for a `u64`.
+    #[error("Bad integer literal")]
+    Integer(#[from] core::num::ParseIntError),
+    /// A literal the float type can't hold.
+    #[error("Literal doesn't fit the float type")]
+    Unrepresentable,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn keeps_constants_whole_for_any_type() {
+        let arena = EntityArena::new();
+        let parser = Parser::<f32>::default().single_letter_variables(true);
+        let entity = parser.parse("2pi", &arena).unwrap();
+        assert_eq!(
+            entity,
+            Entity::Operation(Operation::Mul(&Entity::ValueLit(2.0), &Entity::Value("pi")))
+        );
+        let no_vars: &[(&str, f32)] = &[];
+        assert_eq!(eval(&entity, no_vars).unwrap(), 2.0 * core::f32::consts::PI);
+    }
+
+    #[test]
+    fn parses_and_evaluates_in_f32() {
+        let arena = EntityArena::new();
+        let no_vars: &[(&str, f32)] = &[];
+        let eval = |equation| eval(&parse::<f32>(equation, &arena).unwrap(), no_vars).unwrap();
+
+        assert_eq!(eval("0.1"), 0.1f32);
+        assert_eq!(eval("tau/2"), core::f32::consts::PI);
+        assert!(eval("34!").is_finite());
+        assert_eq!(eval("35!"), f32::INFINITY);
+    }
+
+    #[test]
+    fn builds_tree_by_precedence() {
+        let arena = EntityArena::new();
+        assert_eq!(
+            parse::<f64>("2+3*4", &arena).unwrap(),
+            Entity::Operation(Operation::Add(
+                &Entity::ValueLit(2.0),
+                &Entity::Operation(Operation::Mul(
+                    &Entity::ValueLit(3.0),
+                    &Entity::ValueLit(4.0)
+                ))
+            ))
+        );
+        assert_eq!(
+            parse::<f64>("2*3-4/x", &arena).unwrap(),
+            Entity::Operation(Operation::Sub(
+                &Entity::Operation(Operation::Mul(
+                    &Entity::ValueLit(2.0),
+                    &Entity::ValueLit(3.0)
+                )),
+                &Entity::Operation(Operation::Div(&Entity::ValueLit(4.0), &Entity::Value("x")))
+            ))
+        );
+    }
+
+    #[test]
+    fn builds_tree_by_associativity() {
+        let arena = EntityArena::new();
+        assert_eq!(
+            parse::<f64>("2^3^2", &arena).unwrap(),
+            Entity::Operation(Operation::Pow(
+                &Entity::ValueLit(2.0),
+                &Entity::Operation(Operation::Pow(
+                    &Entity::ValueLit(3.0),
+                    &Entity::ValueLit(2.0)
+                ))
+            ))
+        );
+        assert_eq!(
+            parse::<f64>("8-4-2", &arena).unwrap(),
+            Entity::Operation(Operation::Sub(
+                &Entity::Operation(Operation::Sub(
+                    &Entity::ValueLit(8.0),
+                    &Entity::ValueLit(4.0)
+                )),
+                &Entity::ValueLit(2.0)
+            ))
+        );
+    }
+
+    #[test]
+    fn rejects_malformed_token_streams() {
+        let arena = EntityArena::new();
+        let tokens = [
+            Syntax::<f64>::ValueLit(2.0),
+            Syntax::ValueLit(3.0),
+            Syntax::Operator(Operator::Add),
+            Syntax::Operator(Operator::Mul),
+        ];
+        assert!(matches!(
+            build_tree(&tokens, &arena),
+            Err(EquationParseError::MalformedExpression)
+        ));
+        assert!(matches!(
+            build_tree::<f64>(&[], &arena),
+            Err(EquationParseError::EmptyInput)
+        ));
+    }
+
+    fn unbalanced_at(equation: &str) -> Option<usize> {
+        let arena = EntityArena::new();
+        match parse::<f64>(equation, &arena) {
+            Err(EquationParseError::UnbalancedParenthesis { index }) => Some(index),
+            _ => None,
+        }
+    }
+
+    #[test]
+    fn reports_unbalanced_parentheses() {
+        assert_eq!(unbalanced_at("(2+3"), Some(0));
+        assert_eq!(unbalanced_at("2+3)"), Some(3));
+        assert_eq!(unbalanced_at("(2]"), Some(2));
+        assert_eq!(unbalanced_at("{[2+3)]}"), Some(5));
+        assert_eq!(unbalanced_at("{[2+3]*(4)}"), None);
+    }
+
+    #[test]
+    fn reports_bad_literal_span() {
+        let arena = EntityArena::new();
+        let equation = "1.2.3+x";
+        match parse::<f64>(equation, &arena) {
+            Err(EquationParseError::LiteralParseError { start, end, .. }) => {
+                assert_eq!((start, end), (0, 5));
+                assert_eq!(&equation[start..end], "1.2.3");
+            }
+            other => panic!("expected a literal error, got {other:?}"),
+        }
+    }
+
+    #[test]
+    fn parses_unary_minus_and_plus() {
+        let arena = EntityArena::new();
+        let five = &Entity::ValueLit(5.0);
+        assert_eq!(
+            parse::<f64>("-5", &arena).unwrap(),
+            Entity::Operation(Operation::Neg(five))
+        );
+        assert_eq!(parse::<f64>("+5", &arena).unwrap(), *five);
+        assert_eq!(
+            parse::<f64>("3*-2", &arena).unwrap(),
+            Entity::Operation(Operation::Mul(
+                &Entity::ValueLit(3.0),
+                &Entity::Operation(Operation::Neg(&Entity::ValueLit(2.0)))
+            ))
+        );
+        assert_eq!(
+            parse::<f64>("-(x+1)", &arena).unwrap(),
+            Entity::Operation(Operation::Neg(&Entity::Operation(Operation::Add(
+                &Entity::Value("x"),
+                &Entity::ValueLit(1.0)
+            ))))
+        );
+        assert_eq!(
+            parse::<f64>("2--3", &arena).unwrap(),
+            Entity::Operation(Operation::Sub(
+                &Entity::ValueLit(2.0),
+                &Entity::Operation(Operation::Neg(&Entity::ValueLit(3.0)))
+            ))
+        );
+    }
+
+    /// The value of an equation without variables.
+    fn value(equation: &str) -> f64 {
+        let arena = EntityArena::new();
+        let no_vars: &[(&str, f64)] = &[];
+        eval(&parse(equation, &arena).unwrap(), no_vars).unwrap()
+    }
+
+    #[test]
+    fn evaluates_unary_minus() {
+        assert_eq!(value("2--3"), 5.0);
+        assert_eq!(value("-2^2"), -4.0);
+        assert_eq!(value("2^-1"), 0.5);
+        assert_eq!(value("max(-1, -2)"), -1.0);
+    }
+
+    #[test]
+    fn parses_factorials() {
+        assert_eq!(value("5!"), 120.0);
+        assert_eq!(value("(2+1)!"), 6.0);
+        assert_eq!(value("3!+1"), 7.0);
+        assert_eq!(value("2^3!"), 64.0);
+
+        let arena = EntityArena::new();
+        assert_eq!(
+            parse::<f64>("n!", &arena).unwrap(),
+            Entity::Operation(Operation::Factorial(&Entity::Value("n")))
+        );
+        let parser = Parser::<f64>::default().allow_factorial(false);
+        assert!(matches!(
+            parser.parse("5!", &arena),
+            Err(EquationParseError::UnsupportedOperator { index: 1 })
+        ));
+    }
+
+    #[test]
+    fn parses_remainders() {
+        assert_eq!(value("7%3"), 1.0);
+        assert_eq!(value("10%4+1"), 3.0);
+        assert_eq!(value("2*7%4"), 2.0);
+        assert_eq!(value("-7%3"), -1.0);
+    }
+
+    #[test]
+    fn compares_tokens() {
+        assert_eq!(
+            parse_tokens::<f64>("2+x").unwrap(),
+            [
+                Syntax::ValueLit(2.0),
+                Syntax::Operator(Operator::Add),
+                Syntax::ValueIdent("x")
+            ]
+        );
+        assert_ne!(
+            parse_tokens::<f64>("sin(x)").unwrap(),
+            parse_tokens::<f64>("cos(x)").unwrap()
+        );
+
+        fn assert_eq<T: Eq>() {}
+        assert_eq::<RawSyntax>();
+        assert_eq::<Operator>();
+        assert_eq::<ParenthesisType>();
+        assert_eq::<FunctionType>();
+    }
+
+    #[test]
+    fn tokenizes_with_byte_ranges() {
+        assert_eq!(
+            tokenize("2+x").unwrap(),
+            [
+                RawSyntax::ValueLit { start: 0, end: 1 },
+                RawSyntax::Operator {
+                    kind: Operator::Add,
+                    start: 1,
+                    end: 2
+                },
+                RawSyntax::ValueIdent { start: 2, end: 3 },
+            ]
+        );
+
+        // ranges are byte offsets, so they index the equation even past multibyte characters
+        let equation = "√x";
+        let tokens = tokenize(equation).unwrap();
+        assert_eq!(&equation[tokens[1].span()], "x");
+    }
+
+    #[test]
+    fn spans_every_token_in_order() {
+        let equation = "2 + sin(x)";
+        let tokens = tokenize(equation).unwrap();
+        assert_eq!(tokens.len(), 6);
+        for pair in tokens.windows(2) {
+            assert!(pair[0].span().end <= pair[1].span().start);
+        }
+        let text: Vec<&str> = tokens.iter().map(|t| &equation[t.span()]).collect();
+        assert_eq!(text, ["2", "+", "sin", "(", "x", ")"]);
+    }
+
+    #[test]
+    fn multiplies_implicitly() {
+        let mul = Syntax::Operator(Operator::Mul);
+        let open = Syntax::Parenthesis(ParenthesisType::Open);
+        let close = Syntax::Parenthesis(ParenthesisType::Close);
+        assert_eq!(
+            parse_tokens::<f64>("2x").unwrap(),
+            [Syntax::ValueLit(2.0), mul, Syntax::ValueIdent("x")]
+        );
+        assert_eq!(
+            parse_tokens::<f64>("x2").unwrap(),
+            [Syntax::ValueIdent("x"), mul, Syntax::ValueLit(2.0)]
+        );
+        assert_eq!(
+            parse_tokens::<f64>("2(3)").unwrap(),
+            [
+                Syntax::ValueLit(2.0),
+                mul,
+                open,
+                Syntax::ValueLit(3.0),
+                close
+            ]
+        );
+        assert_eq!(
+            parse_tokens::<f64>("(2)(3)").unwrap(),
+            [
+                open,
+                Syntax::ValueLit(2.0),
+                close,
+                mul,
+                open,
+                Syntax::ValueLit(3.0),
+                close
+            ]
+        );
+        assert_eq!(value("(2)(3)"), 6.0);
+        assert_eq!(value("2(3+1)"), 8.0);
+
+        let arena = EntityArena::new();
+        let parser = Parser::<f64>::default().implicit_multiplication(false);
+        assert!(matches!(
+            parser.parse("2x", &arena),
+            Err(EquationParseError::MissingOperator { index: 1 })
+        ));
+    }
+
+    #[test]
+    fn pairs_absolute_value_bars() {
+        let arena = EntityArena::new();
+        let x = &Entity::Value("x");
+        assert_eq!(
+            parse::<f64>("|x|", &arena).unwrap(),
+            Entity::Function(Function::Abs(x))
+        );
+        assert_eq!(
+            parse::<f64>("2|x|+1", &arena).unwrap(),
+            Entity::Operation(Operation::Add(
+                &Entity::Operation(Operation::Mul(
+                    &Entity::ValueLit(2.0),
+                    &Entity::Function(Function::Abs(x))
+                )),
+                &Entity::ValueLit(1.0)
+            ))
+        );
+        assert_eq!(
+            parse::<f64>("|a+|b||", &arena).unwrap(),
+            Entity::Function(Function::Abs(&Entity::Operation(Operation::Add(
+                &Entity::Value("a"),
+                &Entity::Function(Function::Abs(&Entity::Value("b")))
+            ))))
+        );
+        assert_eq!(value("|-3|"), 3.0);
+        assert_eq!(value("|2-5|*|1-2|"), 3.0);
+        assert!(parse::<f64>("|x", &arena).is_err());
+        assert!(parse::<f64>("(|x)|", &arena).is_err());
+    }
+
+    #[test]
+    fn rejects_dangling_operators() {
+        fn dangling_at(equation: &str) -> Option<usize> {
+            match parse_tokens::<f64>(equation) {
+                Err(EquationParseError::DanglingOperator { index }) => Some(index),
+                _ => None,
+            }
+        }
+        assert_eq!(dangling_at("2+"), Some(1));
+        assert_eq!(dangling_at("/2"), Some(0));
+        assert_eq!(dangling_at("*3"), Some(0));
+        assert_eq!(dangling_at("2*/3"), Some(2));
+        // `**` is a power, and the second `+` in `2++3` is a prefix
+        assert_eq!(dangling_at("2**3"), None);
+        assert_eq!(dangling_at("2++3"), None);
+        assert_eq!(dangling_at("(2+)"), Some(2));
+        assert_eq!(dangling_at("2*-3"), None);
+    }
+
+    #[test]
+    fn reorders_into_rpn() {
+        let rpn = |equation| to_rpn(&parse_tokens::<f64>(equation).unwrap()).unwrap();
+        assert_eq!(
+            rpn("3+4*2"),
+            [
+                Syntax::ValueLit(3.0),
+                Syntax::ValueLit(4.0),
+                Syntax::ValueLit(2.0),
+                Syntax::Operator(Operator::Mul),
+                Syntax::Operator(Operator::Add),
+            ]
+        );
+        assert_eq!(
+            rpn("(1+2)*3"),
+            [
+                Syntax::ValueLit(1.0),
+                Syntax::ValueLit(2.0),
+                Syntax::Operator(Operator::Add),
+                Syntax::ValueLit(3.0),
+                Syntax::Operator(Operator::Mul),
+            ]
+        );
+        assert_eq!(
+            rpn("max(x, 1)"),
+            [
+                Syntax::ValueIdent("x"),
+                Syntax::ValueLit(1.0),
+                Syntax::Function(FunctionType::Max),
+            ]
+        );
+        assert_eq!(
+            rpn("log(x)"),
+            [
+                Syntax::ValueLit(10.0),
+                Syntax::ValueIdent("x"),
+                Syntax::Function(FunctionType::Log),
+            ]
+        );
+    }
+
+    #[test]
+    fn parses_relations() {
+        let relation = |kind, start, end| RawSyntax::Relation { kind, start, end };
+        assert_eq!(tokenize("x=4").unwrap()[1], relation(RelationOp::Eq, 1, 2));
+        assert_eq!(tokenize("x<=4").unwrap()[1], relation(RelationOp::Le, 1, 3));
+        assert_eq!(tokenize("x>=4").unwrap()[1], relation(RelationOp::Ge, 1, 3));
+        assert_eq!(tokenize("x>4").unwrap()[1], relation(RelationOp::Gt, 1, 2));
+        assert_eq!(tokenize("x>=4").unwrap().len(), 3);
+
+        let arena = EntityArena::new();
+        let equation = parse_equation::<f64>("x^2 = 4", &arena).unwrap();
+        assert_eq!(equation.lhs, parse("x^2", &arena).unwrap());
+        assert_eq!(equation.op, RelationOp::Eq);
+        assert_eq!(equation.rhs, Entity::ValueLit(4.0));
+
+        assert!(matches!(
+            parse_equation::<f64>("x + 1", &arena),
+            Err(EquationParseError::MissingRelation)
+        ));
+        assert!(matches!(
+            parse_equation::<f64>("0 < x < 1", &arena),
+            Err(EquationParseError::UnexpectedRelation { index: 6 })
+        ));
+        assert!(parse::<f64>("x = 4", &arena).is_err());
+    }
+
+    #[test]
+    fn reads_digit_separators() {
+        assert_eq!(value("1_000_000"), 1e6);
+        assert_eq!(value("2.718_5"), 2.7185);
+        assert_eq!(value("1_0.0_1"), 10.01);
+
+        for equation in ["1_", "1__0", "1._5", "1_.5", "2+3_"] {
+            assert!(
+                matches!(
+                    parse_tokens::<f64>(equation),
+                    Err(EquationParseError::LiteralParseError {
+                        source: LiteralError::Separator,
+                        ..
+                    })
+                ),
+                "{equation} should be rejected"
+            );
+        }
+    }
+
+    #[test]
+    fn reads_hexadecimal_and_binary_integers() {
+        assert_eq!(value("0xFF"), 255.0);
+        assert_eq!(value("0b1010"), 10.0);
+        assert_eq!(value("0XfF + 0B1"), 256.0);
+        assert_eq!(value("0xFF_FF"), 65535.0);
+        assert_eq!(
+            tokenize("0xFF").unwrap(),
+            [RawSyntax::ValueLit { start: 0, end: 4 }]
+        );
+        // without digits, the `b` is a variable
+        assert_eq!(
+            parse_tokens::<f64>("0b").unwrap(),
+            [
+                Syntax::ValueLit(0.0),
+                Syntax::Operator(Operator::Mul),
+                Syntax::ValueIdent("b")
+            ]
+        );
+        assert!(matches!(
+            parse_tokens::<f64>("0x1_0000_0000_0000_0000"),
+            Err(EquationParseError::LiteralParseError {
+                source: LiteralError::Integer(_),
+                ..
+            })
+        ));
+    }
+
+    #[test]
+    fn checks_argument_counts() {
+        fn counts(equation: &str) -> Option<(FunctionType, usize, usize)> {
+            let arena = EntityArena::new();
+            match parse::<f64>(equation, &arena) {
+                Err(EquationParseError::WrongArgumentCount {
+                    function,
+                    expected,
+                    found,
+                }) => Some((function, expected, found)),
+                _ => None,
+            }
+        }
+        assert_eq!(counts("sin(1,2)"), Some((FunctionType::Sin, 1, 2)));
+        assert_eq!(counts("atan2(1)"), Some((FunctionType::Atan2, 2, 1)));
+        assert_eq!(counts("clamp(1,2)"), Some((FunctionType::Clamp, 3, 2)));
+        assert_eq!(counts("log()"), Some((FunctionType::Log, 2, 0)));
+        assert_eq!(counts("max(1, sin(2, 3))"), Some((FunctionType::Sin, 1, 2)));
+        assert_eq!(counts("log(2,8)"), None);
+        assert_eq!(value("log(2,8)"), 3.0);
+    }
+
+    #[test]
+    fn rejects_empty_input() {
+        let arena = EntityArena::new();
+        for equation in ["", "   ", "\n", " \t\n", "# just a comment"] {
+            assert!(
+                matches!(
+                    parse::<f64>(equation, &arena),
+                    Err(EquationParseError::EmptyInput)
+                ),
+                "{equation:?} should be empty"
+            );
+        }
+        assert_eq!(value(" 1 \n"), 1.0);
+    }
+
+    #[test]
+    fn chains_literal_error_sources() {
+        use core::error::Error;
+
+        let error = parse_tokens::<f64>("1.2.3").unwrap_err();
+        let source = error.source().expect("a bad literal has a source");
+        assert!(source.is::<LiteralError>());
+        // and the literal error keeps the standard library's parse error
+        assert!(source.source().unwrap().is::<core::num::ParseFloatError>());
+
+        let error = parse_tokens::<f64>("0x1_0000_0000_0000_0000").unwrap_err();
+        let source = error.source().unwrap().source().unwrap();
+        assert!(source.is::<core::num::ParseIntError>());
+
+        assert!(EquationParseError::EmptyInput.source().is_none());
+    }
+
+    #[test]
+    fn renders_postfix_strings() {
+        let postfix =
+            |equation| to_postfix_string(&parse_tokens::<f64>(equation).unwrap()).unwrap();
+        assert_eq!(postfix("3+4"), "3 4 +");
+        assert_eq!(postfix("3+4*2"), "3 4 2 * +");
+        assert_eq!(postfix("(1+2)*3"), "1 2 + 3 *");
+        assert_eq!(postfix("-x^2"), "x 2 ^ neg");
+        assert_eq!(postfix("max(a, b) + sin(x)"), "a b max x sin +");
+        assert_eq!(postfix("2.5!"), "2.5 !");
+    }
+
+    #[test]
+    fn rejects_empty_parentheses() {
+        let arena = EntityArena::new();
+        let empty_at = |equation| match parse::<f64>(equation, &arena) {
+            Err(EquationParseError::EmptyParentheses { index }) => Some(index),
+            _ => None,
+        };
+        assert_eq!(empty_at("()"), Some(0));
+        assert_eq!(empty_at("2*()"), Some(2));
+        assert_eq!(empty_at("( )+1"), Some(0));
+        assert_eq!(empty_at("(2)"), None);
+        // a function call without arguments has the wrong number of them instead
+        assert!(matches!(
+            parse::<f64>("sin()", &arena),
+            Err(EquationParseError::WrongArgumentCount {
+                function: FunctionType::Sin,
+                expected: 1,
+                found: 0
+            })
+        ));
+    }
+
+    #[test]
+    fn retokenizes_only_after_an_edit() {
+        let mut tokens = tokenize("2+3").unwrap();
+        retokenize(&mut tokens, "2+34", 3).unwrap();
+        assert_eq!(tokens, tokenize("2+34").unwrap());
+        // only the `34` is read again
+        assert_eq!(
+            Tokens::starting_at("2+34", 2)
+                .collect::<Result<Vec<_>, _>>()
+                .unwrap(),
+            [RawSyntax::ValueLit { start: 2, end: 4 }]
+        );
+
+        // edits that change the tokens before them
+        for (before, after, edit_start) in [
+            ("sin", "sin(x)", 3),
+            ("1", "1e5", 1),
+            ("atan+1", "atan2(1,1)+1", 4),
+            ("2*x", "2*(x", 2),
+            ("1+2+3", "1-2+3", 1),
+        ] {
+            let mut tokens = tokenize(before).unwrap();
+            retokenize(&mut tokens, after, edit_start).unwrap();
+            assert_eq!(tokens, tokenize(after).unwrap(), "{before} → {after}");
+        }
+    }
+
+    #[test]
+    fn multiplies_literals_and_variables_into_functions() {
+        let arena = EntityArena::new();
+        let sin_x = &Entity::Function(Function::Sin(&Entity::Value("x")));
+        assert_eq!(
+            parse::<f64>("2sin(x)", &arena).unwrap(),
+            Entity::Operation(Operation::Mul(&Entity::ValueLit(2.0), sin_x))
+        );
+        assert_eq!(
+            parse::<f64>("xsin(x)", &arena).unwrap(),
+            Entity::Operation(Operation::Mul(&Entity::Value("x"), sin_x))
+        );
+        assert_eq!(
+            parse::<f64>("x sin(x)", &arena).unwrap(),
+            Entity::Operation(Operation::Mul(&Entity::Value("x"), sin_x))
+        );
+        assert_eq!(
+            parse::<f64>("2pi", &arena).unwrap(),
+            Entity::Operation(Operation::Mul(&Entity::ValueLit(2.0), &Entity::Value("pi")))
+        );
+    }
+
+    #[test]
+    fn sends_errors_across_threads() {
+        extern crate std;
+
+        let equations = ["1.2.3", "2+", "sin(1,2)", "x"];
+        let handle = std::thread::spawn(move || {
+            equations
+                .iter()
+                .filter_map(|equation| parse_tokens::<f64>(equation).err())
+                .collect::<Vec<_>>()
+        });
+        let errors = handle.join().unwrap();
+        assert_eq!(errors.len(), 2);
+
+        let cloned = errors.clone();
+        assert!(matches!(
+            &cloned[0],
+            EquationParseError::LiteralParseError { text, .. } if text == "1.2.3"
+        ));
+        assert!(matches!(
+            cloned[1],
+            EquationParseError::DanglingOperator { index: 1 }
+        ));
+    }
+
+    #[test]
+    fn pairs_nested_absolute_values_or_reports_ambiguity() {
+        let arena = EntityArena::new();
+        let abs = |x| Entity::Function(Function::Abs(arena.alloc(x)));
+        assert_eq!(
+            parse::<f64>("|x|", &arena).unwrap(),
+            abs(Entity::Value("x"))
+        );
+        assert_eq!(
+            parse::<f64>("|a+|b||", &arena).unwrap(),
+            abs(Entity::Operation(Operation::Add(
+                &Entity::Value("a"),
+                arena.alloc(abs(Entity::Value("b")))
+            )))
+        );
+        assert_eq!(
+            parse::<f64>("||x|-1|", &arena).unwrap(),
+            abs(Entity::Operation(Operation::Sub(
+                arena.alloc(abs(Entity::Value("x"))),
+                &Entity::ValueLit(1.0)
+            )))
+        );
+
+        assert!(matches!(
+            parse::<f64>("|a|b|c|", &arena),
+            Err(EquationParseError::AmbiguousAbs { index: 2 })
+        ));
+        // writing out the multiplication resolves it
+        assert_eq!(
+            parse::<f64>("|a|*b*|c|", &arena).unwrap(),
+            parse("abs(a)*b*abs(c)", &arena).unwrap()
+        );
+        assert!(matches!(
+            parse::<f64>("|x||", &arena),
+            Err(EquationParseError::UnbalancedAbs { .. })
+        ));
+    }
+
+    #[test]
+    fn lowers_operator_functions_into_operations() {
+        let arena = EntityArena::new();
+        for (function, operator) in [
+            ("pow(x, 2)", "x^2"),
+            ("add(x, 2)", "x+2"),
+            ("sub(x, 2)", "x-2"),
+            ("mul(x, 2)", "x*2"),
+            ("div(x, 2)", "x/2"),
+            ("pow(add(a, b), sub(c, d))", "(a+b)^(c-d)"),
+        ] {
+            assert_eq!(
+                parse::<f64>(function, &arena).unwrap(),
+                parse::<f64>(operator, &arena).unwrap(),
+                "{function}"
+            );
+        }
+        assert!(matches!(
+            parse::<f64>("pow(x)", &arena),
+            Err(EquationParseError::WrongArgumentCount {
+                function: FunctionType::Pow,
+                ..
+            })
+        ));
+    }
 }
@@ -0,0 +1,193 @@
+use crate::ast::{Entity, FunctionType, RawSyntax};
+use crate::eval::fold;
+
+use alloc::collections::BTreeSet;
+use core::convert::Infallible;
+
+/// The number of nodes in an [`Entity`] tree, counting both operations and their operands,
+/// so `a+b` has 3. Useful to reject huge expressions before evaluating them.
+pub fn node_count<T>(entity: &Entity<'_, T>) -> usize {
+    let Ok(count) = fold(entity, |_, children: &[usize]| {
+        Ok::<_, Infallible>(1 + children.iter().sum::<usize>())
+    });
+    count
+}
+
+/// The number of nodes on the longest path from the root of an [`Entity`] tree to a leaf,
+/// so `x` has a depth of 1, and `sin(x+1)` of 3.
+/// Useful to reject deeply nested expressions before evaluating them.
+pub fn tree_depth<T>(entity: &Entity<'_, T>) -> usize {
+    let Ok(depth) = fold(entity, |_, children: &[usize]| {
+        Ok::<_, Infallible>(1 + children.iter().max().unwrap_or(&0))
+    });
+    depth
+}
+
+/// Whether `function` is called anywhere in an [`Entity`] tree, so a sandbox can reject
+/// functions like `exp` before evaluating. `|x|` and `√x` call `abs` and `sqrt`.
+pub fn uses_function<T>(entity: &Entity<'_, T>, function: FunctionType) -> bool {
+    // the first call stops the fold
+    fold(entity, |entity, _| match entity {
+        Entity::Function(func) if func.function_type() == function => Err(()),
+        _ => Ok(()),
+    })
+    .is_err()
+}
+
+/// The distinct functions called in an [`Entity`] tree, so `sin(x)+exp(y)` gives
+/// `{Sin, Exp}`. It's a `BTreeSet` rather than a `HashSet`, to work without `std`,
+/// so it iterates in the order [`FunctionType`] declares its variants.
+pub fn used_functions<T>(entity: &Entity<'_, T>) -> BTreeSet<FunctionType> {
+    let mut functions = BTreeSet::new();
+    let Ok(()) = fold(entity, |entity, _| {
+        if let Entity::Function(func) = entity {
+            functions.insert(func.function_type());
+        }
+        Ok::<_, Infallible>(())
+    });
+    functions
+}
+
+/// A stage of the parser, as reported by [`Parser::parse_timed`](crate::Parser::parse_timed).
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Stage {
+    /// Splitting the equation into stage-one tokens, like [`tokenize`](crate::tokenize).
+    Tokenize,
+    /// Resolving those into stage-two tokens, like the rest of [`parse_tokens`](crate::parse_tokens).
+    Resolve,
+    /// Building the [`Entity`] tree out of stage-two tokens.
+    BuildTree,
+}
+
+/// Counts of what an equation is made of, from [`parse_with_stats`](crate::parse_with_stats).
+/// Useful to profile the equations going through a large batch.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct ParseStats {
+    /// The number of stage-one tokens, like from [`tokenize`](crate::tokenize),
+    /// so `sin(x)` has 4.
+    pub token_count: usize,
+    /// The number of literals, like `2` or `0xFF`.
+    pub literal_count: usize,
+    /// The number of function calls, like `sin(x)`. Absolute value bars don't count.
+    pub function_count: usize,
+    /// How deep parentheses are nested, including the ones of function calls,
+    /// like [`Parser::max_depth`](crate::Parser::max_depth), so `2*(x+1)` has 1.
+    pub max_depth: usize,
+}
+
+impl ParseStats {
+    pub(crate) fn from_tokens(tokens: &[RawSyntax]) -> Self {
+        let mut stats = Self {
+            token_count: tokens.len(),
+            ..Self::default()
+        };
+        let mut depth = 0usize;
+
+        for token in tokens {
+            match token {
+                RawSyntax::ValueLit { .. } => stats.literal_count += 1,
+                RawSyntax::Function { .. } => stats.function_count += 1,
+                RawSyntax::Parenthesis { kind, .. } if kind.is_open() => {
+                    depth += 1;
+                    stats.max_depth = stats.max_depth.max(depth);
+                }
+                RawSyntax::Parenthesis { .. } => depth = depth.saturating_sub(1),
+                _ => {}
+            }
+        }
+
+        stats
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ast::{Function, Operation};
+    use crate::{EntityArena, parse};
+
+    #[test]
+    fn detects_used_functions() {
+        let arena = EntityArena::new();
+        let entity = parse::<f64>("sin(x)+exp(y)", &arena).unwrap();
+        assert!(uses_function(&entity, FunctionType::Sin));
+        assert!(uses_function(&entity, FunctionType::Exp));
+        assert!(!uses_function(&entity, FunctionType::Cos));
+        assert_eq!(
+            used_functions(&entity),
+            BTreeSet::from([FunctionType::Sin, FunctionType::Exp])
+        );
+    }
+
+    #[test]
+    fn counts_bars_and_roots_as_functions() {
+        let arena = EntityArena::new();
+        let entity = parse::<f64>("|x| + √y + sin(sin(x))", &arena).unwrap();
+        assert_eq!(
+            used_functions(&entity),
+            BTreeSet::from([FunctionType::Sin, FunctionType::Sqrt, FunctionType::Abs])
+        );
+    }
+
+    #[test]
+    fn measures_trees() {
+        let arena = EntityArena::new();
+        let entity = parse::<f64>("sin(x+1)", &arena).unwrap();
+        assert_eq!(node_count(&entity), 4);
+        assert_eq!(tree_depth(&entity), 3);
+        assert_eq!(tree_depth(&Entity::<f64>::Value("x")), 1);
+    }
+
+    #[test]
+    fn measures_nested_and_flat_trees() {
+        let arena = EntityArena::new();
+        let nested = parse::<f64>("sin(cos(tan(x)))", &arena).unwrap();
+        assert_eq!(node_count(&nested), 4);
+        assert_eq!(tree_depth(&nested), 4);
+
+        // `((a+b)+c)+d` leans left
+        let flat = parse::<f64>("a+b+c+d", &arena).unwrap();
+        assert_eq!(node_count(&flat), 7);
+        assert_eq!(tree_depth(&flat), 4);
+
+        let balanced = parse::<f64>("(a+b)+(c+d)", &arena).unwrap();
+        assert_eq!(node_count(&balanced), 7);
+        assert_eq!(tree_depth(&balanced), 3);
+    }
+
+    #[test]
+    fn counts_parse_stats() {
+        let arena = EntityArena::new();
+        let (entity, stats) = crate::parse_with_stats::<f64>("sin(x)+2*cos(y)", &arena).unwrap();
+        assert_eq!(entity, parse("sin(x)+2*cos(y)", &arena).unwrap());
+        assert_eq!(
+            stats,
+            ParseStats {
+                token_count: 11,
+                literal_count: 1,
+                function_count: 2,
+                max_depth: 1,
+            }
+        );
+
+        let (_, stats) = crate::parse_with_stats::<f64>("|x| * ((1+0xF))", &arena).unwrap();
+        assert_eq!(stats.literal_count, 2);
+        assert_eq!(stats.function_count, 0);
+        assert_eq!(stats.max_depth, 2);
+    }
+
+    #[test]
+    fn measures_trees_of_any_depth() {
+        let arena = EntityArena::new();
+        let one = arena.alloc(Entity::ValueLit(1.0));
+        let mut deep = Entity::Function(Function::Sin(one));
+        for _ in 1..100_000 {
+            deep = Entity::Operation(Operation::Add(arena.alloc(deep), one));
+        }
+        assert_eq!(node_count(&deep), 200_000);
+        assert_eq!(tree_depth(&deep), 100_001);
+        assert!(uses_function(&deep, FunctionType::Sin));
+        assert!(!uses_function(&deep, FunctionType::Cos));
+        assert_eq!(used_functions(&deep), BTreeSet::from([FunctionType::Sin]));
+    }
+}
@@ -0,0 +1,483 @@
+use crate::LiteralError;
+
+/// A number type the parser can read literals into: `f32` and `f64`,
+/// and with the `rational` feature, `num_rational::Rational64`.
+///
+/// Other types can implement it too, like an extended-precision float, wrapped in a newtype
+/// if it comes from another crate. Nothing in the parser goes through `f64`: literals, including
+/// hexadecimal ones, are read by [`Numeric::from_decimal`] and [`Numeric::from_integer`], and
+/// constants like `pi` come from `num_traits::FloatConst`, so they keep the full precision of
+/// the type. To evaluate trees, the type also has to implement `num_traits::Float` and
+/// `FloatConst`. Only `gamma` and `lgamma` are limited to `f64` precision.
+pub trait Numeric: num_traits::Num + Copy + core::fmt::Debug {
+    /// Parses a decimal literal without `_` separators, like `2.5` or `1e-3`.
+    fn from_decimal(literal: &str) -> Result<Self, LiteralError>;
+
+    /// Converts an integer, like the value of `0xFF`. `None` if it doesn't fit.
+    fn from_integer(integer: u64) -> Option<Self>;
+}
+
+macro_rules! float_numeric {
+    ($float:ty) => {
+        impl Numeric for $float {
+            fn from_decimal(literal: &str) -> Result<Self, LiteralError> {
+                Ok(literal.parse()?)
+            }
+
+            fn from_integer(integer: u64) -> Option<Self> {
+                // rounds past the float's mantissa, like any large literal would
+                Some(integer as $float)
+            }
+        }
+    };
+}
+
+float_numeric!(f32);
+float_numeric!(f64);
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{EntityArena, eval, parse};
+    use core::num::FpCategory;
+    use core::ops::{Add, Div, Mul, Neg, Rem, Sub};
+    use num_traits::{Float, FloatConst, NumCast, One, ToPrimitive, Zero};
+
+    /// A double-double: an unevaluated sum `hi + lo` of two `f64`s, with about 106 bits
+    /// of mantissa. Arithmetic and `pi` keep all of them, other functions go through `f64`.
+    #[derive(Debug, Clone, Copy, PartialEq, PartialOrd)]
+    struct DoubleDouble {
+        hi: f64,
+        lo: f64,
+    }
+
+    impl DoubleDouble {
+        fn from_f64(value: f64) -> Self {
+            Self { hi: value, lo: 0.0 }
+        }
+
+        /// `a + b` where `|a| >= |b|`, with the rounding error in `lo`
+        fn quick_two_sum(a: f64, b: f64) -> Self {
+            let hi = a + b;
+            Self {
+                hi,
+                lo: b - (hi - a),
+            }
+        }
+
+        fn two_sum(a: f64, b: f64) -> Self {
+            let hi = a + b;
+            let b_part = hi - a;
+            let lo = (a - (hi - b_part)) + (b - b_part);
+            Self { hi, lo }
+        }
+
+        fn two_prod(a: f64, b: f64) -> Self {
+            let hi = a * b;
+            Self {
+                hi,
+                lo: Float::mul_add(a, b, -hi),
+            }
+        }
+
+        /// Rounds to an integer with `round`, applied to `hi` and, if that's already
+        /// an integer, to `lo`
+        fn round_with(self, round: fn(f64) -> f64) -> Self {
+            let hi = round(self.hi);
+            match hi == self.hi {
+                true => Self::quick_two_sum(hi, round(self.lo)),
+                false => Self::from_f64(hi),
+            }
+        }
+    }
+
+    impl Add for DoubleDouble {
+        type Output = Self;
+
+        fn add(self, other: Self) -> Self {
+            let high = Self::two_sum(self.hi, other.hi);
+            let low = Self::two_sum(self.lo, other.lo);
+            let sum = Self::quick_two_sum(high.hi, high.lo + low.hi);
+            Self::quick_two_sum(sum.hi, sum.lo + low.lo)
+        }
+    }
+
+    impl Sub for DoubleDouble {
+        type Output = Self;
+
+        fn sub(self, other: Self) -> Self {
+            self + -other
+        }
+    }
+
+    impl Mul for DoubleDouble {
+        type Output = Self;
+
+        fn mul(self, other: Self) -> Self {
+            let product = Self::two_prod(self.hi, other.hi);
+            let cross = self.hi * other.lo + self.lo * other.hi;
+            Self::quick_two_sum(product.hi, product.lo + cross)
+        }
+    }
+
+    impl Div for DoubleDouble {
+        type Output = Self;
+
+        fn div(self, other: Self) -> Self {
+            let first = self.hi / other.hi;
+            let rest = self - other * Self::from_f64(first);
+            Self::quick_two_sum(first, rest.hi / other.hi)
+        }
+    }
+
+    impl Rem for DoubleDouble {
+        type Output = Self;
+
+        fn rem(self, other: Self) -> Self {
+            self - other * (self / other).trunc()
+        }
+    }
+
+    impl Neg for DoubleDouble {
+        type Output = Self;
+
+        fn neg(self) -> Self {
+            Self {
+                hi: -self.hi,
+                lo: -self.lo,
+            }
+        }
+    }
+
+    impl Zero for DoubleDouble {
+        fn zero() -> Self {
+            Self::from_f64(0.0)
+        }
+
+        fn is_zero(&self) -> bool {
+            self.hi == 0.0
+        }
+    }
+
+    impl One for DoubleDouble {
+        fn one() -> Self {
+            Self::from_f64(1.0)
+        }
+    }
+
+    impl num_traits::Num for DoubleDouble {
+        type FromStrRadixErr = num_traits::ParseFloatError;
+
+        fn from_str_radix(text: &str, radix: u32) -> Result<Self, Self::FromStrRadixErr> {
+            f64::from_str_radix(text, radix).map(Self::from_f64)
+        }
+    }
+
+    impl ToPrimitive for DoubleDouble {
+        fn to_i64(&self) -> Option<i64> {
+            self.trunc().hi.to_i64()
+        }
+
+        fn to_u64(&self) -> Option<u64> {
+            self.trunc().hi.to_u64()
+        }
+
+        fn to_f64(&self) -> Option<f64> {
+            Some(self.hi)
+        }
+    }
+
+    impl NumCast for DoubleDouble {
+        fn from<N: ToPrimitive>(number: N) -> Option<Self> {
+            number.to_f64().map(Self::from_f64)
+        }
+    }
+
+    impl Numeric for DoubleDouble {
+        fn from_decimal(literal: &str) -> Result<Self, LiteralError> {
+            let (integer, fraction) = literal.split_once('.').unwrap_or((literal, ""));
+            let mut digits = integer.bytes().chain(fraction.bytes());
+            if !digits.all(|digit| digit.is_ascii_digit()) {
+                // exponents only need to work, not to be precise, here
+                return f64::from_decimal(literal).map(Self::from_f64);
+            }
+
+            let ten = Self::from_f64(10.0);
+            let value = integer
+                .bytes()
+                .chain(fraction.bytes())
+                .fold(Self::zero(), |value, digit| {
+                    value * ten + Self::from_f64(<f64 as From<u8>>::from(digit - b'0'))
+                });
+            Ok(value / ten.powi(fraction.len() as i32))
+        }
+
+        fn from_integer(integer: u64) -> Option<Self> {
+            // both halves fit in an f64 exactly, and so does their sum in a double-double
+            let high = Self::from_f64(((integer >> 32) << 32) as f64);
+            Some(high + Self::from_f64((integer & 0xFFFF_FFFF) as f64))
+        }
+    }
+
+    impl FloatConst for DoubleDouble {
+        fn PI() -> Self {
+            Self {
+                hi: core::f64::consts::PI,
+                lo: 1.224_646_799_147_353_2e-16,
+            }
+        }
+
+        fn FRAC_PI_2() -> Self {
+            Self::PI() / Self::from_f64(2.0)
+        }
+
+        fn FRAC_PI_3() -> Self {
+            Self::PI() / Self::from_f64(3.0)
+        }
+
+        fn FRAC_PI_4() -> Self {
+            Self::PI() / Self::from_f64(4.0)
+        }
+
+        fn FRAC_PI_6() -> Self {
+            Self::PI() / Self::from_f64(6.0)
+        }
+
+        fn FRAC_PI_8() -> Self {
+            Self::PI() / Self::from_f64(8.0)
+        }
+
+        fn FRAC_1_PI() -> Self {
+            Self::one() / Self::PI()
+        }
+
+        fn FRAC_2_PI() -> Self {
+            Self::from_f64(2.0) / Self::PI()
+        }
+
+        fn E() -> Self {
+            Self::from_f64(f64::E())
+        }
+
+        fn FRAC_1_SQRT_2() -> Self {
+            Self::from_f64(f64::FRAC_1_SQRT_2())
+        }
+
+        fn FRAC_2_SQRT_PI() -> Self {
+            Self::from_f64(f64::FRAC_2_SQRT_PI())
+        }
+
+        fn LN_10() -> Self {
+            Self::from_f64(f64::LN_10())
+        }
+
+        fn LN_2() -> Self {
+            Self::from_f64(f64::LN_2())
+        }
+
+        fn LOG10_E() -> Self {
+            Self::from_f64(f64::LOG10_E())
+        }
+
+        fn LOG2_E() -> Self {
+            Self::from_f64(f64::LOG2_E())
+        }
+
+        fn SQRT_2() -> Self {
+            Self::from_f64(f64::SQRT_2())
+        }
+    }
+
+    /// Functions that only keep `f64` precision
+    macro_rules! through_f64 {
+        ($($function:ident),*) => {
+            $(fn $function(self) -> Self {
+                Self::from_f64(Float::$function(self.hi))
+            })*
+        };
+        ($($function:ident(other)),*) => {
+            $(fn $function(self, other: Self) -> Self {
+                Self::from_f64(Float::$function(self.hi, other.hi))
+            })*
+        };
+    }
+
+    impl Float for DoubleDouble {
+        through_f64!(
+            sqrt, exp, exp2, ln, log2, log10, cbrt, sin, cos, tan, asin, acos, atan, exp_m1, ln_1p,
+            sinh, cosh, tanh, asinh, acosh, atanh
+        );
+        through_f64!(powf(other), log(other), hypot(other), atan2(other));
+
+        fn nan() -> Self {
+            Self::from_f64(f64::NAN)
+        }
+
+        fn infinity() -> Self {
+            Self::from_f64(f64::INFINITY)
+        }
+
+        fn neg_infinity() -> Self {
+            Self::from_f64(f64::NEG_INFINITY)
+        }
+
+        fn neg_zero() -> Self {
+            Self::from_f64(-0.0)
+        }
+
+        fn min_value() -> Self {
+            Self::from_f64(f64::MIN)
+        }
+
+        fn min_positive_value() -> Self {
+            Self::from_f64(f64::MIN_POSITIVE)
+        }
+
+        fn max_value() -> Self {
+            Self::from_f64(f64::MAX)
+        }
+
+        fn is_nan(self) -> bool {
+            self.hi.is_nan()
+        }
+
+        fn is_infinite(self) -> bool {
+            self.hi.is_infinite()
+        }
+
+        fn is_finite(self) -> bool {
+            self.hi.is_finite()
+        }
+
+        fn is_normal(self) -> bool {
+            self.hi.is_normal()
+        }
+
+        fn classify(self) -> FpCategory {
+            self.hi.classify()
+        }
+
+        fn floor(self) -> Self {
+            self.round_with(Float::floor)
+        }
+
+        fn ceil(self) -> Self {
+            self.round_with(Float::ceil)
+        }
+
+        fn round(self) -> Self {
+            (self + Self::from_f64(0.5)).floor()
+        }
+
+        fn trunc(self) -> Self {
+            match self.hi < 0.0 {
+                true => self.ceil(),
+                false => self.floor(),
+            }
+        }
+
+        fn fract(self) -> Self {
+            self - self.trunc()
+        }
+
+        fn abs(self) -> Self {
+            match self.hi < 0.0 {
+                true => -self,
+                false => self,
+            }
+        }
+
+        fn signum(self) -> Self {
+            Self::from_f64(self.hi.signum())
+        }
+
+        fn is_sign_positive(self) -> bool {
+            self.hi.is_sign_positive()
+        }
+
+        fn is_sign_negative(self) -> bool {
+            self.hi.is_sign_negative()
+        }
+
+        fn mul_add(self, a: Self, b: Self) -> Self {
+            self * a + b
+        }
+
+        fn recip(self) -> Self {
+            Self::one() / self
+        }
+
+        fn powi(self, n: i32) -> Self {
+            let mut result = Self::one();
+            let mut base = self;
+            let mut exponent = n.unsigned_abs();
+            while exponent > 0 {
+                if exponent & 1 == 1 {
+                    result = result * base;
+                }
+                base = base * base;
+                exponent >>= 1;
+            }
+            match n < 0 {
+                true => result.recip(),
+                false => result,
+            }
+        }
+
+        fn max(self, other: Self) -> Self {
+            match self < other {
+                true => other,
+                false => self,
+            }
+        }
+
+        fn min(self, other: Self) -> Self {
+            match other < self {
+                true => other,
+                false => self,
+            }
+        }
+
+        fn abs_sub(self, other: Self) -> Self {
+            (self - other).max(Self::zero())
+        }
+
+        fn sin_cos(self) -> (Self, Self) {
+            (self.sin(), self.cos())
+        }
+
+        fn integer_decode(self) -> (u64, i16, i8) {
+            self.hi.integer_decode()
+        }
+    }
+
+    #[test]
+    fn evaluates_pi_past_f64_precision() {
+        let arena = EntityArena::new();
+        let value = |equation| eval(&parse(equation, &arena).unwrap(), &[][..]).unwrap();
+        let pi = DoubleDouble::PI();
+        let close = |actual: DoubleDouble, expected: DoubleDouble| {
+            (actual - expected).abs() < DoubleDouble::from_f64(1e-30)
+        };
+
+        for equation in [
+            "pi",
+            "tau/2",
+            "(pi*3 - pi)/2",
+            "3.14159265358979323846264338327950288",
+        ] {
+            assert!(close(value(equation), pi), "{equation}");
+        }
+
+        // the part of pi an f64 rounds off, about 1.2e-16, comes back too
+        let rest = value("pi") - DoubleDouble::from_f64(core::f64::consts::PI);
+        assert!(close(
+            rest,
+            DoubleDouble::from_f64(1.224_646_799_147_353_2e-16)
+        ));
+        assert!(close(
+            value("0xFFFFFFFFFFFFFFFF + 1"),
+            DoubleDouble::from_f64(2f64.powi(64))
+        ));
+    }
+}
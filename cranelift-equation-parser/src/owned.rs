@@ -0,0 +1,185 @@
+use crate::EquationParseError;
+use crate::arena::EntityArena;
+use crate::ast::{Entity, Function, FunctionType, Operation, Operator};
+
+use alloc::boxed::Box;
+use alloc::string::{String, ToString};
+use alloc::vec::Vec;
+
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+/// An [`Entity`] tree that owns its nodes and variable names, so it borrows neither an
+/// [`EntityArena`] nor the equation text. Useful to store or serialize parsed trees.
+pub enum OwnedEntity<T> {
+    ValueLit(T),
+    Value(String),
+    Operation(OwnedOperation<T>),
+    Function(OwnedFunction<T>),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum OwnedOperation<T> {
+    Add(Box<OwnedEntity<T>>, Box<OwnedEntity<T>>),
+    Sub(Box<OwnedEntity<T>>, Box<OwnedEntity<T>>),
+    Mul(Box<OwnedEntity<T>>, Box<OwnedEntity<T>>),
+    Div(Box<OwnedEntity<T>>, Box<OwnedEntity<T>>),
+    FloorDiv(Box<OwnedEntity<T>>, Box<OwnedEntity<T>>),
+    Pow(Box<OwnedEntity<T>>, Box<OwnedEntity<T>>),
+    Rem(Box<OwnedEntity<T>>, Box<OwnedEntity<T>>),
+    Neg(Box<OwnedEntity<T>>),
+    Factorial(Box<OwnedEntity<T>>),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+/// A function applied to its arguments, in the order they were written.
+/// `log` with a single argument is the base 10 logarithm, like in [`Function::from_arguments`].
+pub struct OwnedFunction<T> {
+    pub function: FunctionType,
+    pub arguments: Vec<OwnedEntity<T>>,
+}
+
+impl<T: Copy> From<&Entity<'_, T>> for OwnedEntity<T> {
+    fn from(entity: &Entity<'_, T>) -> Self {
+        let owned = |entity: &Entity<'_, T>| Box::new(Self::from(entity));
+
+        match entity {
+            Entity::ValueLit(val) => Self::ValueLit(*val),
+            Entity::Value(name) => Self::Value(name.to_string()),
+            Entity::Operation(op) => Self::Operation(match *op {
+                Operation::Add(lhs, rhs) => OwnedOperation::Add(owned(lhs), owned(rhs)),
+                Operation::Sub(lhs, rhs) => OwnedOperation::Sub(owned(lhs), owned(rhs)),
+                Operation::Mul(lhs, rhs) => OwnedOperation::Mul(owned(lhs), owned(rhs)),
+                Operation::Div(lhs, rhs) => OwnedOperation::Div(owned(lhs), owned(rhs)),
+                Operation::FloorDiv(lhs, rhs) => OwnedOperation::FloorDiv(owned(lhs), owned(rhs)),
+                Operation::Pow(lhs, rhs) => OwnedOperation::Pow(owned(lhs), owned(rhs)),
+                Operation::Rem(lhs, rhs) => OwnedOperation::Rem(owned(lhs), owned(rhs)),
+                Operation::Neg(x) => OwnedOperation::Neg(owned(x)),
+                Operation::Factorial(x) => OwnedOperation::Factorial(owned(x)),
+            }),
+            Entity::Function(func) => Self::Function(OwnedFunction {
+                function: func.function_type(),
+                arguments: func.arguments().into_iter().map(Self::from).collect(),
+            }),
+        }
+    }
+}
+
+impl<T: Copy> OwnedEntity<T> {
+    /// Borrows this tree as an [`Entity`], allocating its nodes in `arena`.
+    /// Functions that are operators in disguise, like `pow`, become an [`Operation`].
+    /// Fails if a function has the wrong number of arguments, which a deserialized tree can.
+    pub fn to_entity<'a>(
+        &'a self,
+        arena: &'a EntityArena<'a, T>,
+    ) -> Result<Entity<'a, T>, EquationParseError> {
+        let child = |owned: &'a Self| owned.to_entity(arena).map(|entity| arena.alloc(entity));
+
+        Ok(match self {
+            Self::ValueLit(val) => Entity::ValueLit(*val),
+            Self::Value(name) => Entity::Value(name),
+            Self::Operation(op) => Entity::Operation(match op {
+                OwnedOperation::Add(lhs, rhs) => Operation::Add(child(lhs)?, child(rhs)?),
+                OwnedOperation::Sub(lhs, rhs) => Operation::Sub(child(lhs)?, child(rhs)?),
+                OwnedOperation::Mul(lhs, rhs) => Operation::Mul(child(lhs)?, child(rhs)?),
+                OwnedOperation::Div(lhs, rhs) => Operation::Div(child(lhs)?, child(rhs)?),
+                OwnedOperation::FloorDiv(lhs, rhs) => Operation::FloorDiv(child(lhs)?, child(rhs)?),
+                OwnedOperation::Pow(lhs, rhs) => Operation::Pow(child(lhs)?, child(rhs)?),
+                OwnedOperation::Rem(lhs, rhs) => Operation::Rem(child(lhs)?, child(rhs)?),
+                OwnedOperation::Neg(x) => Operation::Neg(child(x)?),
+                OwnedOperation::Factorial(x) => Operation::Factorial(child(x)?),
+            }),
+            Self::Function(func) => {
+                let args = func
+                    .arguments
+                    .iter()
+                    .map(child)
+                    .collect::<Result<Vec<_>, _>>()?;
+                // functions like `pow(x, 2)` are parsed as operations, so they're built like one
+                if let (Some(op), &[lhs, rhs]) = (func.function.operator(), &args[..]) {
+                    return Ok(Entity::Operation(match op {
+                        Operator::Add => Operation::Add(lhs, rhs),
+                        Operator::Sub => Operation::Sub(lhs, rhs),
+                        Operator::Mul => Operation::Mul(lhs, rhs),
+                        Operator::Div => Operation::Div(lhs, rhs),
+                        Operator::Pow => Operation::Pow(lhs, rhs),
+                        _ => unreachable!("only binary operators are functions"),
+                    }));
+                }
+                let function = Function::from_arguments(func.function, &args[..]).ok_or(
+                    EquationParseError::WrongArgumentCount {
+                        function: func.function,
+                        expected: func.function.arity(),
+                        found: args.len(),
+                    },
+                )?;
+                Entity::Function(function)
+            }
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{parse, parse_owned};
+
+    #[test]
+    fn round_trips_through_borrowed_tree() {
+        let arena = EntityArena::new();
+        for equation in [
+            "sin(x)+2",
+            "-x^2 // 3 % y!",
+            "log(2, x) * log(x) - clamp(x, 0, 1)",
+        ] {
+            let entity = parse::<f64>(equation, &arena).unwrap();
+            let owned = OwnedEntity::from(&entity);
+            let borrowed = EntityArena::new();
+            assert_eq!(owned.to_entity(&borrowed).unwrap(), entity);
+            assert_eq!(parse_owned::<f64>(equation).unwrap(), owned);
+        }
+    }
+
+    #[test]
+    fn builds_operator_functions_as_operations() {
+        let arena = EntityArena::new();
+        let owned = OwnedEntity::Function(OwnedFunction {
+            function: FunctionType::Pow,
+            arguments: Vec::from([
+                OwnedEntity::Value("x".to_string()),
+                OwnedEntity::ValueLit(2.0),
+            ]),
+        });
+        let expected = parse::<f64>("x^2", &arena).unwrap();
+        assert_eq!(owned.to_entity(&arena).unwrap(), expected);
+    }
+
+    #[test]
+    fn rejects_wrong_argument_counts() {
+        let arena = EntityArena::new();
+        let owned = OwnedEntity::<f64>::Function(OwnedFunction {
+            function: FunctionType::Sin,
+            arguments: Vec::new(),
+        });
+        assert!(matches!(
+            owned.to_entity(&arena),
+            Err(EquationParseError::WrongArgumentCount {
+                function: FunctionType::Sin,
+                expected: 1,
+                found: 0,
+            })
+        ));
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn round_trips_through_json() {
+        let owned = parse_owned::<f64>("sin(x)+2").unwrap();
+        let json = serde_json::to_string(&owned).unwrap();
+        assert_eq!(
+            serde_json::from_str::<OwnedEntity<f64>>(&json).unwrap(),
+            owned
+        );
+    }
+}
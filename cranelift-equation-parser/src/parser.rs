@@ -0,0 +1,672 @@
+use crate::arena::EntityArena;
+use crate::ast::{Entity, FunctionType, RawSyntax, Syntax};
+use crate::diagnostic::Bail;
+use crate::metrics::ParseStats;
+#[cfg(feature = "std")]
+use crate::metrics::Stage;
+use crate::tokens::Tokens;
+use crate::{EquationParseError, Numeric, second_parse, third_parse, validate_parens};
+
+use alloc::collections::BTreeMap;
+use alloc::string::{String, ToString};
+use alloc::vec::Vec;
+
+/// A configurable parser, set up with builder methods like
+/// `Parser::default().implicit_multiplication(false)`.
+/// [`Parser::default`] parses exactly like [`parse`](crate::parse).
+#[derive(Debug, Clone)]
+pub struct Parser<T> {
+    pub(crate) implicit_multiplication: bool,
+    pub(crate) allow_factorial: bool,
+    pub(crate) constants: BTreeMap<String, T>,
+    pub(crate) function_aliases: BTreeMap<String, FunctionType>,
+    pub(crate) allowed_functions: Option<Vec<FunctionType>>,
+    pub(crate) max_depth: usize,
+    pub(crate) percent_mode: bool,
+    pub(crate) single_letter_variables: bool,
+    pub(crate) only_round_parens: bool,
+    pub(crate) function_application: bool,
+    pub(crate) decimal_separator: char,
+}
+
+impl<T> Default for Parser<T> {
+    fn default() -> Self {
+        Self {
+            implicit_multiplication: true,
+            allow_factorial: true,
+            constants: BTreeMap::new(),
+            function_aliases: BTreeMap::new(),
+            allowed_functions: None,
+            max_depth: 128,
+            percent_mode: false,
+            single_letter_variables: false,
+            only_round_parens: false,
+            function_application: false,
+            decimal_separator: '.',
+        }
+    }
+}
+
+impl<T: Numeric> Parser<T> {
+    /// Whether a `*` is implied between two operands, like in `2x`, `x y` or `(a)(b)`.
+    /// When disabled, these are an [`EquationParseError::MissingOperator`]. Enabled by default.
+    pub fn implicit_multiplication(mut self, enabled: bool) -> Self {
+        self.implicit_multiplication = enabled;
+        self
+    }
+
+    /// Whether `!` is the factorial operator. When disabled, it's an
+    /// [`EquationParseError::UnsupportedOperator`]. Enabled by default.
+    pub fn allow_factorial(mut self, allowed: bool) -> Self {
+        self.allow_factorial = allowed;
+        self
+    }
+
+    /// Whether only `(` and `)` group, so `[` and `]`, `{` and `}` are an
+    /// [`EquationParseError::UnsupportedBracket`], leaving them free for other meanings.
+    /// Disabled by default, where all three kinds group the same way.
+    pub fn only_round_parens(mut self, enabled: bool) -> Self {
+        self.only_round_parens = enabled;
+        self
+    }
+
+    /// Whether `%` means percent instead of remainder, so `50%` is `0.5`
+    /// and `200*5%` is `200*0.05`. Disabled by default.
+    pub fn percent_mode(mut self, enabled: bool) -> Self {
+        self.percent_mode = enabled;
+        self
+    }
+
+    /// Whether every letter is a variable of its own, so `xy` is `x*y` and `sin x` is `s*i*n*x`.
+    /// Constants, the well-known ones like `pi` and those set with [`Parser::constants`],
+    /// stay whole, and so do function names followed by `(`, like in `sin(x)`.
+    ///
+    /// Disabled by default, where a run of letters is a single variable, so `xy` is `xy`.
+    pub fn single_letter_variables(mut self, enabled: bool) -> Self {
+        self.single_letter_variables = enabled;
+        self
+    }
+
+    /// Whether a function name followed by a value applies to it without parentheses,
+    /// like in handwritten math, so `sin2` and `sin 2` are `sin(2)`, and `sin cos x` is
+    /// `sin(cos(x))`. Only the value right after the name is the argument,
+    /// so `sin x^2` is `sin(x)^2`. Functions of more than one argument still need parentheses.
+    ///
+    /// Disabled by default, where a function name without parentheses is a variable,
+    /// so `sin2` is `sin*2`.
+    pub fn function_application(mut self, enabled: bool) -> Self {
+        self.function_application = enabled;
+        self
+    }
+
+    /// The character that separates the integer and fractional parts of literals,
+    /// either `.`, the default, or `,`, like many locales write `2,5`.
+    ///
+    /// With `,`, `.` groups digits like `_` does, so `1.000,5` is `1000.5`. Commas can't
+    /// separate function arguments anymore, so `;` does instead, like in `max(1,5; 2)`.
+    ///
+    /// # Panics
+    ///
+    /// If `separator` is neither `.` nor `,`.
+    pub fn decimal_separator(mut self, separator: char) -> Self {
+        assert!(
+            matches!(separator, '.' | ','),
+            "the decimal separator has to be `.` or `,`"
+        );
+        self.decimal_separator = separator;
+        self
+    }
+
+    /// Identifiers that are replaced with a literal while parsing, like `g` for `9.81`.
+    /// These can be folded by [`simplify`](crate::simplify), and don't have to be passed to
+    /// [`eval`](crate::eval). Adds to the constants set by earlier calls.
+    pub fn constants<'n>(mut self, constants: impl IntoIterator<Item = (&'n str, T)>) -> Self {
+        self.constants.extend(
+            constants
+                .into_iter()
+                .map(|(name, value)| (name.to_string(), value)),
+        );
+        self
+    }
+
+    /// Adds a single constant, like [`Parser::constants`]: `with_constant("g", 9.81)`.
+    /// Replaces an earlier constant with the same name.
+    pub fn with_constant(mut self, name: &str, value: T) -> Self {
+        self.constants.insert(name.to_string(), value);
+        self
+    }
+
+    /// Adds another name for a function, like `with_function_alias("arkussinus", FunctionType::Asin)`,
+    /// on top of the common aliases [`FunctionType`] already knows, like `arcsin`.
+    /// Replaces an earlier alias with the same name, and takes precedence over built-in names.
+    pub fn with_function_alias(mut self, alias: &str, canonical: FunctionType) -> Self {
+        self.function_aliases.insert(alias.to_string(), canonical);
+        self
+    }
+
+    /// The only functions equations can call, so a sandbox can rule out ones like `exp`.
+    /// Calling any other fails with [`EquationParseError::DisallowedFunction`], and so do
+    /// `|x|` and `√x` without `abs` and `sqrt`. Replaces the functions allowed by earlier calls.
+    ///
+    /// All functions are allowed by default.
+    pub fn allowed_functions(mut self, functions: impl IntoIterator<Item = FunctionType>) -> Self {
+        self.allowed_functions = Some(functions.into_iter().collect());
+        self
+    }
+
+    /// How deep the equation can nest before parsing fails with [`EquationParseError::TooDeep`],
+    /// or [`EquationParseError::NestedTooDeep`] when it isn't just parentheses.
+    /// Every open parenthesis is a level, and so is every function, prefix operator like a unary
+    /// minus, and operator still waiting for its right operand, like each `^` in `2^2^x`.
+    /// A call like `sin(x)` is two levels, the function and its parentheses.
+    /// This keeps untrusted equations from building trees too deep to walk. 128 by default.
+    pub fn max_depth(mut self, depth: usize) -> Self {
+        self.max_depth = depth;
+        self
+    }
+
+    /// Parses an equation into an [`Entity`] tree, like [`parse`](crate::parse).
+    /// Every node below the returned root is allocated in `arena`.
+    pub fn parse<'a>(
+        &self,
+        equation: &'a str,
+        arena: &'a EntityArena<'a, T>,
+    ) -> Result<Entity<'a, T>, EquationParseError> {
+        let second = self.parse_tokens(equation)?;
+        self.build_tree(&second[..], arena)
+    }
+
+    /// Parses an equation like [`Parser::parse`], also counting what it's made of.
+    pub fn parse_with_stats<'a>(
+        &self,
+        equation: &'a str,
+        arena: &'a EntityArena<'a, T>,
+    ) -> Result<(Entity<'a, T>, ParseStats), EquationParseError> {
+        let first = self.tokenize(equation)?;
+        let stats = ParseStats::from_tokens(&first);
+        let second = self.resolve(&first, equation)?;
+        Ok((self.build_tree(&second[..], arena)?, stats))
+    }
+
+    /// Parses an equation like [`Parser::parse`], calling `on_stage` with how long each
+    /// [`Stage`] took as soon as it's done, so a stage that fails is still reported.
+    #[cfg(feature = "std")]
+    pub fn parse_timed<'a>(
+        &self,
+        equation: &'a str,
+        arena: &'a EntityArena<'a, T>,
+        mut on_stage: impl FnMut(Stage, std::time::Duration),
+    ) -> Result<Entity<'a, T>, EquationParseError> {
+        use std::time::Instant;
+
+        let start = Instant::now();
+        let first = self.tokenize(equation);
+        on_stage(Stage::Tokenize, start.elapsed());
+
+        let start = Instant::now();
+        let second = self.resolve(&first?, equation);
+        on_stage(Stage::Resolve, start.elapsed());
+
+        let start = Instant::now();
+        let root = self.build_tree(&second?[..], arena);
+        on_stage(Stage::BuildTree, start.elapsed());
+        root
+    }
+
+    /// Runs only the first two stages of the parser, like [`parse_tokens`](crate::parse_tokens).
+    pub fn parse_tokens<'a>(
+        &self,
+        equation: &'a str,
+    ) -> Result<Vec<Syntax<'a, T>>, EquationParseError> {
+        let first = self.tokenize(equation)?;
+        self.resolve(&first, equation)
+    }
+
+    /// The function called `name`, or one of its aliases.
+    pub(crate) fn function(&self, name: &str) -> Result<FunctionType, EquationParseError> {
+        match self.function_aliases.get(name) {
+            Some(function) => Ok(*function),
+            None => name.parse(),
+        }
+    }
+
+    /// Splits an equation into stage-one tokens, like [`tokenize`](crate::tokenize),
+    /// with this parser's decimal separator and function aliases.
+    pub fn tokenize(&self, equation: &str) -> Result<Vec<RawSyntax>, EquationParseError> {
+        self.tokens(equation, 0).collect()
+    }
+
+    /// Updates the tokens of an edited equation, like [`retokenize`](crate::retokenize),
+    /// splitting it like [`Parser::tokenize`].
+    pub fn retokenize(
+        &self,
+        tokens: &mut Vec<RawSyntax>,
+        equation: &str,
+        edit_start: usize,
+    ) -> Result<(), EquationParseError> {
+        // a token can depend on the character right after it, like the `(` that makes `sin` a function
+        let kept = tokens
+            .iter()
+            .position(|token| token.span().end >= edit_start)
+            .unwrap_or(tokens.len());
+        tokens.truncate(kept);
+        // and identifiers and literals on any number after that, like the `2(` that makes `atan`
+        // part of `atan2(`, or the `e5` that makes `1` part of `1e5`
+        while let Some(
+            RawSyntax::ValueLit { .. } | RawSyntax::ValueIdent { .. } | RawSyntax::Function { .. },
+        ) = tokens.last()
+        {
+            tokens.pop();
+        }
+
+        let offset = tokens.last().map_or(0, |token| token.span().end);
+        for token in self.tokens(equation, offset) {
+            tokens.push(token?);
+        }
+        Ok(())
+    }
+
+    /// The tokens of `equation` from the byte offset `offset`, split with this parser's options.
+    fn tokens<'a>(&'a self, equation: &'a str, offset: usize) -> Tokens<'a> {
+        Tokens::starting_at(equation, offset)
+            .decimal_separator(self.decimal_separator)
+            .function_aliases(&self.function_aliases)
+    }
+
+    /// Resolves stage-one tokens of `equation` into stage-two [`Syntax`] tokens,
+    /// like [`resolve`](crate::resolve), with this parser's options.
+    pub fn resolve<'a>(
+        &self,
+        tokens: &[RawSyntax],
+        equation: &'a str,
+    ) -> Result<Vec<Syntax<'a, T>>, EquationParseError> {
+        validate_parens(tokens, self.max_depth, &mut Bail)?;
+        second_parse(tokens, equation, self, &mut Bail)
+    }
+
+    /// Builds an [`Entity`] tree out of stage-two [`Syntax`] tokens, like
+    /// [`build_tree`](crate::build_tree), nesting at most [`Parser::max_depth`] deep.
+    pub fn build_tree<'a>(
+        &self,
+        tokens: &[Syntax<'a, T>],
+        arena: &'a EntityArena<'a, T>,
+    ) -> Result<Entity<'a, T>, EquationParseError> {
+        third_parse(tokens, self.max_depth, arena)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ast::Operation;
+    use crate::parse;
+    use alloc::format;
+
+    fn nested(open: &str, close: &str, depth: usize) -> String {
+        format!("{}x{}", open.repeat(depth), close.repeat(depth))
+    }
+
+    fn depth(parser: &Parser<f64>, equation: &str) -> Result<usize, EquationParseError> {
+        let arena = EntityArena::new();
+        parser
+            .parse(equation, &arena)
+            .map(|root| crate::tree_depth(&root))
+    }
+
+    #[test]
+    fn max_depth_limits_parentheses() {
+        let parser = Parser::default();
+        assert!(matches!(
+            depth(&parser, &nested("(", ")", 1000)),
+            Err(EquationParseError::TooDeep { index: 128 })
+        ));
+        assert_eq!(depth(&parser, &nested("(", ")", 128)).unwrap(), 1);
+
+        let parser = Parser::default().max_depth(1000);
+        assert_eq!(depth(&parser, &nested("(", ")", 1000)).unwrap(), 1);
+    }
+
+    #[test]
+    fn max_depth_limits_nesting_without_parentheses() {
+        let parser = Parser::default().max_depth(50);
+        for equation in [
+            nested("-", "", 100),
+            nested("|", "|", 100),
+            nested("sin(", ")", 50),
+            ["2"; 100].join("^"),
+        ] {
+            assert!(
+                matches!(
+                    depth(&parser, &equation),
+                    Err(EquationParseError::NestedTooDeep)
+                ),
+                "{equation}"
+            );
+        }
+        assert_eq!(depth(&parser, &nested("-", "", 40)).unwrap(), 41);
+        assert_eq!(depth(&parser, &nested("sin(", ")", 20)).unwrap(), 21);
+    }
+
+    #[test]
+    fn max_depth_allows_long_flat_expressions() {
+        let parser = Parser::default().max_depth(10);
+        assert_eq!(depth(&parser, &["1"; 1200].join("+")).unwrap(), 1200);
+        assert_eq!(depth(&parser, &["x"; 1200].join("*")).unwrap(), 1200);
+    }
+
+    #[test]
+    fn toggles_implicit_multiplication() {
+        let arena = EntityArena::new();
+        let product =
+            Entity::Operation(Operation::Mul(&Entity::ValueLit(2.0), &Entity::Value("x")));
+        assert_eq!(
+            Parser::<f64>::default().parse("2x", &arena).unwrap(),
+            product
+        );
+        assert_eq!(
+            Parser::<f64>::default()
+                .implicit_multiplication(true)
+                .parse("2x", &arena)
+                .unwrap(),
+            product
+        );
+
+        let explicit = Parser::<f64>::default().implicit_multiplication(false);
+        assert!(matches!(
+            explicit.parse("2x", &arena),
+            Err(EquationParseError::MissingOperator { index: 1 })
+        ));
+        assert_eq!(explicit.parse("2*x", &arena).unwrap(), product);
+    }
+
+    #[test]
+    fn replaces_constants_with_literals() {
+        let arena = EntityArena::new();
+        let parser = Parser::default()
+            .constants([("g", 9.81), ("c", 3e8)])
+            .with_constant("c", 299_792_458.0);
+        assert_eq!(
+            parser.parse("g*t", &arena).unwrap(),
+            Entity::Operation(Operation::Mul(&Entity::ValueLit(9.81), &Entity::Value("t")))
+        );
+        assert_eq!(
+            parser.parse("c", &arena).unwrap(),
+            Entity::ValueLit(299_792_458.0)
+        );
+    }
+
+    #[test]
+    fn rejects_adjacent_identifiers_when_strict() {
+        let arena = EntityArena::new();
+        let product = Entity::Operation(Operation::Mul(&Entity::Value("x"), &Entity::Value("y")));
+        assert_eq!(
+            Parser::<f64>::default().parse("x y", &arena).unwrap(),
+            product
+        );
+
+        let strict = Parser::<f64>::default().implicit_multiplication(false);
+        assert!(matches!(
+            strict.parse("x y", &arena),
+            Err(EquationParseError::MissingOperator { index: 2 })
+        ));
+        assert!(matches!(
+            strict.parse("sin cos", &arena),
+            Err(EquationParseError::MissingOperator { index: 4 })
+        ));
+        assert_eq!(strict.parse("x*y", &arena).unwrap(), product);
+    }
+
+    #[test]
+    fn leaves_only_free_variables_after_constants() {
+        let arena = EntityArena::new();
+        let parser = Parser::default().with_constant("g", 9.81);
+        let entity = parser.parse("g*t^2/2", &arena).unwrap();
+        assert_eq!(crate::variables(&entity), ["t"]);
+        assert_eq!(crate::eval(&entity, &[("t", 2.0)][..]).unwrap(), 9.81 * 2.0);
+
+        // constants are folded away like any other literal
+        let folded = crate::simplify(&parser.parse("g*2", &arena).unwrap(), &arena);
+        assert_eq!(*folded, Entity::ValueLit(9.81 * 2.0));
+    }
+
+    #[test]
+    fn reads_percentages_in_percent_mode() {
+        let arena = EntityArena::new();
+        let no_vars: &[(&str, f64)] = &[];
+        let value = |parser: &Parser<f64>, equation| {
+            crate::eval(&parser.parse(equation, &arena).unwrap(), no_vars).unwrap()
+        };
+        let percent = Parser::default().percent_mode(true);
+        assert_eq!(value(&percent, "50%"), 0.5);
+        assert_eq!(value(&percent, "200*5%"), 10.0);
+        assert_eq!(value(&percent, "(1+1)%"), 0.02);
+
+        let modulo = Parser::default();
+        assert_eq!(value(&modulo, "7%4"), 3.0);
+        assert!(modulo.parse("50%", &arena).is_err());
+    }
+
+    #[test]
+    fn splits_letters_into_variables_when_asked() {
+        let arena = EntityArena::new();
+        let (x, y) = (&Entity::Value("x"), &Entity::Value("y"));
+        let words = Parser::<f64>::default();
+        assert_eq!(words.parse("xy", &arena).unwrap(), Entity::Value("xy"));
+        assert_eq!(words.parse("sin", &arena).unwrap(), Entity::Value("sin"));
+
+        let letters = Parser::<f64>::default().single_letter_variables(true);
+        assert_eq!(
+            letters.parse("xy", &arena).unwrap(),
+            Entity::Operation(Operation::Mul(x, y))
+        );
+        assert_eq!(
+            crate::variables(&letters.parse("sin", &arena).unwrap()),
+            ["s", "i", "n"]
+        );
+        // function calls and constants stay whole
+        assert_eq!(
+            letters.parse("sin(x)", &arena).unwrap(),
+            Entity::Function(crate::Function::Sin(x))
+        );
+        assert_eq!(
+            letters.parse("2pi", &arena).unwrap(),
+            Entity::Operation(Operation::Mul(&Entity::ValueLit(2.0), &Entity::Value("pi")))
+        );
+        let g = letters.with_constant("g", 9.81);
+        assert_eq!(
+            g.parse("gt", &arena).unwrap(),
+            Entity::Operation(Operation::Mul(&Entity::ValueLit(9.81), &Entity::Value("t")))
+        );
+    }
+
+    #[test]
+    fn rejects_square_and_curly_brackets_when_asked() {
+        let arena = EntityArena::new();
+        let sum = Entity::Operation(Operation::Add(
+            &Entity::ValueLit(2.0),
+            &Entity::ValueLit(3.0),
+        ));
+        for equation in ["[2+3]", "{2+3}", "(2+3)"] {
+            assert_eq!(Parser::default().parse(equation, &arena).unwrap(), sum);
+        }
+
+        let round = Parser::<f64>::default().only_round_parens(true);
+        assert!(matches!(
+            round.parse("[2+3]", &arena),
+            Err(EquationParseError::UnsupportedBracket { index: 0 })
+        ));
+        assert!(matches!(
+            round.parse("(2+{3})", &arena),
+            Err(EquationParseError::UnsupportedBracket { index: 3 })
+        ));
+        assert_eq!(round.parse("(2+3)", &arena).unwrap(), sum);
+    }
+
+    #[test]
+    fn accepts_function_aliases() {
+        let arena = EntityArena::new();
+        let x = &Entity::Value("x");
+        let default = Parser::<f64>::default();
+        assert_eq!(
+            default.parse("arcsin(x)", &arena).unwrap(),
+            Entity::Function(crate::Function::Asin(x))
+        );
+        assert_eq!(
+            default.parse("tg(x)", &arena).unwrap(),
+            Entity::Function(crate::Function::Tan(x))
+        );
+        assert_eq!(
+            default.parse("lg(x)", &arena).unwrap(),
+            Entity::Function(crate::Function::Log2(x))
+        );
+        assert!(default.parse("arkussinus(x)", &arena).is_err());
+
+        let custom = Parser::<f64>::default()
+            .with_function_alias("arkussinus", FunctionType::Asin)
+            // custom aliases win over built-in names
+            .with_function_alias("ln", FunctionType::Log2);
+        assert_eq!(
+            custom.parse("arkussinus(x)", &arena).unwrap(),
+            Entity::Function(crate::Function::Asin(x))
+        );
+        assert_eq!(
+            custom.parse("ln(x)", &arena).unwrap(),
+            Entity::Function(crate::Function::Log2(x))
+        );
+    }
+
+    #[test]
+    fn accepts_function_aliases_ending_in_digits() {
+        let arena = EntityArena::new();
+        let x = &Entity::Value("x");
+        let custom = Parser::<f64>::default().with_function_alias("log10", FunctionType::Log);
+        assert_eq!(
+            custom.parse("log10(x)", &arena).unwrap(),
+            Entity::Function(crate::Function::Log10(x))
+        );
+        // like built-in names, they can end a longer identifier
+        assert_eq!(
+            custom.parse("2xlog10(x)", &arena).unwrap(),
+            custom.parse("2x*log10(x)", &arena).unwrap()
+        );
+        // without the alias, it's still a name times a literal
+        assert!(matches!(
+            Parser::<f64>::default().parse("log10(x)", &arena),
+            Ok(Entity::Operation(Operation::Mul(..)))
+        ));
+    }
+
+    #[test]
+    fn applies_functions_without_parentheses_when_asked() {
+        let arena = EntityArena::new();
+        let sin_two = Entity::Function(crate::Function::Sin(&Entity::ValueLit(2.0)));
+        let sin_times_two = Entity::Operation(Operation::Mul(
+            &Entity::Value("sin"),
+            &Entity::ValueLit(2.0),
+        ));
+
+        let default = Parser::<f64>::default();
+        assert_eq!(default.parse("sin2", &arena).unwrap(), sin_times_two);
+        assert_eq!(default.parse("sin 2", &arena).unwrap(), sin_times_two);
+        assert_eq!(default.parse("sin(2)", &arena).unwrap(), sin_two);
+
+        let applied = Parser::<f64>::default().function_application(true);
+        assert_eq!(applied.parse("sin2", &arena).unwrap(), sin_two);
+        assert_eq!(applied.parse("sin 2", &arena).unwrap(), sin_two);
+        assert_eq!(applied.parse("sin(2)", &arena).unwrap(), sin_two);
+        assert_eq!(
+            applied.parse("sin cos x", &arena).unwrap(),
+            parse("sin(cos(x))", &arena).unwrap()
+        );
+        assert_eq!(
+            applied.parse("sin x^2", &arena).unwrap(),
+            parse("sin(x)^2", &arena).unwrap()
+        );
+    }
+
+    #[test]
+    fn rejects_functions_outside_the_allowed_set() {
+        let arena = EntityArena::new();
+        let sandbox = Parser::<f64>::default()
+            .allowed_functions([FunctionType::Exp])
+            .allowed_functions([FunctionType::Sin, FunctionType::Abs]);
+        let disallowed = |equation| match sandbox.parse(equation, &arena) {
+            Err(EquationParseError::DisallowedFunction { function, index }) => {
+                Some((function, index))
+            }
+            _ => None,
+        };
+
+        assert_eq!(disallowed("2*exp(x)"), Some((FunctionType::Exp, 2)));
+        assert_eq!(disallowed("√x"), Some((FunctionType::Sqrt, 0)));
+        assert_eq!(disallowed("sin(|x|)"), None);
+        assert_eq!(
+            sandbox.parse("sin(x)", &arena).unwrap(),
+            parse("sin(x)", &arena).unwrap()
+        );
+    }
+
+    #[test]
+    fn reads_comma_decimals() {
+        let arena = EntityArena::new();
+        let no_vars: &[(&str, f64)] = &[];
+        let comma = Parser::default().decimal_separator(',');
+        let value =
+            |equation| crate::eval(&comma.parse(equation, &arena).unwrap(), no_vars).unwrap();
+        assert_eq!(value("1,5+2,5"), 4.0);
+        assert_eq!(value("1.000,5"), 1000.5);
+        // semicolons separate arguments instead
+        assert_eq!(value("max(1,5; 2)"), 2.0);
+        assert_eq!(value("min(1,5; 2)"), 1.5);
+
+        let dot = Parser::<f64>::default();
+        assert!(matches!(
+            dot.parse("1,5+2,5", &arena),
+            Err(EquationParseError::MalformedExpression)
+        ));
+    }
+
+    #[test]
+    fn retokenizes_with_its_options() {
+        let comma = Parser::<f64>::default().decimal_separator(',');
+        let mut tokens = comma.tokenize("1+2").unwrap();
+        comma.retokenize(&mut tokens, "1+2,5", 3).unwrap();
+        assert_eq!(tokens, comma.tokenize("1+2,5").unwrap());
+        assert_eq!(tokens[2], RawSyntax::ValueLit { start: 2, end: 5 });
+
+        let alias = Parser::<f64>::default().with_function_alias("log10", FunctionType::Log);
+        let mut tokens = alias.tokenize("log1").unwrap();
+        alias.retokenize(&mut tokens, "log10(x)", 4).unwrap();
+        assert_eq!(tokens, alias.tokenize("log10(x)").unwrap());
+        assert_eq!(tokens[0], RawSyntax::Function { start: 0, end: 5 });
+    }
+
+    #[test]
+    #[should_panic(expected = "decimal separator")]
+    fn rejects_other_decimal_separators() {
+        let _ = Parser::<f64>::default().decimal_separator('\'');
+    }
+
+    #[test]
+    #[cfg(feature = "std")]
+    fn reports_every_stage() {
+        let arena = EntityArena::new();
+        let mut stages = Vec::new();
+        let root = Parser::<f64>::default()
+            .parse_timed("sin(x)+1", &arena, |stage, _| stages.push(stage))
+            .unwrap();
+        assert_eq!(crate::node_count(&root), 4);
+        assert_eq!(stages, [Stage::Tokenize, Stage::Resolve, Stage::BuildTree]);
+    }
+
+    #[test]
+    #[cfg(feature = "std")]
+    fn reports_the_stage_that_failed() {
+        let arena = EntityArena::new();
+        let mut stages = Vec::new();
+        let result =
+            Parser::<f64>::default().parse_timed("1 $ 2", &arena, |stage, _| stages.push(stage));
+        assert!(result.is_err());
+        assert_eq!(stages, [Stage::Tokenize]);
+    }
+}
@@ -0,0 +1,207 @@
+use crate::LiteralError;
+use crate::ast::{Entity, FunctionType, Operation};
+use crate::eval::{EvalError, Variables, fold, is_known_constant};
+use crate::numeric::Numeric;
+
+use alloc::string::ToString;
+use num_rational::Rational64;
+use num_traits::{CheckedAdd, CheckedDiv, CheckedMul, CheckedSub, Signed, Zero};
+
+impl Numeric for Rational64 {
+    fn from_decimal(literal: &str) -> Result<Self, LiteralError> {
+        // the float parser checks the syntax, then the digits are read exactly
+        literal.parse::<f64>()?;
+
+        let (mantissa, exponent) = match literal.split_once(['e', 'E']) {
+            Some((mantissa, exponent)) => (mantissa, exponent.parse::<i64>()),
+            None => (literal, Ok(0)),
+        };
+        let exponent = exponent.map_err(|_| LiteralError::Unrepresentable)?;
+        let (whole, fraction) = mantissa.split_once('.').unwrap_or((mantissa, ""));
+
+        let mut numer: i64 = 0;
+        for digit in whole.chars().chain(fraction.chars()) {
+            // `inf` and `NaN` pass the float parser, but aren't rational
+            let digit = digit.to_digit(10).ok_or(LiteralError::Unrepresentable)?;
+            numer = numer
+                .checked_mul(10)
+                .and_then(|numer| numer.checked_add(i64::from(digit)))
+                .ok_or(LiteralError::Unrepresentable)?;
+        }
+        if numer == 0 {
+            return Ok(Self::zero());
+        }
+
+        // `1.25e1` is `125 * 10^(1 - 2)`
+        let exponent = exponent - fraction.len() as i64;
+        let scale = usize::try_from(exponent.unsigned_abs())
+            .ok()
+            .and_then(|exponent| num_traits::checked_pow(10i64, exponent))
+            .ok_or(LiteralError::Unrepresentable)?;
+        match exponent >= 0 {
+            true => numer
+                .checked_mul(scale)
+                .map(Rational64::from_integer)
+                .ok_or(LiteralError::Unrepresentable),
+            false => Ok(Rational64::new(numer, scale)),
+        }
+    }
+
+    fn from_integer(integer: u64) -> Option<Self> {
+        i64::try_from(integer).ok().map(Rational64::from_integer)
+    }
+}
+
+/// Evaluates an [`Entity`] tree exactly, over rationals, so `1/3+1/6` is exactly `1/2`.
+/// The tree has to be parsed as a [`Rational64`] one, so its literals are exact too.
+///
+/// Only what has a rational result is supported: arithmetic, powers with an integer exponent,
+/// factorials, and `abs`, `ceil`, `floor`, `round`, `trunc`, `fract`, `signum`, `min`, `max`,
+/// `clamp` and `mod`. Anything else, like `sqrt(2)` or `pi`, and results that overflow, fail
+/// with [`EvalError::Inexact`]. Dividing by zero fails with [`EvalError::NotFinite`].
+pub fn eval_rational<V: Variables<Rational64> + ?Sized>(
+    entity: &Entity<'_, Rational64>,
+    vars: &V,
+) -> Result<Rational64, EvalError> {
+    fold(entity, |entity, args| match entity {
+        Entity::ValueLit(val) => Ok(*val),
+        Entity::Value(name) => match vars.get(name) {
+            Some(val) => Ok(val),
+            None if is_known_constant(name) => Err(EvalError::Inexact),
+            None => Err(EvalError::UndefinedVariable(name.to_string())),
+        },
+        Entity::Operation(op) => operation(op, args),
+        Entity::Function(func) => function(func.function_type(), args),
+    })
+}
+
+/// Applies `op` to the already evaluated values of its operands.
+fn operation(op: &Operation<'_, Rational64>, args: &[Rational64]) -> Result<Rational64, EvalError> {
+    match (*op, args) {
+        (Operation::Add(..), &[lhs, rhs]) => exact(lhs.checked_add(&rhs)),
+        (Operation::Sub(..), &[lhs, rhs]) => exact(lhs.checked_sub(&rhs)),
+        (Operation::Mul(..), &[lhs, rhs]) => exact(lhs.checked_mul(&rhs)),
+        (Operation::Div(..), &[lhs, rhs]) => divide(lhs, rhs),
+        (Operation::FloorDiv(..), &[lhs, rhs]) => Ok(divide(lhs, rhs)?.floor()),
+        (Operation::Pow(..), &[base, exponent]) => power(base, exponent),
+        (Operation::Rem(..), &[lhs, rhs]) => remainder(lhs, rhs),
+        (Operation::Neg(_), &[x]) => exact(Rational64::zero().checked_sub(&x)),
+        (Operation::Factorial(_), &[x]) => factorial(x),
+        _ => unreachable!("an operation has a value for each of its operands"),
+    }
+}
+
+fn function(function: FunctionType, args: &[Rational64]) -> Result<Rational64, EvalError> {
+    match (function, args) {
+        (FunctionType::Mod, &[x, y]) => remainder(x, y),
+        (FunctionType::Ceil, &[x]) => Ok(x.ceil()),
+        (FunctionType::Floor, &[x]) => Ok(x.floor()),
+        (FunctionType::Round, &[x]) => Ok(x.round()),
+        (FunctionType::Trunc, &[x]) => Ok(x.trunc()),
+        (FunctionType::Fract, &[x]) => Ok(x.fract()),
+        (FunctionType::Abs, &[x]) => Ok(x.abs()),
+        (FunctionType::Signum, &[x]) => Ok(x.signum()),
+        (FunctionType::Min, &[x, y]) => Ok(x.min(y)),
+        (FunctionType::Max, &[x, y]) => Ok(x.max(y)),
+        (FunctionType::Clamp, &[x, lo, hi]) => Ok(x.max(lo).min(hi)),
+        _ => Err(EvalError::Inexact),
+    }
+}
+
+/// Overflowing checked arithmetic gives `None`.
+fn exact(result: Option<Rational64>) -> Result<Rational64, EvalError> {
+    result.ok_or(EvalError::Inexact)
+}
+
+fn divide(lhs: Rational64, rhs: Rational64) -> Result<Rational64, EvalError> {
+    if rhs.is_zero() {
+        return Err(EvalError::NotFinite);
+    }
+    exact(lhs.checked_div(&rhs))
+}
+
+/// `lhs - rhs * trunc(lhs / rhs)`, so the sign follows `lhs`, like `%` on floats.
+fn remainder(lhs: Rational64, rhs: Rational64) -> Result<Rational64, EvalError> {
+    let quotient = divide(lhs, rhs)?.trunc();
+    exact(
+        rhs.checked_mul(&quotient)
+            .and_then(|product| lhs.checked_sub(&product)),
+    )
+}
+
+fn power(base: Rational64, exponent: Rational64) -> Result<Rational64, EvalError> {
+    if !exponent.is_integer() {
+        return Err(EvalError::Inexact);
+    }
+
+    let exponent = *exponent.numer();
+    let magnitude = usize::try_from(exponent.unsigned_abs()).map_err(|_| EvalError::Inexact)?;
+    let result = exact(num_traits::checked_pow(base, magnitude))?;
+    match exponent < 0 {
+        true => divide(Rational64::from_integer(1), result),
+        false => Ok(result),
+    }
+}
+
+fn factorial(n: Rational64) -> Result<Rational64, EvalError> {
+    if !n.is_integer() || n.is_negative() {
+        return Err(EvalError::Inexact);
+    }
+
+    let mut result: i64 = 1;
+    for i in 2..=*n.numer() {
+        result = result.checked_mul(i).ok_or(EvalError::Inexact)?;
+    }
+    Ok(Rational64::from_integer(result))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{EntityArena, parse};
+
+    fn eval(equation: &str) -> Result<Rational64, EvalError> {
+        let arena = EntityArena::new();
+        let entity = parse::<Rational64>(equation, &arena).unwrap();
+        eval_rational(&entity, &[("x", Rational64::new(2, 3))][..])
+    }
+
+    #[test]
+    fn evaluates_exactly() {
+        assert_eq!(eval("1/3+1/6").unwrap(), Rational64::new(1, 2));
+        assert_eq!(eval("0.1+0.2").unwrap(), Rational64::new(3, 10));
+        assert_eq!(eval("x^-2 * 3!").unwrap(), Rational64::new(27, 2));
+        assert_eq!(
+            eval("-7 % 3 + 7 // 2").unwrap(),
+            Rational64::from_integer(2)
+        );
+        assert_eq!(
+            eval("max(x, 1/2) - floor(x)").unwrap(),
+            Rational64::new(2, 3)
+        );
+    }
+
+    #[test]
+    fn fails_on_inexact_results() {
+        assert!(matches!(eval("sqrt(2)"), Err(EvalError::Inexact)));
+        assert!(matches!(eval("2*pi"), Err(EvalError::Inexact)));
+        assert!(matches!(eval("2^(1/2)"), Err(EvalError::Inexact)));
+        assert!(matches!(eval("1/(x-x)"), Err(EvalError::NotFinite)));
+        assert!(matches!(eval("y"), Err(EvalError::UndefinedVariable(_))));
+    }
+
+    #[test]
+    fn evaluates_trees_of_any_depth() {
+        let arena = EntityArena::new();
+        let one = arena.alloc(Entity::ValueLit(Rational64::from_integer(1)));
+        let mut sum = Entity::ValueLit(Rational64::from_integer(1));
+        for _ in 1..100_000 {
+            sum = Entity::Operation(Operation::Add(arena.alloc(sum), one));
+        }
+        let no_vars: &[(&str, Rational64)] = &[];
+        assert_eq!(
+            eval_rational(&sum, no_vars).unwrap(),
+            Rational64::from_integer(100_000)
+        );
+    }
+}
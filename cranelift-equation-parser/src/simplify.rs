@@ -0,0 +1,217 @@
+use crate::arena::EntityArena;
+use crate::ast::{Entity, Operation};
+use crate::eval::eval;
+
+use num_traits::FloatConst;
+
+/// Simplifies `entity`, allocating the new nodes in `arena`.
+///
+/// - Subexpressions made only of literals are folded into one literal, like `2+3` → `5`.
+///   The folded value is exactly what [`eval`] would compute, rounding included, so
+///   `0.1+0.2` becomes `0.30000000000000004`. Results that aren't finite, like `1/0`, are left
+///   unfolded. Named constants like `pi` are identifiers, and never folded.
+/// - Identity operations are dropped: `x+0`, `0+x`, `x-0`, `x*1`, `1*x`, `x/1`, `x^1` → `x`,
+///   `0-x` → `-x` and `--x` → `x`.
+/// - Annihilators win: `x*0` and `0*x` → `0`, `x^0` and `1^x` → `1`. This assumes `x` is finite,
+///   as `inf*0` would otherwise be NaN.
+/// - An operand subtracted from itself cancels: `x-x` → `0`, for any identical subtrees, like
+///   `sin(x)-sin(x)`. This is safe wherever `x` is finite, the same assumption as above.
+///
+/// Rules that change the result where an operand is undefined need
+/// [`SimplifyOptions::assume_defined`], see [`simplify_with_options`].
+pub fn simplify<'a, T: num_traits::Float + FloatConst>(
+    entity: &Entity<'a, T>,
+    arena: &'a EntityArena<'a, T>,
+) -> &'a Entity<'a, T> {
+    simplify_with_options(entity, arena, &SimplifyOptions::default())
+}
+
+#[derive(Debug, Clone, Copy, Default)]
+/// Knobs for [`simplify_with_options`].
+pub struct SimplifyOptions {
+    /// Assume every division is by something nonzero, which enables these rules, for any
+    /// identical subtrees `x`:
+    ///
+    /// - `x/x` and `x//x` → `1`, which is wrong where `x` is 0, as `0/0` is NaN.
+    /// - `x%x` → `0`, and `0/x` → `0`, which are wrong where `x` is 0 too.
+    ///
+    /// The simplified tree is then only equivalent where the original is defined, which is
+    /// usually what's wanted when simplifying by hand. Disabled by default.
+    pub assume_defined: bool,
+}
+
+/// Simplifies `entity` like [`simplify`], with custom [`SimplifyOptions`].
+pub fn simplify_with_options<'a, T: num_traits::Float + FloatConst>(
+    entity: &Entity<'a, T>,
+    arena: &'a EntityArena<'a, T>,
+    options: &SimplifyOptions,
+) -> &'a Entity<'a, T> {
+    Simplifier { arena, options }.simplify(entity)
+}
+
+struct Simplifier<'a, 'o, T> {
+    arena: &'a EntityArena<'a, T>,
+    options: &'o SimplifyOptions,
+}
+
+impl<'a, T: num_traits::Float + FloatConst> Simplifier<'a, '_, T> {
+    fn simplify(&self, entity: &Entity<'a, T>) -> &'a Entity<'a, T> {
+        match entity {
+            Entity::ValueLit(_) | Entity::Value(_) => self.arena.alloc(*entity),
+            Entity::Operation(op) => self.operation(op.map(|operand| self.simplify(operand))),
+            Entity::Function(func) => {
+                let func = func.map(|arg| self.simplify(arg));
+                self.arena.alloc(self.fold(Entity::Function(func)))
+            }
+        }
+    }
+
+    /// Simplifies an operation whose operands are already simplified.
+    fn operation(&self, op: Operation<'a, T>) -> &'a Entity<'a, T> {
+        match self.identity(&op) {
+            Some(simplified) => simplified,
+            None => self.arena.alloc(self.fold(Entity::Operation(op))),
+        }
+    }
+
+    /// Evaluates `entity` if all of its children are literals.
+    fn fold(&self, entity: Entity<'a, T>) -> Entity<'a, T> {
+        let literal = |child: &&Entity<'a, T>| matches!(child, Entity::ValueLit(_));
+        if !entity.children().iter().all(literal) {
+            return entity;
+        }
+
+        let no_vars: &[(&str, T)] = &[];
+        match eval(&entity, no_vars) {
+            Ok(val) if val.is_finite() => Entity::ValueLit(val),
+            _ => entity,
+        }
+    }
+
+    /// Applies the identity, annihilator and cancellation rules.
+    fn identity(&self, op: &Operation<'a, T>) -> Option<&'a Entity<'a, T>> {
+        let is =
+            |entity: &Entity<'a, T>, val: T| matches!(*entity, Entity::ValueLit(lit) if lit == val);
+        let (zero, one) = (T::zero(), T::one());
+        let literal = |val: T| Some(self.arena.alloc(Entity::ValueLit(val)));
+        let defined = self.options.assume_defined;
+
+        match *op {
+            Operation::Add(x, rhs) if is(rhs, zero) => Some(x),
+            Operation::Add(lhs, x) if is(lhs, zero) => Some(x),
+            Operation::Sub(x, rhs) if is(rhs, zero) => Some(x),
+            Operation::Sub(lhs, x) if is(lhs, zero) => Some(self.operation(Operation::Neg(x))),
+            Operation::Mul(lhs, rhs) if is(lhs, zero) || is(rhs, zero) => {
+                Some(self.arena.alloc(Entity::ValueLit(zero)))
+            }
+            Operation::Mul(x, rhs) if is(rhs, one) => Some(x),
+            Operation::Mul(lhs, x) if is(lhs, one) => Some(x),
+            Operation::Div(x, rhs) if is(rhs, one) => Some(x),
+            Operation::Pow(lhs, rhs) if is(rhs, zero) || is(lhs, one) => {
+                Some(self.arena.alloc(Entity::ValueLit(one)))
+            }
+            Operation::Pow(x, rhs) if is(rhs, one) => Some(x),
+            Operation::Neg(Entity::Operation(Operation::Neg(x))) => Some(x),
+            Operation::Sub(lhs, rhs) if lhs == rhs => literal(zero),
+
+            Operation::Div(lhs, rhs) | Operation::FloorDiv(lhs, rhs) if defined && lhs == rhs => {
+                literal(one)
+            }
+            Operation::Rem(lhs, rhs) if defined && lhs == rhs => literal(zero),
+            Operation::Div(lhs, _) if defined && is(lhs, zero) => literal(zero),
+            _ => None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parse;
+
+    fn assert_simplifies(equation: &str, expected: &str, options: &SimplifyOptions) {
+        let arena = EntityArena::new();
+        let entity = parse::<f64>(equation, &arena).unwrap();
+        assert_eq!(
+            *simplify_with_options(&entity, &arena, options),
+            parse::<f64>(expected, &arena).unwrap(),
+            "{equation} should simplify to {expected}"
+        );
+    }
+
+    fn simplifies(equation: &str, expected: &str) {
+        assert_simplifies(equation, expected, &SimplifyOptions::default());
+    }
+
+    #[test]
+    fn folds_literals() {
+        simplifies("2+3", "5");
+        simplifies("2*3+x", "6+x");
+        simplifies("sqrt(16)*x", "4*x");
+        simplifies("0.1+0.2", "0.30000000000000004");
+        // not finite, so left alone
+        simplifies("1/0", "1/0");
+        simplifies("pi*2", "pi*2");
+    }
+
+    #[test]
+    fn drops_identities() {
+        for equation in ["x+0", "0+x", "x-0", "x*1", "1*x", "x/1", "x^1", "--x"] {
+            simplifies(equation, "x");
+        }
+        simplifies("0-x", "-x");
+        simplifies("(x+0)*(y^1)", "x*y");
+    }
+
+    #[test]
+    fn applies_annihilators() {
+        simplifies("x*0", "0");
+        simplifies("0*sin(x)", "0");
+        simplifies("x^0", "1");
+        simplifies("1^x", "1");
+    }
+
+    #[test]
+    fn cancels_subtraction() {
+        simplifies("x-x", "0");
+        simplifies("sin(x)-sin(x)", "0");
+        simplifies("x-y", "x-y");
+    }
+
+    #[test]
+    fn cancels_division_when_assuming_defined() {
+        simplifies("x/x", "x/x");
+        simplifies("0/x", "0/x");
+
+        let defined = SimplifyOptions {
+            assume_defined: true,
+        };
+        assert_simplifies("x/x", "1", &defined);
+        assert_simplifies("x//x", "1", &defined);
+        assert_simplifies("x%x", "0", &defined);
+        assert_simplifies("0/x", "0", &defined);
+    }
+
+    #[test]
+    fn only_changes_undefined_points_when_assuming_defined() {
+        let arena = EntityArena::new();
+        let defined = SimplifyOptions {
+            assume_defined: true,
+        };
+        let at = |entity: &Entity<'_, f64>, x: f64| eval(entity, &[("x", x)][..]).unwrap();
+
+        for equation in ["x/x", "x//x", "x%x", "0/x", "(x+1)-(x+1)"] {
+            let entity = parse::<f64>(equation, &arena).unwrap();
+            let safe = simplify(&entity, &arena);
+            let assuming = simplify_with_options(&entity, &arena, &defined);
+            for x in [-2.5, 1.0, 3.0] {
+                assert_eq!(at(assuming, x), at(&entity, x), "{equation} at {x}");
+                assert_eq!(at(safe, x), at(&entity, x), "{equation} at {x}");
+            }
+            // the default rules stay exact where `x` is 0, but the assuming ones don't
+            let original = at(&entity, 0.0);
+            assert_eq!(at(safe, 0.0).to_bits(), original.to_bits(), "{equation}");
+            assert!(!at(assuming, 0.0).is_nan());
+        }
+    }
+}
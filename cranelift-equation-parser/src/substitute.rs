@@ -0,0 +1,72 @@
+use crate::arena::EntityArena;
+use crate::ast::Entity;
+
+/// Replaces every occurrence of the variable `var` in `entity` with `replacement`,
+/// allocating the new nodes in `arena`. Substituting `t+1` for `x` in `x^2` gives `(t+1)^2`.
+pub fn substitute<'a, T: Copy>(
+    entity: &Entity<'a, T>,
+    var: &str,
+    replacement: &Entity<'a, T>,
+    arena: &'a EntityArena<'a, T>,
+) -> &'a Entity<'a, T> {
+    // every occurrence shares the same copy of `replacement`
+    let replacement = arena.alloc(*replacement);
+    Substitution {
+        var,
+        replacement,
+        arena,
+    }
+    .substitute(entity)
+}
+
+struct Substitution<'a, 'v, T> {
+    var: &'v str,
+    replacement: &'a Entity<'a, T>,
+    arena: &'a EntityArena<'a, T>,
+}
+
+impl<'a, T: Copy> Substitution<'a, '_, T> {
+    fn substitute(&self, entity: &Entity<'a, T>) -> &'a Entity<'a, T> {
+        match entity {
+            Entity::Value(name) if *name == self.var => self.replacement,
+            Entity::ValueLit(_) | Entity::Value(_) => self.arena.alloc(*entity),
+            Entity::Operation(op) => self.arena.alloc(Entity::Operation(
+                op.map(|operand| self.substitute(operand)),
+            )),
+            Entity::Function(func) => self
+                .arena
+                .alloc(Entity::Function(func.map(|arg| self.substitute(arg)))),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parse;
+
+    fn substituted(equation: &str, var: &str, replacement: &str, expected: &str) {
+        let arena = EntityArena::new();
+        let entity = parse::<f64>(equation, &arena).unwrap();
+        let replacement = parse(replacement, &arena).unwrap();
+        assert_eq!(
+            *substitute(&entity, var, &replacement, &arena),
+            parse(expected, &arena).unwrap()
+        );
+    }
+
+    #[test]
+    fn substitutes_variables() {
+        substituted("x^2", "x", "t+1", "(t+1)^2");
+        substituted("x+y", "y", "2", "x+2");
+        substituted("x+y", "z", "2", "x+y");
+    }
+
+    #[test]
+    fn substitutes_nested_occurrences() {
+        substituted("sin(x)*max(x, y^x)", "x", "2t", "sin(2t)*max(2t, y^(2t))");
+        // the replacement isn't substituted into again
+        substituted("x^2", "x", "x+1", "(x+1)^2");
+        substituted("ln(g)", "g", "exp(x)", "ln(exp(x))");
+    }
+}
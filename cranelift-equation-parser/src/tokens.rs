@@ -0,0 +1,469 @@
+use crate::EquationParseError;
+use crate::ast::{FunctionType, Operator, ParenthesisType, RawSyntax, RelationOp};
+
+use alloc::collections::BTreeMap;
+use alloc::string::String;
+use core::str::{CharIndices, FromStr};
+
+/// Lazily splits an equation into its stage-one [`RawSyntax`] tokens, scanning only as far as
+/// the next token. [`tokenize`](crate::tokenize) collects these into a `Vec`.
+pub struct Tokens<'a> {
+    equation: &'a str,
+    chars: CharIndices<'a>,
+    /// Where `chars` starts in `equation`
+    offset: usize,
+    /// Where the identifier or literal being read started, and whether it's an identifier
+    last_start_index: Option<(usize, bool)>,
+    /// Characters before this index were already folded into the current token
+    skip_until: usize,
+    /// A token read together with the previous one, like the `+` that ended `x` in `x+`,
+    /// or the error for the character that ended it
+    queued: Option<Result<RawSyntax, EquationParseError>>,
+    /// `.`, or `,` with `;` separating arguments instead
+    decimal_separator: char,
+    /// Other names for functions, which can end in digits too, like `log10(`
+    function_aliases: Option<&'a BTreeMap<String, FunctionType>>,
+}
+
+impl<'a> Tokens<'a> {
+    pub fn new(equation: &'a str) -> Self {
+        Self::starting_at(equation, 0)
+    }
+
+    /// Splits only the part of `equation` from the byte offset `offset`, which has to be where
+    /// a token ends, like [`retokenize`](crate::retokenize) does.
+    /// The tokens' ranges are still offsets into the whole `equation`.
+    pub fn starting_at(equation: &'a str, offset: usize) -> Self {
+        Self {
+            equation,
+            chars: equation[offset..].char_indices(),
+            offset,
+            last_start_index: None,
+            skip_until: offset,
+            queued: None,
+            decimal_separator: '.',
+            function_aliases: None,
+        }
+    }
+
+    /// With `,`, reads it as part of literals and `;` as the argument separator instead,
+    /// like [`Parser::decimal_separator`](crate::Parser::decimal_separator).
+    /// Any other separator keeps the default, `.`.
+    pub fn decimal_separator(mut self, separator: char) -> Self {
+        self.decimal_separator = separator;
+        self
+    }
+
+    /// Reads these names as functions too, like [`Parser::with_function_alias`](crate::Parser::with_function_alias),
+    /// so an alias ending in digits isn't split into a name and a literal.
+    pub(crate) fn function_aliases(mut self, aliases: &'a BTreeMap<String, FunctionType>) -> Self {
+        self.function_aliases = Some(aliases);
+        self
+    }
+
+    /// Whether `name` is a function name or alias, or ends in one after a variable, like `xatan2`.
+    fn ends_in_function(&self, name: &str) -> bool {
+        name.char_indices().any(|(offset, _)| {
+            let suffix = &name[offset..];
+            FunctionType::from_str(suffix).is_ok()
+                || self
+                    .function_aliases
+                    .is_some_and(|aliases| aliases.contains_key(suffix))
+        })
+    }
+
+    /// Reads one character. It can end the identifier or literal before it,
+    /// and be a token of its own, so this returns up to two tokens.
+    /// The second one is an error if the character can't be part of any token.
+    fn scan(
+        &mut self,
+        index: usize,
+        value: char,
+    ) -> (
+        Option<RawSyntax>,
+        Option<Result<RawSyntax, EquationParseError>>,
+    ) {
+        let equation = self.equation;
+        if index < self.skip_until {
+            return (None, None);
+        }
+
+        // digit separators, like `1_000`. Misplaced ones are kept in the literal,
+        // like the one in `_1`, so `second_parse` can reject them
+        if value == '_' {
+            match self.last_start_index {
+                Some((_, false)) => return (None, None),
+                _ if equation[index + 1..].starts_with(|c: char| c.is_ascii_digit()) => {
+                    let word = self
+                        .last_start_index
+                        .map(|(start, _)| RawSyntax::ValueIdent { start, end: index });
+                    self.last_start_index = Some((index, false));
+                    return (word, None);
+                }
+                _ => {}
+            }
+        }
+
+        // with a decimal comma, `.` is still read, as a digit separator like in `1.000,5`
+        if (value.is_numeric() && superscript_digit(value).is_none())
+            || value == '.'
+            || value == self.decimal_separator
+        {
+            let word = match self.last_start_index {
+                None => None,
+                Some((_, false)) => return (None, None),
+                Some((start, true)) => {
+                    // function names can end in digits, like `atan2(`, or `xatan2(`
+                    let digits = equation[index..]
+                        .bytes()
+                        .take_while(u8::is_ascii_digit)
+                        .count();
+                    if equation[index + digits..].starts_with('(')
+                        && self.ends_in_function(&equation[start..index + digits])
+                    {
+                        self.skip_until = index + digits;
+                        return (None, None);
+                    }
+                    Some(RawSyntax::ValueIdent { start, end: index })
+                }
+            };
+
+            // hexadecimal and binary integers, like `0xFF` or `0b1010`
+            if let Some(len) = radix_prefix_length(&equation[index..]) {
+                self.skip_until = index + len;
+            }
+
+            self.last_start_index = Some((index, false));
+            return (word, None);
+        } else if value.is_alphabetic() && value != 'π' {
+            let word = match self.last_start_index {
+                None => None,
+                Some((_, true)) => return (None, None),
+                Some((start, false)) => {
+                    // scientific notation, like `1e5` or `2.5E-3`
+                    if let Some(len) = exponent_length(&equation[index..]) {
+                        self.skip_until = index + len;
+                        return (None, None);
+                    }
+                    Some(RawSyntax::ValueLit { start, end: index })
+                }
+            };
+
+            self.last_start_index = Some((index, true));
+            return (word, None);
+        }
+
+        let word = match self.last_start_index.take() {
+            None => None,
+            Some((start, false)) => Some(RawSyntax::ValueLit { start, end: index }),
+            Some((start, true)) if value == '(' => Some(RawSyntax::Function { start, end: index }),
+            Some((start, true)) => Some(RawSyntax::ValueIdent { start, end: index }),
+        };
+
+        let (start, end) = (index, index + value.len_utf8());
+
+        let symbol = match value {
+            _ if value.is_whitespace() => None,
+            // a comment, until the end of the line
+            '#' => {
+                self.skip_until = equation[start..]
+                    .find('\n')
+                    .map_or(equation.len(), |len| start + len);
+                None
+            }
+            ',' => Some(RawSyntax::Comma { start, end }),
+            ';' if self.decimal_separator == ',' => Some(RawSyntax::Comma { start, end }),
+            '|' => Some(RawSyntax::Abs { start, end }),
+            // `π` is an identifier of its own, so `2πr` is `2*π*r`
+            'π' => Some(RawSyntax::ValueIdent { start, end }),
+            '=' | '<' | '>' => {
+                let or_equal = value != '=' && equation[end..].starts_with('=');
+                let kind = match (value, or_equal) {
+                    ('<', false) => RelationOp::Lt,
+                    ('<', true) => RelationOp::Le,
+                    ('>', false) => RelationOp::Gt,
+                    ('>', true) => RelationOp::Ge,
+                    _ => RelationOp::Eq,
+                };
+                let end = end + or_equal as usize;
+
+                self.skip_until = end;
+                Some(RawSyntax::Relation { kind, start, end })
+            }
+            _ => {
+                if let Ok(kind) = ParenthesisType::try_from(value) {
+                    Some(RawSyntax::Parenthesis { kind, start, end })
+                } else if superscript_digit(value).is_some() {
+                    let len: usize = equation[start..]
+                        .chars()
+                        .take_while(|c| superscript_digit(*c).is_some())
+                        .map(char::len_utf8)
+                        .sum();
+
+                    self.skip_until = start + len;
+                    Some(RawSyntax::Superscript {
+                        start,
+                        end: start + len,
+                    })
+                } else if let Ok(kind) = Operator::try_from(value) {
+                    // Python-style `**` power and `//` floor division
+                    let (kind, end) = match (value, equation[end..].chars().next()) {
+                        ('*', Some('*')) => (Operator::Pow, end + 1),
+                        ('/', Some('/')) => (Operator::FloorDiv, end + 1),
+                        _ => (kind, end),
+                    };
+
+                    self.skip_until = end;
+                    Some(RawSyntax::Operator { kind, start, end })
+                } else {
+                    let err = EquationParseError::UnexpectedCharacter { ch: value, index };
+                    return (word, Some(Err(err)));
+                }
+            }
+        };
+
+        (word, symbol.map(Ok))
+    }
+}
+
+impl Iterator for Tokens<'_> {
+    type Item = Result<RawSyntax, EquationParseError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if let Some(token) = self.queued.take() {
+            return Some(token);
+        }
+
+        while let Some((index, value)) = self.chars.next() {
+            match self.scan(self.offset + index, value) {
+                (Some(word), symbol) => {
+                    self.queued = symbol;
+                    return Some(Ok(word));
+                }
+                (None, Some(symbol)) => return Some(symbol),
+                (None, None) => {}
+            }
+        }
+
+        let end = self.equation.len();
+        self.last_start_index.take().map(|(start, ident)| {
+            Ok(match ident {
+                true => RawSyntax::ValueIdent { start, end },
+                false => RawSyntax::ValueLit { start, end },
+            })
+        })
+    }
+}
+
+/// If `s` starts with an exponent suffix like `e5`, `E+2` or `e-3`, returns its length in bytes.
+fn exponent_length(s: &str) -> Option<usize> {
+    let bytes = s.as_bytes();
+    if !matches!(bytes.first(), Some(b'e' | b'E')) {
+        return None;
+    }
+
+    let sign = matches!(bytes.get(1), Some(b'+' | b'-')) as usize;
+    let digits = bytes[1 + sign..]
+        .iter()
+        .take_while(|b| b.is_ascii_digit())
+        .count();
+
+    (digits > 0).then_some(1 + sign + digits)
+}
+
+/// The value of a superscript digit, like `3` for `³`.
+pub(crate) fn superscript_digit(value: char) -> Option<u32> {
+    match value {
+        '⁰' => Some(0),
+        '¹' => Some(1),
+        '²' => Some(2),
+        '³' => Some(3),
+        '⁴'..='⁹' => Some(value as u32 - '⁴' as u32 + 4),
+        _ => None,
+    }
+}
+
+/// If `s` starts with a `0x` or `0b` integer, like `0xFF`, returns its length in bytes.
+fn radix_prefix_length(s: &str) -> Option<usize> {
+    let radix = match s.as_bytes() {
+        [b'0', b'x' | b'X', ..] => 16,
+        [b'0', b'b' | b'B', ..] => 2,
+        _ => return None,
+    };
+
+    // without a digit, like in `0b`, it's `0` times `b`
+    let digits = &s[2..];
+    if !digits.starts_with(|c: char| c.is_digit(radix)) {
+        return None;
+    }
+
+    let len = digits
+        .chars()
+        .take_while(|c| c.is_digit(radix) || *c == '_')
+        .count();
+    Some(2 + len)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ast::{Entity, Operation};
+    use crate::{EntityArena, eval, parse, tokenize};
+
+    #[test]
+    fn reads_scientific_notation() {
+        for (equation, expected) in [("1e5", 1e5), ("1.5e-3", 1.5e-3), ("3E+2", 3e2)] {
+            assert_eq!(
+                tokenize(equation).unwrap(),
+                [RawSyntax::ValueLit {
+                    start: 0,
+                    end: equation.len()
+                }]
+            );
+            let arena = EntityArena::new();
+            let entity = parse::<f64>(equation, &arena).unwrap();
+            assert_eq!(eval(&entity, &[][..]).unwrap(), expected);
+        }
+    }
+
+    #[test]
+    fn keeps_bare_e_an_identifier() {
+        assert_eq!(
+            tokenize("e").unwrap(),
+            [RawSyntax::ValueIdent { start: 0, end: 1 }]
+        );
+        // `2e` has no exponent digits, so it's `2*e`
+        assert_eq!(
+            tokenize("2e").unwrap(),
+            [
+                RawSyntax::ValueLit { start: 0, end: 1 },
+                RawSyntax::ValueIdent { start: 1, end: 2 }
+            ]
+        );
+    }
+
+    #[test]
+    fn reads_two_character_operators() {
+        let operator = |kind, start, end| RawSyntax::Operator { kind, start, end };
+        assert_eq!(tokenize("2**3").unwrap()[1], operator(Operator::Pow, 1, 3));
+        assert_eq!(
+            tokenize("7//2").unwrap()[1],
+            operator(Operator::FloorDiv, 1, 3)
+        );
+        assert_eq!(tokenize("2*3").unwrap()[1], operator(Operator::Mul, 1, 2));
+        assert_eq!(tokenize("7/2").unwrap()[1], operator(Operator::Div, 1, 2));
+
+        let arena = EntityArena::new();
+        let value = |equation| eval(&parse::<f64>(equation, &arena).unwrap(), &[][..]).unwrap();
+        assert_eq!(value("2**3"), 8.0);
+        assert_eq!(value("2**3**2"), 512.0);
+        assert_eq!(value("7//2"), 3.0);
+        assert_eq!(value("-7//2"), -4.0);
+        assert_eq!(value("2*3"), 6.0);
+        assert_eq!(value("7/2"), 3.5);
+    }
+
+    #[test]
+    fn iterates_like_tokenize() {
+        use alloc::vec::Vec;
+
+        for equation in [
+            "2+x",
+            "sin(x)^2",
+            "|x| * 3!",
+            "max(1.5e3, y) // 2",
+            "x <= 2",
+        ] {
+            let tokens: Vec<_> = Tokens::new(equation).map(Result::unwrap).collect();
+            assert_eq!(tokens, tokenize(equation).unwrap());
+            assert!(!tokens.is_empty());
+        }
+
+        // tokens before an error are read without scanning past it
+        let mut tokens = Tokens::new("1+$+2");
+        assert_eq!(
+            tokens.next().unwrap().unwrap(),
+            RawSyntax::ValueLit { start: 0, end: 1 }
+        );
+        assert!(tokens.next().unwrap().is_ok());
+        assert!(tokens.next().unwrap().is_err());
+    }
+
+    #[test]
+    fn reads_unicode_math_symbols() {
+        let arena = EntityArena::new();
+        let value = |equation| eval(&parse::<f64>(equation, &arena).unwrap(), &[][..]).unwrap();
+        assert_eq!(value("2×3"), 6.0);
+        assert_eq!(value("6÷2"), 3.0);
+        assert_eq!(value("5−2"), 3.0);
+        assert_eq!(value("−2"), -2.0);
+        assert_eq!(value("√4"), 2.0);
+        assert_eq!(value("2√9"), 6.0);
+        assert_eq!(value("π"), core::f64::consts::PI);
+        assert_eq!(value("2π"), core::f64::consts::TAU);
+
+        // ranges cover the whole multibyte character
+        assert_eq!(
+            tokenize("2×3").unwrap()[1],
+            RawSyntax::Operator {
+                kind: Operator::Mul,
+                start: 1,
+                end: 3
+            }
+        );
+    }
+
+    #[test]
+    fn reads_superscript_exponents() {
+        let arena = EntityArena::new();
+        let x = &Entity::Value("x");
+        let power = |n| Entity::Operation(Operation::Pow(x, arena.alloc(Entity::ValueLit(n))));
+        assert_eq!(parse::<f64>("x²", &arena).unwrap(), power(2.0));
+        assert_eq!(parse::<f64>("x³", &arena).unwrap(), power(3.0));
+        assert_eq!(parse::<f64>("x¹²", &arena).unwrap(), power(12.0));
+        assert_eq!(
+            tokenize("x¹²").unwrap()[1],
+            RawSyntax::Superscript { start: 1, end: 5 }
+        );
+
+        let value = |equation| eval(&parse::<f64>(equation, &arena).unwrap(), &[][..]).unwrap();
+        assert_eq!(value("2⁵"), 32.0);
+        assert_eq!(value("3²+4²"), 25.0);
+        assert_eq!(value("-2²"), -4.0);
+    }
+
+    #[test]
+    fn rejects_unexpected_characters() {
+        let unexpected = |equation| match tokenize(equation) {
+            Err(EquationParseError::UnexpectedCharacter { ch, index }) => Some((ch, index)),
+            _ => None,
+        };
+        assert_eq!(unexpected("2@3"), Some(('@', 1)));
+        assert_eq!(unexpected("5$"), Some(('$', 1)));
+        assert_eq!(unexpected("x+€"), Some(('€', 2)));
+        assert_eq!(unexpected("2 + 3"), None);
+    }
+
+    #[test]
+    fn skips_comments() {
+        assert_eq!(
+            tokenize("2+3 # add them").unwrap(),
+            tokenize("2+3").unwrap()
+        );
+        assert!(tokenize("# nothing here").unwrap().is_empty());
+        // a comment ends at the end of its line
+        let tokens = tokenize("2 # two\n+ 3").unwrap();
+        assert_eq!(tokens.len(), 3);
+        assert_eq!(tokens[2], RawSyntax::ValueLit { start: 10, end: 11 });
+
+        let arena = EntityArena::new();
+        assert!(matches!(
+            parse::<f64>("# a comment-only line\n", &arena),
+            Err(EquationParseError::EmptyInput)
+        ));
+        assert_eq!(
+            eval(&parse::<f64>("2+3 # add them", &arena).unwrap(), &[][..]).unwrap(),
+            5.0
+        );
+    }
+}
@@ -0,0 +1,106 @@
+use crate::ast::{Entity, Function, Operation};
+
+/// A pass over an [`Entity`] tree, driven by [`walk`].
+/// Every method does nothing by default, so a visitor only implements the nodes it cares about.
+pub trait Visitor<'a, T> {
+    fn visit_literal(&mut self, _value: T) {}
+    fn visit_value(&mut self, _name: &'a str) {}
+    /// Called before the operands of `operation` are walked.
+    fn visit_operation(&mut self, _operation: &Operation<'a, T>) {}
+    /// Called before the arguments of `function` are walked.
+    fn visit_function(&mut self, _function: &Function<'a, T>) {}
+}
+
+/// Walks every node of `entity` depth-first, parents before their children,
+/// and children from left to right, calling the matching method of `visitor`.
+pub fn walk<'a, T: Copy>(entity: &Entity<'a, T>, visitor: &mut impl Visitor<'a, T>) {
+    match entity {
+        Entity::ValueLit(val) => visitor.visit_literal(*val),
+        Entity::Value(name) => visitor.visit_value(name),
+        Entity::Operation(op) => visitor.visit_operation(op),
+        Entity::Function(func) => visitor.visit_function(func),
+    }
+
+    for child in entity.children() {
+        walk(child, visitor);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ast::FunctionType;
+    use crate::{EntityArena, parse};
+    use alloc::vec::Vec;
+
+    /// Counts the function calls in an equation, like the 2 in `sin(x)+cos(x)`.
+    struct FunctionCounter {
+        calls: usize,
+    }
+
+    impl<'a, T> Visitor<'a, T> for FunctionCounter {
+        fn visit_function(&mut self, _function: &Function<'a, T>) {
+            self.calls += 1;
+        }
+    }
+
+    /// Writes down every node it visits, in order.
+    #[derive(Default)]
+    struct Trace<'a> {
+        nodes: Vec<Node<'a>>,
+    }
+
+    #[derive(Debug, PartialEq)]
+    enum Node<'a> {
+        Literal(f64),
+        Value(&'a str),
+        Operation,
+        Function(FunctionType),
+    }
+
+    impl<'a> Visitor<'a, f64> for Trace<'a> {
+        fn visit_literal(&mut self, value: f64) {
+            self.nodes.push(Node::Literal(value));
+        }
+
+        fn visit_value(&mut self, name: &'a str) {
+            self.nodes.push(Node::Value(name));
+        }
+
+        fn visit_operation(&mut self, _operation: &Operation<'a, f64>) {
+            self.nodes.push(Node::Operation);
+        }
+
+        fn visit_function(&mut self, function: &Function<'a, f64>) {
+            self.nodes.push(Node::Function(function.function_type()));
+        }
+    }
+
+    #[test]
+    fn counts_function_calls() {
+        let arena = EntityArena::new();
+        let entity = parse::<f64>("sin(x)+cos(x)", &arena).unwrap();
+        let mut counter = FunctionCounter { calls: 0 };
+        walk(&entity, &mut counter);
+        assert_eq!(counter.calls, 2);
+    }
+
+    #[test]
+    fn walks_parents_before_children() {
+        let arena = EntityArena::new();
+        let entity = parse::<f64>("sin(x)+2*y", &arena).unwrap();
+        let mut trace = Trace::default();
+        walk(&entity, &mut trace);
+        assert_eq!(
+            trace.nodes,
+            [
+                Node::Operation,
+                Node::Function(FunctionType::Sin),
+                Node::Value("x"),
+                Node::Operation,
+                Node::Literal(2.0),
+                Node::Value("y"),
+            ]
+        );
+    }
+}
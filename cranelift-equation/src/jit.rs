@@ -0,0 +1,353 @@
+use crate::libcall::{self, Binary, Ternary, Unary};
+
+use cranelift_codegen::CodegenError;
+use cranelift_codegen::ir::{
+    AbiParam, InstBuilder, MemFlagsData, Signature, Type, UserFuncName, Value, types,
+};
+use cranelift_codegen::settings::{self, Configurable};
+use cranelift_equation_parser::{
+    Entity, Function, Operation, fold, integer_exponent, known_constant,
+};
+use cranelift_frontend::{FunctionBuilder, FunctionBuilderContext};
+use cranelift_jit::{JITBuilder, JITModule};
+use cranelift_module::{Linkage, Module, ModuleError, default_libcall_names};
+use thiserror::Error;
+
+/// A native function compiled by [`jit_compile`]. It owns the memory its code lives in.
+pub struct JitFunction {
+    module: Option<JITModule>,
+    function: extern "C" fn(*const f64) -> f64,
+    params: usize,
+}
+
+impl JitFunction {
+    /// Calls the function, with `args` in the order of the `params` it was compiled with.
+    ///
+    /// # Panics
+    ///
+    /// If `args` doesn't have one value per parameter.
+    pub fn call(&self, args: &[f64]) -> f64 {
+        assert_eq!(
+            args.len(),
+            self.params,
+            "expected {} arguments, got {}",
+            self.params,
+            args.len()
+        );
+        (self.function)(args.as_ptr())
+    }
+
+    /// The number of parameters the function takes.
+    pub fn params(&self) -> usize {
+        self.params
+    }
+}
+
+impl Drop for JitFunction {
+    fn drop(&mut self) {
+        if let Some(module) = self.module.take() {
+            // SAFETY: `function` points into this module, and can't be called anymore
+            unsafe { module.free_memory() };
+        }
+    }
+}
+
+/// JIT-compiles an [`Entity`] tree to native code with Cranelift.
+///
+/// The compiled function takes one argument per name in `params`, in that order.
+/// Other identifiers have to be well-known constants like `pi`, which are inlined.
+/// Arithmetic, `sqrt`, `abs`, `floor`, `ceil` and `trunc` become machine instructions,
+/// and the other functions are calls into Rust, so results match [`eval`](cranelift_equation_parser::eval).
+pub fn jit_compile(entity: &Entity<'_, f64>, params: &[&str]) -> Result<JitFunction, JitError> {
+    let mut flags = settings::builder();
+    flags.set("opt_level", "speed")?;
+    let isa = cranelift_native::builder()
+        .map_err(JitError::UnsupportedHost)?
+        .finish(settings::Flags::new(flags))?;
+    let mut module = JITModule::new(JITBuilder::with_isa(isa, default_libcall_names()));
+
+    let config = module.target_config();
+    let pointer = config.pointer_type();
+    let mut ctx = module.make_context();
+    ctx.func.signature.params.push(AbiParam::new(pointer));
+    ctx.func.signature.returns.push(AbiParam::new(types::F64));
+    ctx.func.name = UserFuncName::user(0, 0);
+
+    let mut builder_ctx = FunctionBuilderContext::new();
+    let mut builder = FunctionBuilder::new(&mut ctx.func, &mut builder_ctx);
+    let block = builder.create_block();
+    builder.append_block_params_for_function_params(block);
+    builder.switch_to_block(block);
+    builder.seal_block(block);
+
+    let args = builder.block_params(block)[0];
+    let mut lowering = Lowering {
+        builder,
+        call_conv: module.isa().default_call_conv(),
+        pointer,
+        params,
+        args,
+    };
+    let result = fold(entity, |entity, args| lowering.node(entity, args))?;
+    lowering.builder.ins().return_(&[result]);
+    lowering.builder.finalize(config);
+
+    let id = module.declare_function("equation", Linkage::Export, &ctx.func.signature)?;
+    module.define_function(id, &mut ctx)?;
+    module.clear_context(&mut ctx);
+    module.finalize_definitions()?;
+
+    let code = module.get_finalized_function(id);
+    Ok(JitFunction {
+        module: Some(module),
+        // SAFETY: the function was just defined with this signature,
+        // and the module it lives in is kept alive alongside it
+        function: unsafe {
+            core::mem::transmute::<*const u8, extern "C" fn(*const f64) -> f64>(code)
+        },
+        params: params.len(),
+    })
+}
+
+struct Lowering<'f, 'p> {
+    builder: FunctionBuilder<'f>,
+    call_conv: cranelift_codegen::isa::CallConv,
+    pointer: Type,
+    params: &'p [&'p str],
+    /// Pointer to the argument array
+    args: Value,
+}
+
+impl Lowering<'_, '_> {
+    /// The value of `entity`, given the values of its children in `args`.
+    /// It's called through [`fold`], children first, so no tree is too deep to lower.
+    fn node(&mut self, entity: &Entity<'_, f64>, args: &[Value]) -> Result<Value, JitError> {
+        Ok(match entity {
+            Entity::ValueLit(val) => self.builder.ins().f64const(*val),
+            Entity::Value(name) => self.value(name)?,
+            Entity::Operation(op) => self.operation(op, args),
+            Entity::Function(func) => self.function(func, args),
+        })
+    }
+
+    fn value(&mut self, name: &str) -> Result<Value, JitError> {
+        if let Some(index) = self.params.iter().position(|param| *param == name) {
+            let offset = (index * size_of::<f64>()) as i32;
+            return Ok(self.builder.ins().load(
+                types::F64,
+                MemFlagsData::trusted(),
+                self.args,
+                offset,
+            ));
+        }
+
+        match known_constant::<f64>(name) {
+            Some(val) => Ok(self.builder.ins().f64const(val)),
+            None => Err(JitError::UndefinedVariable(name.to_string())),
+        }
+    }
+
+    fn operation(&mut self, op: &Operation<'_, f64>, args: &[Value]) -> Value {
+        match *op {
+            Operation::Add(..) => self.builder.ins().fadd(args[0], args[1]),
+            Operation::Sub(..) => self.builder.ins().fsub(args[0], args[1]),
+            Operation::Mul(..) => self.builder.ins().fmul(args[0], args[1]),
+            Operation::Div(..) => self.builder.ins().fdiv(args[0], args[1]),
+            Operation::FloorDiv(..) => {
+                let quotient = self.builder.ins().fdiv(args[0], args[1]);
+                self.builder.ins().floor(quotient)
+            }
+            // integer exponents are raised like `eval` does, which can differ from `powf` by an ulp
+            Operation::Pow(_, rhs) => match integer_exponent(rhs) {
+                Some(n) => {
+                    let args = [args[0], self.builder.ins().f64const(f64::from(n))];
+                    self.binary(libcall::powi, &args)
+                }
+                None => self.binary(libcall::pow, args),
+            },
+            Operation::Rem(..) => self.binary(libcall::rem, args),
+            Operation::Neg(..) => self.builder.ins().fneg(args[0]),
+            Operation::Factorial(..) => self.unary(libcall::factorial, args),
+        }
+    }
+
+    fn function(&mut self, func: &Function<'_, f64>, args: &[Value]) -> Value {
+        match *func {
+            Function::Sqrt(..) => self.builder.ins().sqrt(args[0]),
+            Function::Abs(..) => self.builder.ins().fabs(args[0]),
+            Function::Floor(..) => self.builder.ins().floor(args[0]),
+            Function::Ceil(..) => self.builder.ins().ceil(args[0]),
+            Function::Trunc(..) => self.builder.ins().trunc(args[0]),
+
+            Function::Sin(..) => self.unary(libcall::sin, args),
+            Function::Cos(..) => self.unary(libcall::cos, args),
+            Function::Tan(..) => self.unary(libcall::tan, args),
+            Function::Cot(..) => self.unary(libcall::cot, args),
+            Function::Sec(..) => self.unary(libcall::sec, args),
+            Function::Csc(..) => self.unary(libcall::csc, args),
+            Function::Asin(..) => self.unary(libcall::asin, args),
+            Function::Acos(..) => self.unary(libcall::acos, args),
+            Function::Atan(..) => self.unary(libcall::atan, args),
+            Function::Atan2(..) => self.binary(libcall::atan2, args),
+            Function::Sinh(..) => self.unary(libcall::sinh, args),
+            Function::Cosh(..) => self.unary(libcall::cosh, args),
+            Function::Tanh(..) => self.unary(libcall::tanh, args),
+            Function::Coth(..) => self.unary(libcall::coth, args),
+            Function::Sech(..) => self.unary(libcall::sech, args),
+            Function::Csch(..) => self.unary(libcall::csch, args),
+            Function::Asinh(..) => self.unary(libcall::asinh, args),
+            Function::Acosh(..) => self.unary(libcall::acosh, args),
+            Function::Atanh(..) => self.unary(libcall::atanh, args),
+
+            Function::Log(..) => self.binary(libcall::log, args),
+            Function::Log10(..) => self.unary(libcall::log10, args),
+            Function::Ln(..) => self.unary(libcall::ln, args),
+            Function::Log2(..) => self.unary(libcall::log2, args),
+
+            Function::Root(..) => self.binary(libcall::root, args),
+            Function::Hypot(..) => self.binary(libcall::hypot, args),
+            Function::Exp(..) => self.unary(libcall::exp, args),
+            Function::Exp2(..) => self.unary(libcall::exp2, args),
+            Function::Exp10(..) => self.unary(libcall::exp10, args),
+            Function::Mod(..) => self.binary(libcall::rem, args),
+            Function::Gcd(..) => self.binary(libcall::gcd, args),
+            Function::Lcm(..) => self.binary(libcall::lcm, args),
+            Function::Gamma(..) => self.unary(libcall::gamma, args),
+            Function::Lgamma(..) => self.unary(libcall::lgamma, args),
+
+            Function::Round(..) => self.unary(libcall::round, args),
+            Function::Fract(..) => self.unary(libcall::fract, args),
+            Function::Signum(..) => self.unary(libcall::signum, args),
+            Function::Min(..) => self.binary(libcall::min, args),
+            Function::Max(..) => self.binary(libcall::max, args),
+            Function::Clamp(..) => self.ternary(libcall::clamp, args),
+        }
+    }
+
+    fn unary(&mut self, function: Unary, args: &[Value]) -> Value {
+        self.call(function as usize, args)
+    }
+
+    fn binary(&mut self, function: Binary, args: &[Value]) -> Value {
+        self.call(function as usize, args)
+    }
+
+    fn ternary(&mut self, function: Ternary, args: &[Value]) -> Value {
+        self.call(function as usize, args)
+    }
+
+    /// Calls the function at `address`, which takes `args.len()` floats and returns one.
+    fn call(&mut self, address: usize, args: &[Value]) -> Value {
+        let mut signature = Signature::new(self.call_conv);
+        signature
+            .params
+            .extend(args.iter().map(|_| AbiParam::new(types::F64)));
+        signature.returns.push(AbiParam::new(types::F64));
+        let signature = self.builder.import_signature(signature);
+
+        let callee = self.builder.ins().iconst(self.pointer, address as i64);
+        let call = self.builder.ins().call_indirect(signature, callee, args);
+        self.builder.inst_results(call)[0]
+    }
+}
+
+#[derive(Debug, Error)]
+pub enum JitError {
+    #[error("Undefined variable {0}")]
+    UndefinedVariable(String),
+    #[error("Unsupported host: {0}")]
+    UnsupportedHost(&'static str),
+    #[error("Invalid Cranelift setting: {0}")]
+    Setting(#[from] settings::SetError),
+    #[error("Code generation failed: {0}")]
+    Codegen(Box<CodegenError>),
+    #[error("Module error: {0}")]
+    Module(Box<ModuleError>),
+}
+
+// Cranelift's errors are boxed, as they're much larger than the rest
+impl From<CodegenError> for JitError {
+    fn from(error: CodegenError) -> Self {
+        Self::Codegen(Box::new(error))
+    }
+}
+
+impl From<ModuleError> for JitError {
+    fn from(error: ModuleError) -> Self {
+        Self::Module(Box::new(error))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use cranelift_equation_parser::{EntityArena, eval, parse};
+
+    #[test]
+    fn calls_compiled_function() {
+        let arena = EntityArena::new();
+        let entity = parse("x*x+1", &arena).unwrap();
+        let function = jit_compile(&entity, &["x"]).unwrap();
+        assert_eq!(function.params(), 1);
+        assert_eq!(function.call(&[3.0]), 10.0);
+    }
+
+    #[test]
+    fn matches_eval() {
+        let equations = [
+            "x^3 - 2x^2 + x/7",
+            "x^-2 + 1.1^x",
+            "sin(x)^2 + cos(x)^2",
+            "sqrt(|x|) + floor(x) * ceil(x) - trunc(x)",
+            "log(2, |x|+1) + ln(|x|+1) + exp(x/10)",
+            "x % 3 + x // 2 + root(3, x)",
+            "gamma(|x|+1) + atan2(x, 2) + hypot(x, y)",
+            "min(x, y) + max(x, y) + clamp(x, -1, 1) + signum(x)",
+            "pi*x + e^y + x!",
+        ];
+        let points = [
+            (-2.5, 1.0),
+            (-1.0, 0.5),
+            (0.0, 0.0),
+            (0.3, -4.0),
+            (3.0, 2.0),
+            (7.5, 3.0),
+        ];
+
+        let arena = EntityArena::new();
+        for equation in equations {
+            let entity = parse(equation, &arena).unwrap();
+            let function = jit_compile(&entity, &["x", "y"]).unwrap();
+            for (x, y) in points {
+                let expected = eval(&entity, &[("x", x), ("y", y)][..]).unwrap();
+                let actual = function.call(&[x, y]);
+                assert!(
+                    actual.to_bits() == expected.to_bits() || actual.is_nan() && expected.is_nan(),
+                    "{equation} at x = {x}, y = {y}: {actual} != {expected}"
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn compiles_trees_of_any_depth() {
+        let arena = EntityArena::new();
+        let x = arena.alloc(Entity::Value("x"));
+        let mut sum = Entity::Value("x");
+        for _ in 1..20_000 {
+            sum = Entity::Operation(Operation::Add(arena.alloc(sum), x));
+        }
+        let function = jit_compile(&sum, &["x"]).unwrap();
+        assert_eq!(function.call(&[2.0]), 40_000.0);
+    }
+
+    #[test]
+    fn rejects_undefined_variables() {
+        let arena = EntityArena::new();
+        let entity = parse("x + y", &arena).unwrap();
+        assert!(matches!(
+            jit_compile(&entity, &["x"]),
+            Err(JitError::UndefinedVariable(name)) if name == "y"
+        ));
+    }
+}
@@ -1,3 +1,6 @@
-fn main() {
-    println!("Hello, world!");
-}
+//! Cranelift code generation for equations parsed by `cranelift-equation-parser`.
+
+mod jit;
+mod libcall;
+
+pub use jit::{JitError, JitFunction, jit_compile};
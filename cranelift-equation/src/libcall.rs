@@ -0,0 +1,191 @@
+//! Functions the generated code calls for everything Cranelift has no instruction for.
+//! They use the C calling convention, which is what Cranelift's default calling convention is.
+
+use cranelift_equation_parser::{Entity, Function, Operation, eval};
+
+pub type Unary = extern "C" fn(f64) -> f64;
+pub type Binary = extern "C" fn(f64, f64) -> f64;
+pub type Ternary = extern "C" fn(f64, f64, f64) -> f64;
+
+/// Evaluates an entity made of literals with [`eval`], for the functions whose exact
+/// behavior the parser defines, like `root` of negative numbers or non-integer factorials.
+fn eval_literals(entity: &Entity<'_, f64>) -> f64 {
+    let no_vars: &[(&str, f64)] = &[];
+    eval(entity, no_vars).unwrap_or(f64::NAN)
+}
+
+pub extern "C" fn pow(x: f64, y: f64) -> f64 {
+    x.powf(y)
+}
+
+/// `x^n` for an integer `n`, which is passed as a float like every other argument.
+pub extern "C" fn powi(x: f64, n: f64) -> f64 {
+    x.powi(n as i32)
+}
+
+pub extern "C" fn rem(x: f64, y: f64) -> f64 {
+    x % y
+}
+
+pub extern "C" fn factorial(x: f64) -> f64 {
+    let x = Entity::ValueLit(x);
+    eval_literals(&Entity::Operation(Operation::Factorial(&x)))
+}
+
+pub extern "C" fn sin(x: f64) -> f64 {
+    x.sin()
+}
+
+pub extern "C" fn cos(x: f64) -> f64 {
+    x.cos()
+}
+
+pub extern "C" fn tan(x: f64) -> f64 {
+    x.tan()
+}
+
+pub extern "C" fn cot(x: f64) -> f64 {
+    x.tan().recip()
+}
+
+pub extern "C" fn sec(x: f64) -> f64 {
+    x.cos().recip()
+}
+
+pub extern "C" fn csc(x: f64) -> f64 {
+    x.sin().recip()
+}
+
+pub extern "C" fn asin(x: f64) -> f64 {
+    x.asin()
+}
+
+pub extern "C" fn acos(x: f64) -> f64 {
+    x.acos()
+}
+
+pub extern "C" fn atan(x: f64) -> f64 {
+    x.atan()
+}
+
+pub extern "C" fn atan2(y: f64, x: f64) -> f64 {
+    y.atan2(x)
+}
+
+pub extern "C" fn sinh(x: f64) -> f64 {
+    x.sinh()
+}
+
+pub extern "C" fn cosh(x: f64) -> f64 {
+    x.cosh()
+}
+
+pub extern "C" fn tanh(x: f64) -> f64 {
+    x.tanh()
+}
+
+pub extern "C" fn coth(x: f64) -> f64 {
+    x.tanh().recip()
+}
+
+pub extern "C" fn sech(x: f64) -> f64 {
+    x.cosh().recip()
+}
+
+pub extern "C" fn csch(x: f64) -> f64 {
+    x.sinh().recip()
+}
+
+pub extern "C" fn asinh(x: f64) -> f64 {
+    x.asinh()
+}
+
+pub extern "C" fn acosh(x: f64) -> f64 {
+    x.acosh()
+}
+
+pub extern "C" fn atanh(x: f64) -> f64 {
+    x.atanh()
+}
+
+pub extern "C" fn log(base: f64, x: f64) -> f64 {
+    x.log(base)
+}
+
+pub extern "C" fn log10(x: f64) -> f64 {
+    x.log10()
+}
+
+pub extern "C" fn ln(x: f64) -> f64 {
+    x.ln()
+}
+
+pub extern "C" fn log2(x: f64) -> f64 {
+    x.log2()
+}
+
+pub extern "C" fn root(n: f64, x: f64) -> f64 {
+    let (n, x) = (Entity::ValueLit(n), Entity::ValueLit(x));
+    eval_literals(&Entity::Function(Function::Root(&n, &x)))
+}
+
+pub extern "C" fn hypot(x: f64, y: f64) -> f64 {
+    x.hypot(y)
+}
+
+pub extern "C" fn exp(x: f64) -> f64 {
+    x.exp()
+}
+
+pub extern "C" fn exp2(x: f64) -> f64 {
+    x.exp2()
+}
+
+pub extern "C" fn exp10(x: f64) -> f64 {
+    10f64.powf(x)
+}
+
+pub extern "C" fn gcd(x: f64, y: f64) -> f64 {
+    let (x, y) = (Entity::ValueLit(x), Entity::ValueLit(y));
+    eval_literals(&Entity::Function(Function::Gcd(&x, &y)))
+}
+
+pub extern "C" fn lcm(x: f64, y: f64) -> f64 {
+    let (x, y) = (Entity::ValueLit(x), Entity::ValueLit(y));
+    eval_literals(&Entity::Function(Function::Lcm(&x, &y)))
+}
+
+pub extern "C" fn gamma(x: f64) -> f64 {
+    let x = Entity::ValueLit(x);
+    eval_literals(&Entity::Function(Function::Gamma(&x)))
+}
+
+pub extern "C" fn lgamma(x: f64) -> f64 {
+    let x = Entity::ValueLit(x);
+    eval_literals(&Entity::Function(Function::Lgamma(&x)))
+}
+
+pub extern "C" fn round(x: f64) -> f64 {
+    x.round()
+}
+
+pub extern "C" fn fract(x: f64) -> f64 {
+    x.fract()
+}
+
+pub extern "C" fn signum(x: f64) -> f64 {
+    let x = Entity::ValueLit(x);
+    eval_literals(&Entity::Function(Function::Signum(&x)))
+}
+
+pub extern "C" fn min(x: f64, y: f64) -> f64 {
+    x.min(y)
+}
+
+pub extern "C" fn max(x: f64, y: f64) -> f64 {
+    x.max(y)
+}
+
+pub extern "C" fn clamp(x: f64, lo: f64, hi: f64) -> f64 {
+    x.max(lo).min(hi)
+}